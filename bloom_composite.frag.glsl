@@ -0,0 +1,15 @@
+#version 440 core
+
+layout(location = 0) out vec4 o_Color;
+
+layout(location = 0) in vec2 v_TexCoord;
+
+uniform sampler2D u_Texture;
+uniform sampler2D u_Bloom;
+uniform float u_Intensity;
+
+void main() {
+    vec4 color = texture(u_Texture, v_TexCoord);
+    vec3 bloom = texture(u_Bloom, v_TexCoord).rgb;
+    o_Color = vec4(color.rgb + bloom * u_Intensity, color.a);
+}
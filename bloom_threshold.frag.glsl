@@ -0,0 +1,15 @@
+#version 440 core
+
+layout(location = 0) out vec4 o_Color;
+
+layout(location = 0) in vec2 v_TexCoord;
+
+uniform sampler2D u_Texture;
+uniform float u_Threshold;
+
+void main() {
+    vec3 color = texture(u_Texture, v_TexCoord).rgb;
+    float brightness = max(color.r, max(color.g, color.b));
+    float contribution = max(brightness - u_Threshold, 0.0) / max(brightness, 0.0001);
+    o_Color = vec4(color * contribution, 1.0);
+}
@@ -0,0 +1,7 @@
+#version 440 core
+
+layout(location = 0) out vec4 o_Color;
+
+void main() {
+    o_Color = vec4(1.0);
+}
@@ -0,0 +1,12 @@
+#version 440 core
+
+// A unit cube, stretched to the box being tested.
+layout(location = 0) in vec3 a_Position;
+
+uniform mat4 u_ViewProjection;
+uniform vec3 u_Min;
+uniform vec3 u_Max;
+
+void main() {
+    gl_Position = u_ViewProjection * vec4(mix(u_Min, u_Max, a_Position), 1.0);
+}
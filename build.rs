@@ -0,0 +1,204 @@
+#[path = "src/shader_diagnostics.rs"]
+#[allow(dead_code)]
+mod shader_diagnostics;
+
+use shader_diagnostics::{ DiagnosticSeverity, ShaderDiagnostic };
+
+use std::collections::{ HashMap };
+use std::path::{ Path, PathBuf };
+
+fn main() {
+    let manifest_directory = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/shader_diagnostics.rs");
+
+    let mut failed = false;
+    for (path, stage) in find_shaders(&manifest_directory) {
+        println!("cargo:rerun-if-changed={}", path.display());
+
+        let source = std::fs::read_to_string(&path)
+            .unwrap_or_else(|error| panic!("Failed to read {}: {}", path.display(), error));
+        let name = path.file_name().unwrap().to_string_lossy();
+
+        let diagnostics = validate_shader(&source, stage);
+        for diagnostic in &diagnostics {
+            eprint!("{}", shader_diagnostics::render_diagnostic(diagnostic, &name, &source));
+        }
+        failed |= shader_diagnostics::has_errors(&diagnostics);
+    }
+
+    if failed {
+        panic!("Shader validation failed!");
+    }
+}
+
+fn find_shaders(directory: &Path) -> Vec<(PathBuf, naga::ShaderStage)> {
+    let mut shaders = Vec::new();
+    for entry in std::fs::read_dir(directory).unwrap() {
+        let path = entry.unwrap().path();
+        let file_name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(file_name) => file_name.to_string(),
+            None => continue,
+        };
+
+        let stage = if file_name.ends_with(".vert.glsl") {
+            naga::ShaderStage::Vertex
+        } else if file_name.ends_with(".frag.glsl") {
+            naga::ShaderStage::Fragment
+        } else if file_name.ends_with(".comp.glsl") {
+            naga::ShaderStage::Compute
+        } else {
+            continue;
+        };
+        shaders.push((path, stage));
+    }
+    shaders.sort();
+    return shaders;
+}
+
+fn validate_shader(source: &str, stage: naga::ShaderStage) -> Vec<ShaderDiagnostic> {
+    let lowered_source = lower_to_vulkan_glsl(source);
+
+    let mut frontend = naga::front::glsl::Frontend::default();
+    let module = match frontend.parse(&naga::front::glsl::Options::from(stage), &lowered_source) {
+        Ok(module) => module,
+        Err(errors) => {
+            return errors.errors.iter()
+                .map(|error| create_diagnostic(error.to_string(), error.location(&lowered_source)))
+                .collect();
+        }
+    };
+
+    let mut validator = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all());
+    return match validator.validate(&module) {
+        Ok(_) => Vec::new(),
+        Err(error) => {
+            let mut message = error.to_string();
+            let mut cause = std::error::Error::source(&error);
+            while let Some(inner) = cause {
+                message.push_str(&format!(": {}", inner));
+                cause = inner.source();
+            }
+            vec![create_diagnostic(message, error.location(&lowered_source))]
+        }
+    };
+}
+
+fn create_diagnostic(message: String, location: Option<naga::SourceLocation>) -> ShaderDiagnostic {
+    ShaderDiagnostic {
+        severity: DiagnosticSeverity::Error,
+        source_index: 0,
+        line: location.map(|location| location.line_number),
+        column: location.map(|location| location.line_position),
+        message,
+    }
+}
+
+// naga only understands Vulkan-flavoured GLSL, so combined samplers are split into a texture and a sampler,
+// loose uniforms are gathered into a block, `gl_VertexID`/`gl_InstanceID` take their Vulkan names and older `#version`s are bumped to 440.
+// Every rewrite happens in place so line numbers keep matching the original file.
+fn lower_to_vulkan_glsl(source: &str) -> String {
+    let mut lines: Vec<String> = source.lines().map(str::to_string).collect();
+    let mut binding = 0;
+    let mut replacements = HashMap::new();
+    replacements.insert("gl_VertexID".to_string(), "gl_VertexIndex".to_string());
+    replacements.insert("gl_InstanceID".to_string(), "gl_InstanceIndex".to_string());
+    let mut loose_uniforms = Vec::new();
+    let mut first_loose_uniform_line = None;
+
+    for (index, line) in lines.iter_mut().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("#version") {
+            if !trimmed.contains("440") && !trimmed.contains("450") && !trimmed.contains("460") {
+                *line = "#version 440 core".to_string();
+            }
+            continue;
+        }
+        if trimmed.starts_with("precision ") {
+            line.clear();
+            continue;
+        }
+
+        let declaration = match parse_uniform_declaration(trimmed) {
+            Some(declaration) => declaration,
+            None => continue,
+        };
+        let (type_name, name) = declaration;
+
+        if let Some(sampled_type) = type_name.strip_prefix("sampler") {
+            let (texture_type, sampler_type) = match sampled_type.strip_suffix("Shadow") {
+                Some(texture_type) => (format!("texture{}", texture_type), "samplerShadow"),
+                None => (format!("texture{}", sampled_type), "sampler"),
+            };
+            *line = format!(
+                "layout(set = 0, binding = {}) uniform {} {}_texture; layout(set = 0, binding = {}) uniform {} {}_sampler;",
+                binding, texture_type, name, binding + 1, sampler_type, name,
+            );
+            replacements.insert(name.clone(), format!("{}({}_texture, {}_sampler)", type_name, name, name));
+            binding += 2;
+        } else {
+            loose_uniforms.push(format!("{} {};", type_name, name));
+            first_loose_uniform_line.get_or_insert(index);
+            line.clear();
+        }
+    }
+
+    if let Some(index) = first_loose_uniform_line {
+        lines[index] = format!("layout(set = 0, binding = {}) uniform _LooseUniforms {{ {} }};", binding, loose_uniforms.join(" "));
+    }
+
+    let mut output = String::with_capacity(source.len() * 2);
+    for line in &lines {
+        if line.contains("uniform") {
+            output.push_str(line);
+        } else {
+            output.push_str(&replace_identifiers(line, &replacements));
+        }
+        output.push('\n');
+    }
+    return output;
+}
+
+fn parse_uniform_declaration(line: &str) -> Option<(String, String)> {
+    let line = match line.strip_prefix("layout") {
+        Some(rest) => rest.trim_start().strip_prefix('(')?.split_once(')')?.1.trim_start(),
+        None => line,
+    };
+    let rest = line.strip_prefix("uniform ")?;
+    if rest.contains('{') {
+        return None;
+    }
+
+    let declaration = rest.split(';').next()?.trim();
+    let mut parts = declaration.split_whitespace();
+    let type_name = parts.next()?;
+    let name = parts.next()?;
+    if parts.next().is_some() {
+        return None;
+    }
+    return Some((type_name.to_string(), name.to_string()));
+}
+
+fn replace_identifiers(line: &str, replacements: &HashMap<String, String>) -> String {
+    if replacements.is_empty() {
+        return line.to_string();
+    }
+
+    let mut output = String::with_capacity(line.len());
+    let mut identifier = String::new();
+    for c in line.chars().chain(std::iter::once('\n')) {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            identifier.push(c);
+            continue;
+        }
+        match replacements.get(&identifier) {
+            Some(replacement) => output.push_str(replacement),
+            None => output.push_str(&identifier),
+        }
+        identifier.clear();
+        if c != '\n' {
+            output.push(c);
+        }
+    }
+    return output;
+}
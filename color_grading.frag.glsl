@@ -0,0 +1,20 @@
+#version 440 core
+
+layout(location = 0) out vec4 o_Color;
+
+layout(location = 0) in vec2 v_TexCoord;
+
+uniform sampler2D u_Texture;
+uniform sampler3D u_Lut;
+uniform float u_Intensity;
+
+void main() {
+    vec4 color = texture(u_Texture, v_TexCoord);
+
+    // Remap so 0 and 1 land on the centres of the first and last LUT texels instead of their outer edges.
+    float lut_size = float(textureSize(u_Lut, 0).x);
+    vec3 lut_coordinate = clamp(color.rgb, 0.0, 1.0) * ((lut_size - 1.0) / lut_size) + 0.5 / lut_size;
+    vec3 graded = texture(u_Lut, lut_coordinate).rgb;
+
+    o_Color = vec4(mix(color.rgb, graded, u_Intensity), color.a);
+}
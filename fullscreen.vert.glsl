@@ -0,0 +1,10 @@
+#version 440 core
+
+layout(location = 0) out vec2 v_TexCoord;
+
+// A single triangle that covers the whole screen, generated from the vertex index so no vertex buffer is needed.
+void main() {
+    vec2 position = vec2(float((gl_VertexID << 1) & 2), float(gl_VertexID & 2));
+    v_TexCoord = position;
+    gl_Position = vec4(position * 2.0 - 1.0, 0.0, 1.0);
+}
@@ -0,0 +1,54 @@
+#version 440 core
+
+layout(location = 0) out vec4 o_Color;
+
+layout(location = 0) in vec2 v_TexCoord;
+
+uniform sampler2D u_Texture;
+
+const float REDUCE_MIN = 1.0 / 128.0;
+const float REDUCE_MULTIPLIER = 1.0 / 8.0;
+const float SPAN_MAX = 8.0;
+const vec3 LUMA = vec3(0.299, 0.587, 0.114);
+
+void main() {
+    vec2 texel = 1.0 / vec2(textureSize(u_Texture, 0));
+
+    vec3 north_west = texture(u_Texture, v_TexCoord + vec2(-1.0, -1.0) * texel).rgb;
+    vec3 north_east = texture(u_Texture, v_TexCoord + vec2(1.0, -1.0) * texel).rgb;
+    vec3 south_west = texture(u_Texture, v_TexCoord + vec2(-1.0, 1.0) * texel).rgb;
+    vec3 south_east = texture(u_Texture, v_TexCoord + vec2(1.0, 1.0) * texel).rgb;
+    vec4 middle = texture(u_Texture, v_TexCoord);
+
+    float luma_north_west = dot(north_west, LUMA);
+    float luma_north_east = dot(north_east, LUMA);
+    float luma_south_west = dot(south_west, LUMA);
+    float luma_south_east = dot(south_east, LUMA);
+    float luma_middle = dot(middle.rgb, LUMA);
+
+    float luma_min = min(luma_middle, min(min(luma_north_west, luma_north_east), min(luma_south_west, luma_south_east)));
+    float luma_max = max(luma_middle, max(max(luma_north_west, luma_north_east), max(luma_south_west, luma_south_east)));
+
+    // The blur runs along the edge, which is perpendicular to the luma gradient.
+    vec2 direction = vec2(
+        (luma_south_west + luma_south_east) - (luma_north_west + luma_north_east),
+        (luma_north_west + luma_south_west) - (luma_north_east + luma_south_east)
+    );
+    float direction_reduce = max((luma_north_west + luma_north_east + luma_south_west + luma_south_east) * 0.25 * REDUCE_MULTIPLIER, REDUCE_MIN);
+    float inverse_direction_min = 1.0 / (min(abs(direction.x), abs(direction.y)) + direction_reduce);
+    direction = clamp(direction * inverse_direction_min, vec2(-SPAN_MAX), vec2(SPAN_MAX)) * texel;
+
+    vec3 near_average = 0.5 * (
+        texture(u_Texture, v_TexCoord + direction * (1.0 / 3.0 - 0.5)).rgb +
+        texture(u_Texture, v_TexCoord + direction * (2.0 / 3.0 - 0.5)).rgb
+    );
+    vec3 far_average = near_average * 0.5 + 0.25 * (
+        texture(u_Texture, v_TexCoord + direction * -0.5).rgb +
+        texture(u_Texture, v_TexCoord + direction * 0.5).rgb
+    );
+
+    // Sampling too far along the direction can cross into an unrelated edge, so fall back to the closer samples.
+    float luma_far = dot(far_average, LUMA);
+    vec3 color = (luma_far < luma_min || luma_far > luma_max) ? near_average : far_average;
+    o_Color = vec4(color, middle.a);
+}
@@ -0,0 +1,13 @@
+#version 440 core
+
+layout(location = 0) out vec4 o_Color;
+
+layout(location = 0) in vec2 v_TexCoord;
+
+uniform sampler2D u_Texture;
+uniform float u_Gamma;
+
+void main() {
+    vec4 color = texture(u_Texture, v_TexCoord);
+    o_Color = vec4(pow(max(color.rgb, vec3(0.0)), vec3(1.0 / u_Gamma)), color.a);
+}
@@ -0,0 +1,22 @@
+#version 440 core
+
+layout(location = 0) out vec4 o_Color;
+
+layout(location = 0) in vec2 v_TexCoord;
+
+uniform sampler2D u_Texture;
+// (1, 0) for the horizontal pass and (0, 1) for the vertical one.
+uniform vec2 u_Direction;
+
+const float WEIGHTS[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+
+void main() {
+    vec2 step_size = u_Direction / vec2(textureSize(u_Texture, 0));
+
+    vec3 color = texture(u_Texture, v_TexCoord).rgb * WEIGHTS[0];
+    for (int i = 1; i < 5; i++) {
+        color += texture(u_Texture, v_TexCoord + step_size * float(i)).rgb * WEIGHTS[i];
+        color += texture(u_Texture, v_TexCoord - step_size * float(i)).rgb * WEIGHTS[i];
+    }
+    o_Color = vec4(color, 1.0);
+}
@@ -0,0 +1,256 @@
+use crate::gl_context::{ ContextHandle, GLObject };
+use crate::gl_object_tracker;
+use crate::gl_state;
+use crate::opengl_framebuffer::{ OpenGLFramebuffer };
+use crate::opengl_texture;
+
+use image::{ DynamicImage, ImageFormat, RgbaImage };
+
+use gl::types::*;
+
+use std::io::{ Write };
+use std::path::{ Path, PathBuf };
+
+// How long finishing a recording waits for each outstanding readback before giving up on the driver.
+const FENCE_TIMEOUT_NANOSECONDS: GLuint64 = 1_000_000_000;
+
+#[derive(Debug)]
+pub enum CaptureError {
+    Io(std::io::Error),
+    Image(image::ImageError),
+    // The GPU didn't finish the readback in time, or its buffer couldn't be mapped.
+    Readback(&'static str),
+}
+
+impl std::fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CaptureError::Io(error) => write!(f, "Failed to write the captured frame: {}", error),
+            CaptureError::Image(error) => write!(f, "Failed to encode the captured frame: {}", error),
+            CaptureError::Readback(reason) => write!(f, "Failed to read back the captured frame: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {}
+
+impl From<std::io::Error> for CaptureError {
+    fn from(error: std::io::Error) -> CaptureError {
+        CaptureError::Io(error)
+    }
+}
+
+impl From<image::ImageError> for CaptureError {
+    fn from(error: image::ImageError) -> CaptureError {
+        CaptureError::Image(error)
+    }
+}
+
+// Reads the back buffer of the window. The result has its first row at the top, like any other image.
+pub fn capture_default_framebuffer(width: u32, height: u32) -> RgbaImage {
+    read_pixels_flipped(0, gl::BACK, width, height)
+}
+
+pub fn capture_framebuffer(framebuffer: &OpenGLFramebuffer) -> RgbaImage {
+    assert_eq!(framebuffer.get_samples(), 0, "Multisampled framebuffers have to be resolved before capturing!");
+    read_pixels_flipped(framebuffer.get_id(), gl::COLOR_ATTACHMENT0, framebuffer.get_width(), framebuffer.get_height())
+}
+
+// The encoder is picked from the extension. JPEG has no alpha channel, so it is dropped rather than failing the save.
+pub fn save_capture<P: AsRef<Path>>(image: &RgbaImage, path: P) -> Result<(), CaptureError> {
+    let path = path.as_ref();
+    if ImageFormat::from_path(path)? == ImageFormat::Jpeg {
+        DynamicImage::ImageRgba8(image.clone()).to_rgb8().save(path)?;
+    } else {
+        image.save(path)?;
+    }
+    return Ok(());
+}
+
+fn read_pixels_flipped(framebuffer: GLuint, read_buffer: GLenum, width: u32, height: u32) -> RgbaImage {
+    let mut pixels = vec![0u8; width as usize * height as usize * 4];
+    unsafe {
+        let previous_read_framebuffer = bind_read_framebuffer(framebuffer, read_buffer);
+        let previous_alignment = opengl_texture::set_pixel_store(gl::PACK_ALIGNMENT, opengl_texture::get_row_alignment(width as usize * 4));
+
+        gl::ReadPixels(0, 0, width as GLsizei, height as GLsizei, gl::RGBA, gl::UNSIGNED_BYTE, pixels.as_mut_ptr() as *mut GLvoid);
+
+        opengl_texture::set_pixel_store(gl::PACK_ALIGNMENT, previous_alignment);
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, previous_read_framebuffer);
+    }
+
+    let mut image = RgbaImage::from_raw(width, height, pixels).unwrap();
+    image::imageops::flip_vertical_in_place(&mut image);
+    return image;
+}
+
+unsafe fn bind_read_framebuffer(framebuffer: GLuint, read_buffer: GLenum) -> GLuint {
+    let mut previous_read_framebuffer = 0;
+    gl::GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut previous_read_framebuffer);
+    gl::BindFramebuffer(gl::READ_FRAMEBUFFER, framebuffer);
+    gl::ReadBuffer(read_buffer);
+    return previous_read_framebuffer as GLuint;
+}
+
+pub enum RecordingOutput {
+    // Writes `<prefix><frame number>.<extension>` files into the directory, e.g. `frame_00042.png`.
+    NumberedFrames { directory: PathBuf, prefix: String, extension: String },
+    // Writes every frame as tightly packed, top-down RGBA8 rows, e.g. into the stdin of an encoder process.
+    RawFrames(Box<dyn Write>),
+}
+
+struct PendingReadback {
+    buffer: GLuint,
+    fence: Option<GLsync>,
+}
+
+// Records frames without stalling: each frame is read into one of a ring of pixel pack buffers,
+// and is only mapped once the ring has wrapped around, by which point the GPU has long finished the copy.
+pub struct FrameRecorder {
+    width: u32,
+    height: u32,
+    output: RecordingOutput,
+    readbacks: Vec<PendingReadback>,
+    next_readback: usize,
+    recorded_frames: u64,
+    written_frames: u64,
+    context: ContextHandle,
+}
+
+impl FrameRecorder {
+    pub fn new(width: u32, height: u32, buffer_count: usize, output: RecordingOutput) -> FrameRecorder {
+        assert!(buffer_count > 0, "The recorder needs at least one pixel buffer!");
+
+        let frame_size = width as usize * height as usize * 4;
+        let mut readbacks = Vec::with_capacity(buffer_count);
+        unsafe {
+            for _ in 0..buffer_count {
+                let mut buffer = 0;
+                gl::GenBuffers(1, &mut buffer);
+                gl_object_tracker::register(GLObject::Buffer(buffer).get_current_key());
+                gl_state::bind_buffer(gl::PIXEL_PACK_BUFFER, buffer);
+                gl::BufferData(gl::PIXEL_PACK_BUFFER, frame_size as GLsizeiptr, std::ptr::null(), gl::STREAM_READ);
+                readbacks.push(PendingReadback { buffer, fence: None });
+            }
+            gl_state::bind_buffer(gl::PIXEL_PACK_BUFFER, 0);
+        }
+
+        return FrameRecorder {
+            width,
+            height,
+            output,
+            readbacks,
+            next_readback: 0,
+            recorded_frames: 0,
+            written_frames: 0,
+            context: ContextHandle::current(),
+        };
+    }
+
+    pub fn record_default_framebuffer(&mut self) -> Result<(), CaptureError> {
+        self.record(0, gl::BACK)
+    }
+
+    pub fn record_framebuffer(&mut self, framebuffer: &OpenGLFramebuffer) -> Result<(), CaptureError> {
+        assert_eq!(framebuffer.get_samples(), 0, "Multisampled framebuffers have to be resolved before capturing!");
+        assert!(framebuffer.get_width() == self.width && framebuffer.get_height() == self.height, "The framebuffer doesn't match the recording size!");
+        self.record(framebuffer.get_id(), gl::COLOR_ATTACHMENT0)
+    }
+
+    fn record(&mut self, framebuffer: GLuint, read_buffer: GLenum) -> Result<(), CaptureError> {
+        // The slot about to be reused holds the oldest frame, so it has to be written out first.
+        if self.readbacks[self.next_readback].fence.is_some() {
+            self.write_readback(self.next_readback)?;
+        }
+
+        let readback = &mut self.readbacks[self.next_readback];
+        unsafe {
+            let previous_read_framebuffer = bind_read_framebuffer(framebuffer, read_buffer);
+            let previous_alignment = opengl_texture::set_pixel_store(gl::PACK_ALIGNMENT, opengl_texture::get_row_alignment(self.width as usize * 4));
+
+            gl_state::bind_buffer(gl::PIXEL_PACK_BUFFER, readback.buffer);
+            gl::ReadPixels(0, 0, self.width as GLsizei, self.height as GLsizei, gl::RGBA, gl::UNSIGNED_BYTE, std::ptr::null_mut());
+            gl_state::bind_buffer(gl::PIXEL_PACK_BUFFER, 0);
+            readback.fence = Some(gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0));
+
+            opengl_texture::set_pixel_store(gl::PACK_ALIGNMENT, previous_alignment);
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, previous_read_framebuffer);
+        }
+
+        self.next_readback = (self.next_readback + 1) % self.readbacks.len();
+        self.recorded_frames += 1;
+        return Ok(());
+    }
+
+    fn write_readback(&mut self, index: usize) -> Result<(), CaptureError> {
+        let frame_size = self.width as usize * self.height as usize * 4;
+        let readback = &mut self.readbacks[index];
+        let fence = readback.fence.take().unwrap();
+
+        let mut pixels = vec![0u8; frame_size];
+        unsafe {
+            let status = gl::ClientWaitSync(fence, gl::SYNC_FLUSH_COMMANDS_BIT, FENCE_TIMEOUT_NANOSECONDS);
+            gl::DeleteSync(fence);
+            match status {
+                gl::ALREADY_SIGNALED | gl::CONDITION_SATISFIED => {}
+                gl::TIMEOUT_EXPIRED => return Err(CaptureError::Readback("the GPU didn't finish the frame in time")),
+                _ => return Err(CaptureError::Readback("waiting for the frame failed")),
+            }
+
+            gl_state::bind_buffer(gl::PIXEL_PACK_BUFFER, readback.buffer);
+            let mapped = gl::MapBufferRange(gl::PIXEL_PACK_BUFFER, 0, frame_size as GLsizeiptr, gl::MAP_READ_BIT) as *const u8;
+            if mapped.is_null() {
+                gl_state::bind_buffer(gl::PIXEL_PACK_BUFFER, 0);
+                return Err(CaptureError::Readback("the readback buffer couldn't be mapped"));
+            }
+            std::ptr::copy_nonoverlapping(mapped, pixels.as_mut_ptr(), frame_size);
+            gl::UnmapBuffer(gl::PIXEL_PACK_BUFFER);
+            gl_state::bind_buffer(gl::PIXEL_PACK_BUFFER, 0);
+        }
+
+        let mut image = RgbaImage::from_raw(self.width, self.height, pixels).unwrap();
+        image::imageops::flip_vertical_in_place(&mut image);
+
+        match &mut self.output {
+            RecordingOutput::NumberedFrames { directory, prefix, extension } => {
+                let path = directory.join(format!("{}{:05}.{}", prefix, self.written_frames, extension));
+                save_capture(&image, path)?;
+            }
+            RecordingOutput::RawFrames(writer) => {
+                writer.write_all(image.as_raw())?;
+            }
+        }
+
+        self.written_frames += 1;
+        return Ok(());
+    }
+
+    // Writes out every frame still in flight, oldest first, and flushes the output.
+    pub fn finish(mut self) -> Result<u64, CaptureError> {
+        for offset in 0..self.readbacks.len() {
+            let index = (self.next_readback + offset) % self.readbacks.len();
+            if self.readbacks[index].fence.is_some() {
+                self.write_readback(index)?;
+            }
+        }
+        if let RecordingOutput::RawFrames(writer) = &mut self.output {
+            writer.flush()?;
+        }
+        return Ok(self.written_frames);
+    }
+
+    pub fn get_recorded_frame_count(&self) -> u64 {
+        self.recorded_frames
+    }
+}
+
+impl Drop for FrameRecorder {
+    fn drop(&mut self) {
+        for readback in &self.readbacks {
+            if let Some(fence) = readback.fence {
+                self.context.delete(GLObject::Sync(fence));
+            }
+            self.context.delete(GLObject::Buffer(readback.buffer));
+        }
+    }
+}
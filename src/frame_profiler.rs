@@ -0,0 +1,408 @@
+use crate::opengl_query::{ OpenGLQuery, QueryType };
+
+use gl::types::*;
+use serde::{ Serialize };
+
+use std::cell::{ RefCell };
+use std::collections::{ VecDeque };
+use std::io::{ Write };
+use std::path::{ Path };
+use std::time::{ Duration, Instant };
+
+// A frame whose GPU results still haven't arrived once this many newer frames are waiting is read back with a stall.
+const MAX_PENDING_FRAMES: usize = 4;
+
+#[derive(Clone, Debug)]
+pub struct ProfiledScope {
+    pub name: String,
+    pub depth: u32,
+    pub parent: Option<usize>,
+    // Both timelines start when the profiler is created.
+    pub cpu_start: Duration,
+    pub cpu_duration: Duration,
+    // `None` when the context has no timer queries.
+    pub gpu_start: Option<Duration>,
+    pub gpu_duration: Option<Duration>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ProfiledFrame {
+    pub index: u64,
+    // The first scope covers the whole frame, the others follow in the order they were opened.
+    pub scopes: Vec<ProfiledScope>,
+}
+
+impl ProfiledFrame {
+    pub fn get_cpu_duration(&self) -> Duration {
+        self.scopes[0].cpu_duration
+    }
+
+    pub fn get_gpu_duration(&self) -> Option<Duration> {
+        self.scopes[0].gpu_duration
+    }
+
+    // One indented line per scope, e.g. `  Shadows  cpu 0.21 ms  gpu 1.40 ms`.
+    pub fn format_breakdown(&self) -> String {
+        let mut breakdown = String::new();
+        for scope in &self.scopes {
+            breakdown.push_str(&format!("{:indent$}{}  cpu {:.2} ms", "", scope.name, get_milliseconds(scope.cpu_duration), indent = scope.depth as usize * 2));
+            if let Some(gpu_duration) = scope.gpu_duration {
+                breakdown.push_str(&format!("  gpu {:.2} ms", get_milliseconds(gpu_duration)));
+            }
+            breakdown.push('\n');
+        }
+        return breakdown;
+    }
+}
+
+fn get_milliseconds(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+struct RecordingScope {
+    name: String,
+    depth: u32,
+    parent: Option<usize>,
+    cpu_start: Duration,
+    cpu_end: Duration,
+    // Start and end timestamps.
+    gpu_queries: Option<(OpenGLQuery, OpenGLQuery)>,
+}
+
+struct RecordingFrame {
+    index: u64,
+    scopes: Vec<RecordingScope>,
+    open_scopes: Vec<usize>,
+}
+
+impl RecordingFrame {
+    fn is_gpu_result_available(&self) -> bool {
+        self.scopes.iter()
+            .filter_map(|scope| scope.gpu_queries.as_ref())
+            .all(|(start, end)| start.is_result_available() && end.is_result_available())
+    }
+}
+
+struct ProfilerState {
+    epoch: Instant,
+    // The GPU clock at `epoch`, in nanoseconds.
+    gpu_epoch: Option<u64>,
+    query_pool: Vec<OpenGLQuery>,
+    recording: Option<RecordingFrame>,
+    pending_frames: VecDeque<RecordingFrame>,
+    completed_frames: VecDeque<ProfiledFrame>,
+    max_history: usize,
+    next_frame_index: u64,
+}
+
+impl ProfilerState {
+    fn acquire_query(&mut self) -> OpenGLQuery {
+        self.query_pool.pop().unwrap_or_else(|| OpenGLQuery::new(QueryType::Timestamp))
+    }
+
+    fn resolve_frame(&mut self, frame: RecordingFrame) {
+        let gpu_epoch = self.gpu_epoch.unwrap_or(0);
+        let mut scopes = Vec::with_capacity(frame.scopes.len());
+        for scope in frame.scopes {
+            let (gpu_start, gpu_duration) = match scope.gpu_queries {
+                Some((mut start_query, mut end_query)) => {
+                    let (start, end) = (start_query.get_result(), end_query.get_result());
+                    self.query_pool.push(start_query);
+                    self.query_pool.push(end_query);
+                    (Some(Duration::from_nanos(start.saturating_sub(gpu_epoch))), Some(Duration::from_nanos(end.saturating_sub(start))))
+                }
+                None => (None, None),
+            };
+
+            scopes.push(ProfiledScope {
+                name: scope.name,
+                depth: scope.depth,
+                parent: scope.parent,
+                cpu_start: scope.cpu_start,
+                cpu_duration: scope.cpu_end.saturating_sub(scope.cpu_start),
+                gpu_start,
+                gpu_duration,
+            });
+        }
+
+        self.completed_frames.push_back(ProfiledFrame { index: frame.index, scopes });
+        while self.completed_frames.len() > self.max_history {
+            self.completed_frames.pop_front();
+        }
+    }
+}
+
+// Records nested CPU and GPU timings between `begin_frame` and `end_frame`. GPU times come from timestamp queries,
+// which are read back a few frames later, so the newest completed frame lags behind the one being recorded.
+pub struct FrameProfiler {
+    state: RefCell<ProfilerState>,
+}
+
+// Closes its scope when dropped.
+#[must_use = "The scope ends as soon as it is dropped"]
+pub struct ProfileScope<'a> {
+    profiler: &'a FrameProfiler,
+    index: Option<usize>,
+}
+
+impl<'a> Drop for ProfileScope<'a> {
+    fn drop(&mut self) {
+        if let Some(index) = self.index {
+            self.profiler.end_scope(index);
+        }
+    }
+}
+
+impl FrameProfiler {
+    // Keeps the last `max_history` completed frames.
+    pub fn new(max_history: usize) -> FrameProfiler {
+        let gpu_epoch = if QueryType::Timestamp.is_supported() {
+            let mut timestamp: GLint64 = 0;
+            unsafe {
+                gl::GetInteger64v(gl::TIMESTAMP, &mut timestamp);
+            }
+            Some(timestamp as u64)
+        } else {
+            None
+        };
+
+        return FrameProfiler {
+            state: RefCell::new(ProfilerState {
+                epoch: Instant::now(),
+                gpu_epoch,
+                query_pool: Vec::new(),
+                recording: None,
+                pending_frames: VecDeque::new(),
+                completed_frames: VecDeque::new(),
+                max_history: std::cmp::max(max_history, 1),
+                next_frame_index: 0,
+            }),
+        };
+    }
+
+    pub fn begin_frame(&self) {
+        {
+            let mut state = self.state.borrow_mut();
+            assert!(state.recording.is_none(), "The previous frame hasn't ended!");
+            let index = state.next_frame_index;
+            state.next_frame_index += 1;
+            state.recording = Some(RecordingFrame { index, scopes: Vec::new(), open_scopes: Vec::new() });
+        }
+        let index = self.begin_scope("Frame");
+        assert_eq!(index, Some(0));
+    }
+
+    pub fn end_frame(&self) {
+        self.end_scope(0);
+
+        let mut state = self.state.borrow_mut();
+        let frame = state.recording.take().expect("No frame is being recorded!");
+        assert!(frame.open_scopes.is_empty(), "Every scope has to end before the frame does!");
+        state.pending_frames.push_back(frame);
+
+        while let Some(frame) = state.pending_frames.pop_front() {
+            if !frame.is_gpu_result_available() && state.pending_frames.len() < MAX_PENDING_FRAMES {
+                state.pending_frames.push_front(frame);
+                break;
+            }
+            state.resolve_frame(frame);
+        }
+    }
+
+    // Scopes opened outside of a frame are ignored.
+    pub fn scope(&self, name: &str) -> ProfileScope<'_> {
+        ProfileScope {
+            profiler: self,
+            index: self.begin_scope(name),
+        }
+    }
+
+    fn begin_scope(&self, name: &str) -> Option<usize> {
+        let mut state = self.state.borrow_mut();
+        state.recording.as_ref()?;
+
+        let gpu_queries = if state.gpu_epoch.is_some() {
+            let mut start_query = state.acquire_query();
+            let end_query = state.acquire_query();
+            start_query.record_timestamp();
+            Some((start_query, end_query))
+        } else {
+            None
+        };
+
+        let cpu_start = state.epoch.elapsed();
+        let frame = state.recording.as_mut().unwrap();
+        let index = frame.scopes.len();
+        frame.scopes.push(RecordingScope {
+            name: name.to_string(),
+            depth: frame.open_scopes.len() as u32,
+            parent: frame.open_scopes.last().copied(),
+            cpu_start,
+            cpu_end: cpu_start,
+            gpu_queries,
+        });
+        frame.open_scopes.push(index);
+        return Some(index);
+    }
+
+    fn end_scope(&self, index: usize) {
+        let mut state = self.state.borrow_mut();
+        let cpu_end = state.epoch.elapsed();
+        let frame = state.recording.as_mut().expect("No frame is being recorded!");
+        assert_eq!(frame.open_scopes.pop(), Some(index), "Scopes have to end in the reverse order they began!");
+
+        let scope = &mut frame.scopes[index];
+        scope.cpu_end = cpu_end;
+        if let Some((_, end_query)) = &mut scope.gpu_queries {
+            end_query.record_timestamp();
+        }
+    }
+
+    pub fn get_latest_frame(&self) -> Option<ProfiledFrame> {
+        self.state.borrow().completed_frames.back().cloned()
+    }
+
+    pub fn get_frames(&self) -> Vec<ProfiledFrame> {
+        self.state.borrow().completed_frames.iter().cloned().collect()
+    }
+
+    // The trace can be opened in chrome://tracing or Perfetto, with the CPU and GPU timelines as separate threads.
+    pub fn write_chrome_trace<W: Write>(&self, writer: W) -> serde_json::Result<()> {
+        let state = self.state.borrow();
+        let frames: Vec<&ProfiledFrame> = state.completed_frames.iter().collect();
+        return write_chrome_trace(&frames, writer);
+    }
+
+    pub fn save_chrome_trace<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        self.write_chrome_trace(file)?;
+        return Ok(());
+    }
+}
+
+const CPU_THREAD_ID: u32 = 1;
+const GPU_THREAD_ID: u32 = 2;
+
+#[derive(Serialize)]
+struct ChromeTrace<'a> {
+    #[serde(rename = "traceEvents")]
+    trace_events: Vec<TraceEvent<'a>>,
+    #[serde(rename = "displayTimeUnit")]
+    display_time_unit: &'static str,
+}
+
+#[derive(Serialize)]
+struct TraceEvent<'a> {
+    name: &'a str,
+    ph: &'static str,
+    pid: u32,
+    tid: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ts: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dur: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    args: Option<TraceArguments<'a>>,
+}
+
+#[derive(Serialize)]
+struct TraceArguments<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frame: Option<u64>,
+}
+
+fn get_microseconds(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1_000_000.0
+}
+
+// Complete ("X") events nest by time, so the scope hierarchy needs no extra information.
+fn write_chrome_trace<W: Write>(frames: &[&ProfiledFrame], writer: W) -> serde_json::Result<()> {
+    let thread_name = |thread_id: u32, name: &'static str| TraceEvent {
+        name: "thread_name",
+        ph: "M",
+        pid: 1,
+        tid: thread_id,
+        ts: None,
+        dur: None,
+        args: Some(TraceArguments { name: Some(name), frame: None }),
+    };
+    let mut trace_events = vec![thread_name(CPU_THREAD_ID, "CPU"), thread_name(GPU_THREAD_ID, "GPU")];
+
+    for frame in frames {
+        for scope in &frame.scopes {
+            let event = |thread_id: u32, start: Duration, duration: Duration| TraceEvent {
+                name: &scope.name,
+                ph: "X",
+                pid: 1,
+                tid: thread_id,
+                ts: Some(get_microseconds(start)),
+                dur: Some(get_microseconds(duration)),
+                args: Some(TraceArguments { name: None, frame: Some(frame.index) }),
+            };
+
+            trace_events.push(event(CPU_THREAD_ID, scope.cpu_start, scope.cpu_duration));
+            if let (Some(gpu_start), Some(gpu_duration)) = (scope.gpu_start, scope.gpu_duration) {
+                trace_events.push(event(GPU_THREAD_ID, gpu_start, gpu_duration));
+            }
+        }
+    }
+
+    return serde_json::to_writer(writer, &ChromeTrace { trace_events, display_time_unit: "ms" });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_scope(name: &str, depth: u32, parent: Option<usize>, cpu: (u64, u64), gpu: Option<(u64, u64)>) -> ProfiledScope {
+        ProfiledScope {
+            name: name.to_string(),
+            depth,
+            parent,
+            cpu_start: Duration::from_micros(cpu.0),
+            cpu_duration: Duration::from_micros(cpu.1),
+            gpu_start: gpu.map(|gpu| Duration::from_micros(gpu.0)),
+            gpu_duration: gpu.map(|gpu| Duration::from_micros(gpu.1)),
+        }
+    }
+
+    #[test]
+    fn writes_cpu_and_gpu_events() {
+        let frame = ProfiledFrame {
+            index: 7,
+            scopes: vec![
+                create_scope("Frame", 0, None, (100, 50), Some((120, 40))),
+                create_scope("Shadows", 1, Some(0), (110, 10), None),
+            ],
+        };
+
+        let mut json = Vec::new();
+        write_chrome_trace(&[&frame], &mut json).unwrap();
+        let trace: serde_json::Value = serde_json::from_slice(&json).unwrap();
+
+        let events = trace["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), 5);
+        assert_eq!(events[1]["args"]["name"], "GPU");
+        assert_eq!(events[2]["name"], "Frame");
+        assert_eq!(events[2]["ts"], 100.0);
+        assert_eq!(events[2]["dur"], 50.0);
+        assert_eq!(events[3]["tid"], GPU_THREAD_ID);
+        assert_eq!(events[3]["ts"], 120.0);
+        assert_eq!(events[4]["name"], "Shadows");
+        assert_eq!(events[4]["args"]["frame"], 7);
+    }
+
+    #[test]
+    fn formats_nested_breakdown() {
+        let frame = ProfiledFrame {
+            index: 0,
+            scopes: vec![
+                create_scope("Frame", 0, None, (0, 2000), Some((0, 1500))),
+                create_scope("Shadows", 1, Some(0), (0, 500), None),
+            ],
+        };
+        assert_eq!(frame.format_breakdown(), "Frame  cpu 2.00 ms  gpu 1.50 ms\n  Shadows  cpu 0.50 ms\n");
+    }
+}
@@ -0,0 +1,393 @@
+use gl::types::*;
+
+use std::collections::{ HashSet };
+use std::ffi::{ CStr };
+use std::sync::{ OnceLock };
+
+// The oldest contexts the wrappers support. Anything newer than these is only used when the capabilities report it.
+pub const MINIMUM_GL_VERSION: (u32, u32) = (3, 3);
+pub const MINIMUM_GLES_VERSION: (u32, u32) = (3, 0);
+
+static CAPABILITIES: OnceLock<GLCapabilities> = OnceLock::new();
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ContextApi {
+    OpenGL,
+    OpenGLES,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct GLLimits {
+    pub max_texture_size: u32,
+    pub max_3d_texture_size: u32,
+    pub max_cube_map_texture_size: u32,
+    pub max_array_texture_layers: u32,
+    pub max_texture_image_units: u32,
+    pub max_combined_texture_image_units: u32,
+    pub max_vertex_attributes: u32,
+    pub max_uniform_block_size: u32,
+    pub max_uniform_buffer_bindings: u32,
+    pub max_color_attachments: u32,
+    pub max_draw_buffers: u32,
+    pub max_samples: u32,
+    // 1.0 when anisotropic filtering isn't available.
+    pub max_anisotropy: f32,
+}
+
+#[derive(Debug)]
+pub struct GLCapabilities {
+    api: ContextApi,
+    version: (u32, u32),
+    vendor: String,
+    renderer: String,
+    version_string: String,
+    shading_language_version: String,
+    extensions: HashSet<String>,
+    limits: GLLimits,
+}
+
+impl GLCapabilities {
+    // Needs a current context with the `gl` function pointers loaded.
+    pub fn query() -> GLCapabilities {
+        unsafe {
+            let version_string = get_gl_string(gl::VERSION);
+            let api = if version_string.starts_with("OpenGL ES") { ContextApi::OpenGLES } else { ContextApi::OpenGL };
+
+            let (mut major_version, mut minor_version) = (0, 0);
+            gl::GetIntegerv(gl::MAJOR_VERSION, &mut major_version);
+            gl::GetIntegerv(gl::MINOR_VERSION, &mut minor_version);
+
+            let mut extension_count = 0;
+            gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut extension_count);
+            let mut extensions = HashSet::with_capacity(extension_count as usize);
+            for index in 0..extension_count as GLuint {
+                let extension = gl::GetStringi(gl::EXTENSIONS, index);
+                if !extension.is_null() {
+                    extensions.insert(CStr::from_ptr(extension as *const _).to_string_lossy().into_owned());
+                }
+            }
+
+            let mut capabilities = GLCapabilities {
+                api,
+                version: (major_version as u32, minor_version as u32),
+                vendor: get_gl_string(gl::VENDOR),
+                renderer: get_gl_string(gl::RENDERER),
+                version_string,
+                shading_language_version: get_gl_string(gl::SHADING_LANGUAGE_VERSION),
+                extensions,
+                limits: GLLimits {
+                    max_texture_size: get_integer(gl::MAX_TEXTURE_SIZE),
+                    max_3d_texture_size: get_integer(gl::MAX_3D_TEXTURE_SIZE),
+                    max_cube_map_texture_size: get_integer(gl::MAX_CUBE_MAP_TEXTURE_SIZE),
+                    max_array_texture_layers: get_integer(gl::MAX_ARRAY_TEXTURE_LAYERS),
+                    max_texture_image_units: get_integer(gl::MAX_TEXTURE_IMAGE_UNITS),
+                    max_combined_texture_image_units: get_integer(gl::MAX_COMBINED_TEXTURE_IMAGE_UNITS),
+                    max_vertex_attributes: get_integer(gl::MAX_VERTEX_ATTRIBS),
+                    max_uniform_block_size: get_integer(gl::MAX_UNIFORM_BLOCK_SIZE),
+                    max_uniform_buffer_bindings: get_integer(gl::MAX_UNIFORM_BUFFER_BINDINGS),
+                    max_color_attachments: get_integer(gl::MAX_COLOR_ATTACHMENTS),
+                    max_draw_buffers: get_integer(gl::MAX_DRAW_BUFFERS),
+                    max_samples: get_integer(gl::MAX_SAMPLES),
+                    max_anisotropy: 1.0,
+                },
+            };
+
+            if capabilities.has_anisotropic_filtering() {
+                gl::GetFloatv(crate::gl_extensions::MAX_TEXTURE_MAX_ANISOTROPY, &mut capabilities.limits.max_anisotropy);
+            }
+            return capabilities;
+        }
+    }
+
+    pub fn get_api(&self) -> ContextApi {
+        self.api
+    }
+
+    pub fn is_gles(&self) -> bool {
+        self.api == ContextApi::OpenGLES
+    }
+
+    pub fn get_version(&self) -> (u32, u32) {
+        self.version
+    }
+
+    pub fn get_vendor(&self) -> &str {
+        &self.vendor
+    }
+
+    pub fn get_renderer(&self) -> &str {
+        &self.renderer
+    }
+
+    pub fn get_version_string(&self) -> &str {
+        &self.version_string
+    }
+
+    pub fn get_shading_language_version(&self) -> &str {
+        &self.shading_language_version
+    }
+
+    pub fn get_extensions(&self) -> &HashSet<String> {
+        &self.extensions
+    }
+
+    pub fn get_limits(&self) -> &GLLimits {
+        &self.limits
+    }
+
+    pub fn has_extension(&self, name: &str) -> bool {
+        self.extensions.contains(name)
+    }
+
+    // Checks against the desktop version on GL contexts and the ES version on GLES contexts.
+    pub fn is_version_at_least(&self, gl_version: (u32, u32), gles_version: (u32, u32)) -> bool {
+        match self.api {
+            ContextApi::OpenGL => self.version >= gl_version,
+            ContextApi::OpenGLES => self.version >= gles_version,
+        }
+    }
+
+    pub fn meets_minimum_profile(&self) -> bool {
+        self.is_version_at_least(MINIMUM_GL_VERSION, MINIMUM_GLES_VERSION)
+    }
+
+    // The `#version` line shaders are compiled with, e.g. `330 core` or `300 es`.
+    pub fn get_glsl_version(&self) -> (u32, &'static str) {
+        let (major_version, minor_version) = self.version;
+        return match self.api {
+            ContextApi::OpenGL => (major_version * 100 + minor_version * 10, "core"),
+            ContextApi::OpenGLES => (major_version * 100 + minor_version * 10, "es"),
+        };
+    }
+
+    pub fn has_direct_state_access(&self) -> bool {
+        !self.is_gles() && (self.version >= (4, 5) || self.has_extension("GL_ARB_direct_state_access"))
+    }
+
+    // glProgramUniform*, program pipelines and explicit locations on varyings.
+    pub fn has_separate_shader_objects(&self) -> bool {
+        self.is_version_at_least((4, 1), (3, 1)) || self.has_extension("GL_ARB_separate_shader_objects")
+    }
+
+    pub fn has_texture_storage(&self) -> bool {
+        self.is_version_at_least((4, 2), (3, 0)) || self.has_extension("GL_ARB_texture_storage")
+    }
+
+    pub fn has_texture_storage_multisample(&self) -> bool {
+        self.is_version_at_least((4, 3), (3, 1)) || self.has_extension("GL_ARB_texture_storage_multisample")
+    }
+
+    // GL_TEXTURE_2D_MULTISAMPLE. Without it, multisampled colour attachments have to be renderbuffers.
+    pub fn has_multisample_textures(&self) -> bool {
+        self.is_version_at_least((3, 2), (3, 1)) || self.has_extension("GL_ARB_texture_multisample")
+    }
+
+    // Line and point polygon modes, which GLES only has through glPolygonModeNV.
+    pub fn has_polygon_mode(&self) -> bool {
+        !self.is_gles() || self.has_extension("GL_NV_polygon_mode")
+    }
+
+    pub fn has_texture_border_clamp(&self) -> bool {
+        !self.is_gles() || self.version >= (3, 2) || self.has_extension("GL_OES_texture_border_clamp") || self.has_extension("GL_EXT_texture_border_clamp")
+    }
+
+    // GL_TEXTURE_LOD_BIAS as a texture parameter, which GLES only has as a shader argument.
+    pub fn has_texture_lod_bias(&self) -> bool {
+        !self.is_gles()
+    }
+
+    // Per-attachment blend equations and factors.
+    pub fn has_indexed_blending(&self) -> bool {
+        self.is_version_at_least((4, 0), (3, 2)) || self.has_extension("GL_ARB_draw_buffers_blend") || self.has_extension("GL_OES_draw_buffers_indexed")
+    }
+
+    pub fn has_anisotropic_filtering(&self) -> bool {
+        (!self.is_gles() && self.version >= (4, 6))
+            || self.has_extension("GL_ARB_texture_filter_anisotropic")
+            || self.has_extension("GL_EXT_texture_filter_anisotropic")
+    }
+
+    // glDebugMessageCallback, object labels and debug groups.
+    pub fn has_debug_output(&self) -> bool {
+        self.is_version_at_least((4, 3), (3, 2)) || self.has_extension("GL_KHR_debug")
+    }
+
+    // GL_TIME_ELAPSED and GL_TIMESTAMP queries.
+    pub fn has_timer_queries(&self) -> bool {
+        if self.is_gles() {
+            return self.has_extension("GL_EXT_disjoint_timer_query");
+        }
+        return self.version >= (3, 3) || self.has_extension("GL_ARB_timer_query");
+    }
+
+    // glBeginConditionalRender, which GLES only has through vendor extensions.
+    pub fn has_conditional_render(&self) -> bool {
+        !self.is_gles()
+    }
+
+    pub fn has_texture_image_readback(&self) -> bool {
+        !self.is_gles()
+    }
+
+    pub fn has_spir_v(&self) -> bool {
+        (!self.is_gles() && self.version >= (4, 6)) || self.has_extension("GL_ARB_gl_spirv")
+    }
+
+    // Rewrites a shader written against `#version 440 core` for this context. The line count is kept, so diagnostics
+    // still point at the original source. Without separate shader objects, varyings are matched by name instead of location.
+    pub fn translate_shader_source(&self, source: &str, stage: GLenum) -> String {
+        let (glsl_version, profile) = self.get_glsl_version();
+        let declared_version = source.lines()
+            .find_map(|line| line.trim_start().strip_prefix("#version"))
+            .and_then(|version| version.split_whitespace().next())
+            .and_then(|version| version.parse::<u32>().ok());
+        if !self.is_gles() && declared_version.is_some_and(|version| version <= glsl_version) {
+            return source.to_string();
+        }
+
+        let strip_varying_locations = !self.has_separate_shader_objects();
+        let varying_qualifier = if stage == gl::VERTEX_SHADER { "out" } else { "in" };
+
+        let mut output = String::with_capacity(source.len() + 128);
+        for line in source.lines() {
+            if line.trim_start().starts_with("#version") {
+                output.push_str(&format!("#version {} {}", glsl_version, profile));
+                if self.is_gles() {
+                    // GLES has no default precision for floats in fragment shaders or for most sampler types.
+                    output.push_str("\nprecision highp float; precision highp int; precision highp sampler2DArray; precision highp sampler3D;\n#line 2");
+                }
+            } else if strip_varying_locations && stage != gl::COMPUTE_SHADER {
+                output.push_str(&strip_location_qualifier(line, varying_qualifier));
+            } else {
+                output.push_str(line);
+            }
+            output.push('\n');
+        }
+        return output;
+    }
+}
+
+// Turns `layout(location = 0) out vec2 v_TexCoord;` into `out vec2 v_TexCoord;` for the given storage qualifier.
+fn strip_location_qualifier(line: &str, qualifier: &str) -> String {
+    let trimmed = line.trim_start();
+    let layout = match trimmed.strip_prefix("layout") {
+        Some(layout) => layout.trim_start(),
+        None => return line.to_string(),
+    };
+    let (arguments, declaration) = match layout.strip_prefix('(').and_then(|layout| layout.split_once(')')) {
+        Some(split) => split,
+        None => return line.to_string(),
+    };
+
+    let is_location_only = arguments.split('=').next().is_some_and(|name| name.trim() == "location") && !arguments.contains(',');
+    let declaration = declaration.trim_start();
+    if !is_location_only || declaration.split_whitespace().next() != Some(qualifier) {
+        return line.to_string();
+    }
+
+    let indentation = &line[..line.len() - trimmed.len()];
+    return format!("{}{}", indentation, declaration);
+}
+
+unsafe fn get_gl_string(name: GLenum) -> String {
+    let string = gl::GetString(name);
+    if string.is_null() {
+        return String::new();
+    }
+    return CStr::from_ptr(string as *const _).to_string_lossy().into_owned();
+}
+
+unsafe fn get_integer(name: GLenum) -> u32 {
+    let mut value = 0;
+    gl::GetIntegerv(name, &mut value);
+    return value.max(0) as u32;
+}
+
+// Queried once by `gl_extensions::load_with`. Every context the program creates is expected to share these.
+pub fn initialize() -> &'static GLCapabilities {
+    CAPABILITIES.get_or_init(GLCapabilities::query)
+}
+
+pub fn get() -> &'static GLCapabilities {
+    CAPABILITIES.get().expect("The GL capabilities are queried by gl_extensions::load_with, which hasn't run yet!")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_capabilities(api: ContextApi, version: (u32, u32)) -> GLCapabilities {
+        GLCapabilities {
+            api,
+            version,
+            vendor: String::new(),
+            renderer: String::new(),
+            version_string: String::new(),
+            shading_language_version: String::new(),
+            extensions: HashSet::new(),
+            limits: GLLimits {
+                max_texture_size: 0,
+                max_3d_texture_size: 0,
+                max_cube_map_texture_size: 0,
+                max_array_texture_layers: 0,
+                max_texture_image_units: 0,
+                max_combined_texture_image_units: 0,
+                max_vertex_attributes: 0,
+                max_uniform_block_size: 0,
+                max_uniform_buffer_bindings: 0,
+                max_color_attachments: 0,
+                max_draw_buffers: 0,
+                max_samples: 0,
+                max_anisotropy: 1.0,
+            },
+        }
+    }
+
+    const VERTEX_SOURCE: &str = "#version 440 core\n\nlayout(location = 0) in vec4 a_Position;\nlayout(location = 0) out vec2 v_TexCoord;\n";
+
+    #[test]
+    fn keeps_sources_the_context_supports() {
+        let capabilities = create_capabilities(ContextApi::OpenGL, (4, 6));
+        assert_eq!(capabilities.translate_shader_source(VERTEX_SOURCE, gl::VERTEX_SHADER), VERTEX_SOURCE);
+    }
+
+    #[test]
+    fn lowers_sources_to_gl_3_3() {
+        let capabilities = create_capabilities(ContextApi::OpenGL, (3, 3));
+        assert_eq!(
+            capabilities.translate_shader_source(VERTEX_SOURCE, gl::VERTEX_SHADER),
+            "#version 330 core\n\nlayout(location = 0) in vec4 a_Position;\nout vec2 v_TexCoord;\n"
+        );
+
+        let fragment_source = "#version 440 core\nlayout(location = 0) out vec4 o_Color;\n    layout(location = 0) in vec2 v_TexCoord;\n";
+        assert_eq!(
+            capabilities.translate_shader_source(fragment_source, gl::FRAGMENT_SHADER),
+            "#version 330 core\nlayout(location = 0) out vec4 o_Color;\n    in vec2 v_TexCoord;\n"
+        );
+    }
+
+    #[test]
+    fn adds_default_precision_for_gles() {
+        let capabilities = create_capabilities(ContextApi::OpenGLES, (3, 0));
+        let translated = capabilities.translate_shader_source(VERTEX_SOURCE, gl::VERTEX_SHADER);
+        assert!(translated.starts_with("#version 300 es\nprecision highp float;"));
+        assert!(translated.contains("\n#line 2\n\nlayout(location = 0) in vec4 a_Position;\nout vec2 v_TexCoord;\n"));
+    }
+
+    #[test]
+    fn gles_3_0_falls_back_to_the_core_features() {
+        let mut capabilities = create_capabilities(ContextApi::OpenGLES, (3, 0));
+        assert!(!capabilities.has_polygon_mode());
+        assert!(!capabilities.has_multisample_textures());
+        assert!(!capabilities.has_texture_border_clamp());
+        assert!(!capabilities.has_texture_lod_bias());
+
+        capabilities.extensions.insert("GL_NV_polygon_mode".to_string());
+        capabilities.extensions.insert("GL_EXT_texture_border_clamp".to_string());
+        assert!(capabilities.has_polygon_mode());
+        assert!(capabilities.has_texture_border_clamp());
+
+        let capabilities = create_capabilities(ContextApi::OpenGL, (3, 3));
+        assert!(capabilities.has_polygon_mode() && capabilities.has_multisample_textures() && capabilities.has_texture_border_clamp());
+    }
+}
@@ -0,0 +1,206 @@
+use crate::gl_object_tracker::{ self, ObjectKey, ObjectKind };
+use crate::gl_state;
+
+use gl::types::*;
+
+use std::cell::{ Cell, RefCell };
+use std::rc::{ Rc };
+use std::sync::atomic::{ AtomicU64, AtomicUsize, Ordering };
+
+static LIVE_CONTEXTS: AtomicUsize = AtomicUsize::new(0);
+// 0 stands for no context, so ids start at 1.
+static NEXT_CONTEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    static CURRENT_CONTEXT: RefCell<Option<ContextHandle>> = const { RefCell::new(None) };
+}
+
+// A GL object whose deletion has been deferred until its context is known to be current.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GLObject {
+    Buffer(GLuint),
+    Texture(GLuint),
+    VertexArray(GLuint),
+    Framebuffer(GLuint),
+    Renderbuffer(GLuint),
+    Program(GLuint),
+    ProgramPipeline(GLuint),
+    Query(GLuint),
+    Sync(GLsync),
+}
+
+impl GLObject {
+    // `context_id` only matters for the kinds that aren't shared between contexts.
+    pub fn get_key(self, context_id: u64) -> ObjectKey {
+        let (kind, name) = match self {
+            GLObject::Buffer(buffer) => (ObjectKind::Buffer, buffer as usize),
+            GLObject::Texture(texture) => (ObjectKind::Texture, texture as usize),
+            GLObject::VertexArray(vertex_array) => (ObjectKind::VertexArray, vertex_array as usize),
+            GLObject::Framebuffer(framebuffer) => (ObjectKind::Framebuffer, framebuffer as usize),
+            GLObject::Renderbuffer(renderbuffer) => (ObjectKind::Renderbuffer, renderbuffer as usize),
+            GLObject::Program(program) => (ObjectKind::Program, program as usize),
+            GLObject::ProgramPipeline(program_pipeline) => (ObjectKind::ProgramPipeline, program_pipeline as usize),
+            GLObject::Query(query) => (ObjectKind::Query, query as usize),
+            GLObject::Sync(sync) => (ObjectKind::Sync, sync as usize),
+        };
+        return gl_object_tracker::get_key(kind, name, context_id);
+    }
+
+    // The key as seen from the context current on this thread.
+    pub fn get_current_key(self) -> ObjectKey {
+        self.get_key(ContextHandle::get_current_id())
+    }
+
+    unsafe fn delete(self) {
+        match self {
+            GLObject::Buffer(buffer) => {
+                gl_state::forget_buffer(buffer);
+                gl::DeleteBuffers(1, &buffer);
+            }
+            GLObject::Texture(texture) => {
+                gl_state::forget_texture(texture);
+                gl::DeleteTextures(1, &texture);
+            }
+            GLObject::VertexArray(vertex_array) => {
+                gl_state::forget_vertex_array(vertex_array);
+                gl::DeleteVertexArrays(1, &vertex_array);
+            }
+            GLObject::Framebuffer(framebuffer) => gl::DeleteFramebuffers(1, &framebuffer),
+            GLObject::Renderbuffer(renderbuffer) => gl::DeleteRenderbuffers(1, &renderbuffer),
+            GLObject::Program(program) => {
+                gl_state::forget_program(program);
+                gl::DeleteProgram(program);
+            }
+            GLObject::ProgramPipeline(program_pipeline) => gl::DeleteProgramPipelines(1, &program_pipeline),
+            GLObject::Query(query) => gl::DeleteQueries(1, &query),
+            GLObject::Sync(sync) => gl::DeleteSync(sync),
+        }
+    }
+}
+
+struct ContextState {
+    id: u64,
+    alive: Cell<bool>,
+    destruction_queue: RefCell<Vec<GLObject>>,
+}
+
+// Every wrapper keeps one of these. It is an `Rc`, so the wrappers are neither `Send` nor `Sync` and can only be
+// dropped on the thread their context belongs to.
+#[derive(Clone)]
+pub struct ContextHandle {
+    state: Rc<ContextState>,
+}
+
+impl ContextHandle {
+    // The context of the calling thread. Panics if there is none, since no GL object can be created without one.
+    pub fn current() -> ContextHandle {
+        ContextHandle::try_current().expect("No GL context is current on this thread!")
+    }
+
+    pub fn try_current() -> Option<ContextHandle> {
+        CURRENT_CONTEXT.with(|current| current.borrow().clone())
+    }
+
+    // The id of the calling thread's context, or 0 if there is none.
+    pub fn get_current_id() -> u64 {
+        CURRENT_CONTEXT.with(|current| current.borrow().as_ref().map_or(0, |context| context.get_id()))
+    }
+
+    // The current context, which takes over deleting `object`. Called by the wrappers as they create or receive objects.
+    pub fn adopt(object: GLObject) -> ContextHandle {
+        let context = ContextHandle::current();
+        gl_object_tracker::register(object.get_key(context.get_id()));
+        return context;
+    }
+
+    pub fn get_id(&self) -> u64 {
+        self.state.id
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.state.alive.get()
+    }
+
+    // Queues the object for the next `GLContext::flush_destruction_queue`. Objects of a context that has already been
+    // destroyed went with it, so they are only forgotten.
+    pub fn delete(&self, object: GLObject) {
+        gl_object_tracker::unregister(object.get_key(self.get_id()));
+        if self.is_alive() {
+            self.state.destruction_queue.borrow_mut().push(object);
+        }
+    }
+}
+
+// Owns the destruction queue of a window's context on the thread it is current on. Create it right after the context
+// has been made current and loaded, and drop it while the context is still alive: declared after the window in the
+// same scope, it is dropped first, so everything released up to then is still deleted.
+pub struct GLContext {
+    handle: ContextHandle,
+    // Only compared against the current context, never dereferenced, so it is fine for it to outlive the window.
+    window: *mut glfw::ffi::GLFWwindow,
+}
+
+impl GLContext {
+    pub fn new<C: glfw::Context>(window: &C) -> GLContext {
+        assert!(window.is_current(), "The window's context has to be current on this thread!");
+        let handle = ContextHandle {
+            state: Rc::new(ContextState {
+                id: NEXT_CONTEXT_ID.fetch_add(1, Ordering::Relaxed),
+                alive: Cell::new(true),
+                destruction_queue: RefCell::new(Vec::new()),
+            }),
+        };
+
+        CURRENT_CONTEXT.with(|current| {
+            let mut current = current.borrow_mut();
+            assert!(current.is_none(), "This thread already has a GL context!");
+            *current = Some(handle.clone());
+        });
+        LIVE_CONTEXTS.fetch_add(1, Ordering::Relaxed);
+        return GLContext {
+            handle,
+            window: window.window_ptr(),
+        };
+    }
+
+    pub fn get_handle(&self) -> ContextHandle {
+        self.handle.clone()
+    }
+
+    pub fn is_current(&self) -> bool {
+        unsafe { glfw::ffi::glfwGetCurrentContext() == self.window }
+    }
+
+    // Deletes everything released since the last flush. Call once a frame, e.g. after swapping buffers.
+    pub fn flush_destruction_queue(&self) {
+        assert!(self.is_current(), "The GL context has to be current to delete its objects!");
+        let objects = std::mem::take(&mut *self.handle.state.destruction_queue.borrow_mut());
+        for object in objects {
+            unsafe { object.delete(); }
+        }
+    }
+
+    pub fn get_pending_deletions(&self) -> usize {
+        self.handle.state.destruction_queue.borrow().len()
+    }
+}
+
+impl Drop for GLContext {
+    fn drop(&mut self) {
+        // Without the context, e.g. once its window is gone, the objects can't be deleted and are only forgotten.
+        if self.is_current() {
+            self.flush_destruction_queue();
+        } else {
+            let skipped = std::mem::take(&mut *self.handle.state.destruction_queue.borrow_mut()).len();
+            log::warn!(target: "gl", "The GL context wasn't current when it was dropped, so {} queued deletions were skipped", skipped);
+        }
+        self.handle.state.alive.set(false);
+        CURRENT_CONTEXT.with(|current| *current.borrow_mut() = None);
+        gl_state::invalidate();
+
+        // Whatever the last context leaves behind was never dropped, since dropping only queues the deletion.
+        if LIVE_CONTEXTS.fetch_sub(1, Ordering::Relaxed) == 1 {
+            gl_object_tracker::report_leaks();
+        }
+    }
+}
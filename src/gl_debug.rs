@@ -0,0 +1,236 @@
+use crate::gl_capabilities;
+use crate::gl_context::{ ContextHandle };
+use crate::gl_object_tracker::{ self, ObjectKind };
+
+use gl::types::*;
+
+use std::ffi::{ CStr, CString };
+use std::os::raw::{ c_void };
+use std::sync::atomic::{ AtomicBool, Ordering };
+
+static ERROR_CHECKING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DebugLayer {
+    // Messages come from the driver through glDebugMessageCallback.
+    DebugOutput,
+    // The context has no debug output, so `check_errors` polls glGetError instead.
+    ErrorChecking,
+}
+
+// Opt-in, since both paths cost driver time. With `synchronous` the callback runs inside the offending call,
+// so a breakpoint in it shows the exact call site. Returns which layer ended up active.
+pub fn enable(synchronous: bool) -> DebugLayer {
+    if !is_debug_output_supported() {
+        ERROR_CHECKING.store(true, Ordering::Relaxed);
+        log::info!(target: "gl", "Debug output is not supported by this context, falling back to glGetError checks");
+        return DebugLayer::ErrorChecking;
+    }
+
+    unsafe {
+        gl::Enable(gl::DEBUG_OUTPUT);
+        if synchronous {
+            gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+        } else {
+            gl::Disable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+        }
+        gl::DebugMessageCallback(Some(debug_message_callback), std::ptr::null());
+        gl::DebugMessageControl(gl::DONT_CARE, gl::DONT_CARE, gl::DONT_CARE, 0, std::ptr::null(), gl::TRUE);
+    }
+    ERROR_CHECKING.store(false, Ordering::Relaxed);
+    return DebugLayer::DebugOutput;
+}
+
+pub fn disable() {
+    ERROR_CHECKING.store(false, Ordering::Relaxed);
+    if is_debug_output_supported() {
+        unsafe {
+            gl::DebugMessageCallback(None, std::ptr::null());
+            gl::Disable(gl::DEBUG_OUTPUT);
+        }
+    }
+}
+
+// Labels and groups work without the callback too, so frame captures can use them even when the layer is off.
+pub fn is_debug_output_supported() -> bool {
+    gl_capabilities::get().has_debug_output() && gl::DebugMessageCallback::is_loaded()
+}
+
+// Logs every error raised since the last check. Does nothing unless `enable` fell back to error checking. The wrappers
+// call it after creating objects, uploading data, compiling and linking shaders, attaching framebuffer attachments,
+// beginning queries and drawing, so an error is reported close to the call that raised it.
+pub fn check_errors(operation: &str) {
+    if !ERROR_CHECKING.load(Ordering::Relaxed) {
+        return;
+    }
+
+    loop {
+        let error = unsafe { gl::GetError() };
+        if error == gl::NO_ERROR {
+            break;
+        }
+        log::error!(target: "gl", "{} failed with {}", operation, get_error_name(error));
+    }
+}
+
+fn get_error_name(error: GLenum) -> &'static str {
+    match error {
+        gl::INVALID_ENUM => "GL_INVALID_ENUM",
+        gl::INVALID_VALUE => "GL_INVALID_VALUE",
+        gl::INVALID_OPERATION => "GL_INVALID_OPERATION",
+        gl::INVALID_FRAMEBUFFER_OPERATION => "GL_INVALID_FRAMEBUFFER_OPERATION",
+        gl::OUT_OF_MEMORY => "GL_OUT_OF_MEMORY",
+        gl::STACK_UNDERFLOW => "GL_STACK_UNDERFLOW",
+        gl::STACK_OVERFLOW => "GL_STACK_OVERFLOW",
+        _ => "an unknown error",
+    }
+}
+
+// A name from glGen* only becomes an object, which glObjectLabel needs, once it has been bound. Binds vertex arrays,
+// framebuffers and program pipelines that never have been once, leaving the previous binding in place.
+pub fn ensure_object_exists(identifier: GLenum, name: GLuint) {
+    unsafe {
+        match identifier {
+            gl::VERTEX_ARRAY if gl::IsVertexArray(name) == gl::FALSE => {
+                let mut previous_vertex_array = 0;
+                gl::GetIntegerv(gl::VERTEX_ARRAY_BINDING, &mut previous_vertex_array);
+                gl::BindVertexArray(name);
+                gl::BindVertexArray(previous_vertex_array as GLuint);
+            }
+            gl::FRAMEBUFFER if gl::IsFramebuffer(name) == gl::FALSE => {
+                let mut previous_read_framebuffer = 0;
+                gl::GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut previous_read_framebuffer);
+                gl::BindFramebuffer(gl::READ_FRAMEBUFFER, name);
+                gl::BindFramebuffer(gl::READ_FRAMEBUFFER, previous_read_framebuffer as GLuint);
+            }
+            gl::PROGRAM_PIPELINE if gl::IsProgramPipeline(name) == gl::FALSE => {
+                let mut previous_program_pipeline = 0;
+                gl::GetIntegerv(gl::PROGRAM_PIPELINE_BINDING, &mut previous_program_pipeline);
+                gl::BindProgramPipeline(name);
+                gl::BindProgramPipeline(previous_program_pipeline as GLuint);
+            }
+            _ => {}
+        }
+    }
+}
+
+// `identifier` is the object namespace, e.g. GL_BUFFER or GL_TEXTURE. The object has to exist, not just its name.
+pub fn set_object_label(identifier: GLenum, name: GLuint, label: &str) {
+    if let Some(kind) = ObjectKind::from_gl_identifier(identifier) {
+        gl_object_tracker::set_label(gl_object_tracker::get_key(kind, name as usize, ContextHandle::get_current_id()), label);
+    }
+
+    if !is_debug_output_supported() || !gl::ObjectLabel::is_loaded() {
+        return;
+    }
+
+    let c_string_label = to_c_string(label);
+    unsafe {
+        gl::ObjectLabel(identifier, name, -1, c_string_label.as_ptr());
+    }
+}
+
+// GL reads strings up to the first NUL, so any inside the text are dropped instead of cutting it short.
+fn to_c_string(text: &str) -> CString {
+    CString::new(text.replace('\0', "")).unwrap()
+}
+
+// Groups nest, and tools like RenderDoc show the calls made inside one under its message.
+#[must_use = "The debug group is popped as soon as it is dropped"]
+pub struct DebugGroup {
+    pushed: bool,
+}
+
+pub fn push_debug_group(message: &str) -> DebugGroup {
+    if !is_debug_output_supported() || !gl::PushDebugGroup::is_loaded() {
+        return DebugGroup { pushed: false };
+    }
+
+    let c_string_message = to_c_string(message);
+    unsafe {
+        gl::PushDebugGroup(gl::DEBUG_SOURCE_APPLICATION, 0, -1, c_string_message.as_ptr());
+    }
+    return DebugGroup { pushed: true };
+}
+
+impl DebugGroup {
+    // Ends the group before the end of the scope.
+    pub fn pop_debug_group(self) {
+        drop(self);
+    }
+}
+
+impl Drop for DebugGroup {
+    fn drop(&mut self) {
+        if self.pushed {
+            unsafe {
+                gl::PopDebugGroup();
+            }
+        }
+    }
+}
+
+extern "system" fn debug_message_callback(
+    source: GLenum,
+    message_type: GLenum,
+    id: GLuint,
+    severity: GLenum,
+    length: GLsizei,
+    message: *const GLchar,
+    _user_parameter: *mut c_void,
+) {
+    let message = unsafe {
+        if length >= 0 {
+            String::from_utf8_lossy(std::slice::from_raw_parts(message as *const u8, length as usize)).into_owned()
+        } else {
+            CStr::from_ptr(message).to_string_lossy().into_owned()
+        }
+    };
+
+    // Errors and undefined behaviour are always worth seeing, whatever severity the driver gave them.
+    let level = match (message_type, severity) {
+        (gl::DEBUG_TYPE_ERROR, _) | (gl::DEBUG_TYPE_UNDEFINED_BEHAVIOR, _) => log::Level::Error,
+        (gl::DEBUG_TYPE_PUSH_GROUP, _) | (gl::DEBUG_TYPE_POP_GROUP, _) => log::Level::Trace,
+        (_, gl::DEBUG_SEVERITY_HIGH) => log::Level::Error,
+        (_, gl::DEBUG_SEVERITY_MEDIUM) => log::Level::Warn,
+        (_, gl::DEBUG_SEVERITY_LOW) => log::Level::Info,
+        _ => log::Level::Debug,
+    };
+    log::log!(target: "gl", level, "[{} {} {}] {}", get_source_name(source), get_type_name(message_type), id, message.trim_end());
+}
+
+fn get_source_name(source: GLenum) -> &'static str {
+    match source {
+        gl::DEBUG_SOURCE_API => "API",
+        gl::DEBUG_SOURCE_WINDOW_SYSTEM => "Window System",
+        gl::DEBUG_SOURCE_SHADER_COMPILER => "Shader Compiler",
+        gl::DEBUG_SOURCE_THIRD_PARTY => "Third Party",
+        gl::DEBUG_SOURCE_APPLICATION => "Application",
+        _ => "Other",
+    }
+}
+
+fn get_type_name(message_type: GLenum) -> &'static str {
+    match message_type {
+        gl::DEBUG_TYPE_ERROR => "Error",
+        gl::DEBUG_TYPE_DEPRECATED_BEHAVIOR => "Deprecated Behavior",
+        gl::DEBUG_TYPE_UNDEFINED_BEHAVIOR => "Undefined Behavior",
+        gl::DEBUG_TYPE_PORTABILITY => "Portability",
+        gl::DEBUG_TYPE_PERFORMANCE => "Performance",
+        gl::DEBUG_TYPE_MARKER => "Marker",
+        gl::DEBUG_TYPE_PUSH_GROUP => "Push Group",
+        gl::DEBUG_TYPE_POP_GROUP => "Pop Group",
+        _ => "Other",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interior_nuls_are_dropped() {
+        assert_eq!(to_c_string("Albedo\0 Map").as_bytes(), b"Albedo Map");
+        assert_eq!(to_c_string("\0").as_bytes(), b"");
+    }
+}
@@ -0,0 +1,80 @@
+#![allow(non_snake_case)]
+
+use crate::gl_capabilities;
+
+use gl::types::*;
+use std::os::raw::{ c_void };
+use std::sync::atomic::{ AtomicBool, AtomicPtr, Ordering };
+
+pub const SHADER_BINARY_FORMAT_SPIR_V: GLenum = 0x9551;
+pub const SPIR_V_BINARY: GLenum = 0x9552;
+
+pub const TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FE;
+pub const MAX_TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FF;
+
+pub const COMPRESSED_RGB_S3TC_DXT1: GLenum = 0x83F0;
+pub const COMPRESSED_RGBA_S3TC_DXT1: GLenum = 0x83F1;
+pub const COMPRESSED_RGBA_S3TC_DXT3: GLenum = 0x83F2;
+pub const COMPRESSED_RGBA_S3TC_DXT5: GLenum = 0x83F3;
+pub const COMPRESSED_SRGB_S3TC_DXT1: GLenum = 0x8C4C;
+pub const COMPRESSED_SRGB_ALPHA_S3TC_DXT1: GLenum = 0x8C4D;
+pub const COMPRESSED_SRGB_ALPHA_S3TC_DXT3: GLenum = 0x8C4E;
+pub const COMPRESSED_SRGB_ALPHA_S3TC_DXT5: GLenum = 0x8C4F;
+
+// The ASTC formats are laid out in block size order starting at 4x4: 4x4, 5x4, 5x5, 6x5, 6x6, 8x5, 8x6, 8x8, 10x5, 10x6, 10x8, 10x10, 12x10, 12x12.
+pub const COMPRESSED_RGBA_ASTC_4X4: GLenum = 0x93B0;
+pub const COMPRESSED_SRGB8_ALPHA8_ASTC_4X4: GLenum = 0x93D0;
+
+type SpecializeShaderFn = extern "system" fn(GLuint, *const GLchar, GLuint, *const GLuint, *const GLuint);
+
+static SPECIALIZE_SHADER: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+
+static DIRECT_STATE_ACCESS: AtomicBool = AtomicBool::new(false);
+
+// The `gl` crate only generates the 4.5 core profile, so entry points from newer versions and extensions are loaded here.
+// This also queries the context capabilities, so it has to run after `gl::load_with` with the context current.
+pub fn load_with<F: FnMut(&'static str) -> *const c_void>(mut loader: F) {
+    let mut load = |names: &[&'static str]| {
+        names.iter()
+            .map(|name| loader(name))
+            .find(|pointer| !pointer.is_null())
+            .unwrap_or(std::ptr::null()) as *mut c_void
+    };
+
+    SPECIALIZE_SHADER.store(load(&["glSpecializeShader", "glSpecializeShaderARB"]), Ordering::Relaxed);
+
+    gl_capabilities::initialize();
+    DIRECT_STATE_ACCESS.store(is_direct_state_access_supported(), Ordering::Relaxed);
+}
+
+// ARB_direct_state_access uses the same entry point names as GL 4.5, so the `gl` crate has already loaded them either way.
+fn is_direct_state_access_supported() -> bool {
+    let supported = gl_capabilities::get().has_direct_state_access();
+    return supported && gl::CreateBuffers::is_loaded() && gl::CreateTextures::is_loaded() && gl::CreateVertexArrays::is_loaded();
+}
+
+pub fn has_direct_state_access() -> bool {
+    DIRECT_STATE_ACCESS.load(Ordering::Relaxed)
+}
+
+// Allows forcing the bind-to-edit fallback, e.g. to compare both paths. Enabling it again only works where it is supported.
+pub fn set_direct_state_access_enabled(enabled: bool) {
+    DIRECT_STATE_ACCESS.store(enabled && is_direct_state_access_supported(), Ordering::Relaxed);
+}
+
+pub fn is_specialize_shader_loaded() -> bool {
+    !SPECIALIZE_SHADER.load(Ordering::Relaxed).is_null()
+}
+
+pub unsafe fn SpecializeShader(
+    shader: GLuint,
+    entry_point: *const GLchar,
+    num_specialization_constants: GLuint,
+    constant_index: *const GLuint,
+    constant_value: *const GLuint,
+) {
+    let pointer = SPECIALIZE_SHADER.load(Ordering::Relaxed);
+    assert!(!pointer.is_null(), "glSpecializeShader is not loaded!");
+    let function: SpecializeShaderFn = std::mem::transmute(pointer);
+    function(shader, entry_point, num_specialization_constants, constant_index, constant_value);
+}
@@ -0,0 +1,324 @@
+use gl::types::*;
+
+use std::backtrace::{ Backtrace };
+use std::collections::{ HashMap, VecDeque };
+use std::sync::{ Mutex, OnceLock };
+
+// Debug builds record every GL object the wrappers create, so objects that are never dropped, and names that are
+// still used after their wrapper deleted them, can be traced back to where they came from. Release builds skip all of it.
+static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+
+// Enough to catch stale names that are still around, without keeping every sync object ever created.
+const MAX_DELETED_OBJECTS: usize = 4096;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ObjectKind {
+    Buffer,
+    Texture,
+    VertexArray,
+    Framebuffer,
+    Renderbuffer,
+    Program,
+    ProgramPipeline,
+    Query,
+    Sync,
+}
+
+impl ObjectKind {
+    // The namespaces `glObjectLabel` takes.
+    pub fn from_gl_identifier(identifier: GLenum) -> Option<ObjectKind> {
+        match identifier {
+            gl::BUFFER => Some(ObjectKind::Buffer),
+            gl::TEXTURE => Some(ObjectKind::Texture),
+            gl::VERTEX_ARRAY => Some(ObjectKind::VertexArray),
+            gl::FRAMEBUFFER => Some(ObjectKind::Framebuffer),
+            gl::RENDERBUFFER => Some(ObjectKind::Renderbuffer),
+            gl::PROGRAM => Some(ObjectKind::Program),
+            gl::PROGRAM_PIPELINE => Some(ObjectKind::ProgramPipeline),
+            gl::QUERY => Some(ObjectKind::Query),
+            _ => None,
+        }
+    }
+
+    // Container objects only exist in the context that created them, everything else is shared by its share group.
+    pub fn is_shared(self) -> bool {
+        !matches!(self, ObjectKind::VertexArray | ObjectKind::Framebuffer | ObjectKind::ProgramPipeline | ObjectKind::Query)
+    }
+
+    pub fn get_name(self) -> &'static str {
+        match self {
+            ObjectKind::Buffer => "Buffer",
+            ObjectKind::Texture => "Texture",
+            ObjectKind::VertexArray => "Vertex Array",
+            ObjectKind::Framebuffer => "Framebuffer",
+            ObjectKind::Renderbuffer => "Renderbuffer",
+            ObjectKind::Program => "Program",
+            ObjectKind::ProgramPipeline => "Program Pipeline",
+            ObjectKind::Query => "Query",
+            ObjectKind::Sync => "Sync",
+        }
+    }
+}
+
+// Sync objects are pointers rather than names, so every object is keyed by its kind and its name or address. Names of
+// unshared kinds can be live in several contexts at once, so their keys also hold the id of the context; shared
+// kinds use 0.
+pub type ObjectKey = (ObjectKind, usize, u64);
+
+pub fn get_key(kind: ObjectKind, name: usize, context_id: u64) -> ObjectKey {
+    (kind, name, if kind.is_shared() { 0 } else { context_id })
+}
+
+struct TrackedObject {
+    label: Option<String>,
+    created_at: Backtrace,
+}
+
+struct DeletedObject {
+    // Tells a record apart from an older one of the same key in `deletion_order`.
+    sequence: u64,
+    label: Option<String>,
+    created_at: Backtrace,
+    deleted_at: Backtrace,
+}
+
+#[derive(Debug)]
+pub enum Misuse {
+    UseAfterDelete { key: ObjectKey, label: Option<String>, operation: String },
+    DoubleDelete { key: ObjectKey, label: Option<String> },
+}
+
+impl std::fmt::Display for Misuse {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Misuse::UseAfterDelete { key, label, operation } => {
+                write!(f, "{} {} was used for {} after it was deleted", format_object(*key, label), key.1, operation)
+            }
+            Misuse::DoubleDelete { key, label } => write!(f, "{} {} was deleted twice", format_object(*key, label), key.1),
+        }
+    }
+}
+
+fn format_object(key: ObjectKey, label: &Option<String>) -> String {
+    match label {
+        Some(label) => format!("{} '{}'", key.0.get_name(), label),
+        None => key.0.get_name().to_string(),
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    live: HashMap<ObjectKey, TrackedObject>,
+    // Deleted names are kept until GL hands them out again, so stale copies of them can be recognised.
+    deleted: HashMap<ObjectKey, DeletedObject>,
+    // Oldest first, so the oldest records go once there are more than `MAX_DELETED_OBJECTS`.
+    deletion_order: VecDeque<(ObjectKey, u64)>,
+    next_sequence: u64,
+}
+
+impl Registry {
+    // Registering a live object again keeps its original record, since that only happens when it moves to another
+    // context of the share group.
+    fn register(&mut self, key: ObjectKey) {
+        self.deleted.remove(&key);
+        self.live.entry(key).or_insert_with(|| TrackedObject {
+            label: None,
+            created_at: Backtrace::force_capture(),
+        });
+    }
+
+    fn set_label(&mut self, key: ObjectKey, label: &str) {
+        if let Some(object) = self.live.get_mut(&key) {
+            object.label = Some(label.to_string());
+        }
+    }
+
+    fn delete(&mut self, key: ObjectKey) -> Result<(), Misuse> {
+        if let Some(object) = self.live.remove(&key) {
+            let sequence = self.next_sequence;
+            self.next_sequence += 1;
+            self.deleted.insert(key, DeletedObject {
+                sequence,
+                label: object.label,
+                created_at: object.created_at,
+                deleted_at: Backtrace::force_capture(),
+            });
+            self.deletion_order.push_back((key, sequence));
+            self.prune_deleted();
+            return Ok(());
+        }
+
+        return match self.deleted.get(&key) {
+            Some(object) => Err(Misuse::DoubleDelete { key, label: object.label.clone() }),
+            // Created outside the wrappers, so there is nothing to check against.
+            None => Ok(()),
+        };
+    }
+
+    fn prune_deleted(&mut self) {
+        while self.deletion_order.len() > MAX_DELETED_OBJECTS {
+            let (key, sequence) = self.deletion_order.pop_front().unwrap();
+            // The name may have been reused and deleted again since, in which case the newer record stays.
+            if self.deleted.get(&key).is_some_and(|object| object.sequence == sequence) {
+                self.deleted.remove(&key);
+            }
+        }
+    }
+
+    fn check_use(&self, key: ObjectKey, operation: &str) -> Result<(), Misuse> {
+        match self.deleted.get(&key) {
+            Some(object) => Err(Misuse::UseAfterDelete { key, label: object.label.clone(), operation: operation.to_string() }),
+            None => Ok(()),
+        }
+    }
+}
+
+fn with_registry<R, F: FnOnce(&mut Registry) -> R>(f: F) -> R {
+    let registry = REGISTRY.get_or_init(|| Mutex::new(Registry::default()));
+    // A panic while the lock was held leaves the maps consistent, so the poison can be ignored.
+    let mut registry = registry.lock().unwrap_or_else(|error| error.into_inner());
+    return f(&mut registry);
+}
+
+fn log_misuse(misuse: Misuse) {
+    let details = with_registry(|registry| {
+        let key = match &misuse {
+            Misuse::UseAfterDelete { key, .. } | Misuse::DoubleDelete { key, .. } => *key,
+        };
+        registry.deleted.get(&key).map(|object| format!("Created at:\n{}\nDeleted at:\n{}", object.created_at, object.deleted_at))
+    });
+    log::error!(target: "gl", "{}\n{}", misuse, details.unwrap_or_default());
+}
+
+pub fn is_enabled() -> bool {
+    cfg!(debug_assertions)
+}
+
+pub fn register(key: ObjectKey) {
+    if is_enabled() {
+        with_registry(|registry| registry.register(key));
+    }
+}
+
+pub fn set_label(key: ObjectKey, label: &str) {
+    if is_enabled() {
+        with_registry(|registry| registry.set_label(key, label));
+    }
+}
+
+pub fn unregister(key: ObjectKey) {
+    if !is_enabled() {
+        return;
+    }
+
+    if let Err(misuse) = with_registry(|registry| registry.delete(key)) {
+        log_misuse(misuse);
+    }
+}
+
+// `operation` describes the use in the report, e.g. "binding".
+pub fn check_use(key: ObjectKey, operation: &str) {
+    if !is_enabled() || key.1 == 0 {
+        return;
+    }
+
+    if let Err(misuse) = with_registry(|registry| registry.check_use(key, operation)) {
+        log_misuse(misuse);
+    }
+}
+
+pub fn get_live_object_count() -> usize {
+    with_registry(|registry| registry.live.len())
+}
+
+// Logs every object that is still alive along with where it was created, and returns how many there were.
+// Meant for shutdown, once everything should have been dropped.
+pub fn report_leaks() -> usize {
+    if !is_enabled() {
+        return 0;
+    }
+
+    return with_registry(|registry| {
+        if registry.live.is_empty() {
+            return 0;
+        }
+
+        log::warn!(target: "gl", "{} GL objects were never dropped", registry.live.len());
+        let mut leaks: Vec<_> = registry.live.iter().collect();
+        leaks.sort_by_key(|(key, _)| (key.0.get_name(), key.1));
+        for (key, object) in leaks {
+            log::warn!(target: "gl", "{} {} created at:\n{}", format_object(*key, &object.label), key.1, object.created_at);
+        }
+        return registry.live.len();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deleted_names_are_reported_until_they_are_reused() {
+        let mut registry = Registry::default();
+        let key = get_key(ObjectKind::Texture, 7, 1);
+        registry.register(key);
+        registry.set_label(key, "Albedo");
+        assert!(registry.check_use(key, "binding").is_ok());
+
+        assert!(registry.delete(key).is_ok());
+        assert!(matches!(registry.check_use(key, "binding"), Err(Misuse::UseAfterDelete { label: Some(_), .. })));
+        assert!(matches!(registry.delete(key), Err(Misuse::DoubleDelete { .. })));
+
+        registry.register(key);
+        assert!(registry.check_use(key, "binding").is_ok());
+        assert!(registry.live[&key].label.is_none());
+    }
+
+    #[test]
+    fn registering_a_live_object_again_keeps_it_once() {
+        let mut registry = Registry::default();
+        registry.register((ObjectKind::Buffer, 3, 0));
+        registry.set_label((ObjectKind::Buffer, 3, 0), "Vertices");
+        registry.register((ObjectKind::Buffer, 3, 0));
+        registry.register((ObjectKind::Program, 3, 0));
+
+        assert_eq!(registry.live.len(), 2);
+        assert_eq!(registry.live[&(ObjectKind::Buffer, 3, 0)].label.as_deref(), Some("Vertices"));
+        assert!(registry.delete((ObjectKind::Query, 1, 1)).is_ok());
+    }
+
+    #[test]
+    fn unshared_names_are_tracked_per_context() {
+        let mut registry = Registry::default();
+        registry.register(get_key(ObjectKind::VertexArray, 1, 1));
+        registry.register(get_key(ObjectKind::VertexArray, 1, 2));
+        registry.register(get_key(ObjectKind::Buffer, 1, 1));
+        assert_eq!(get_key(ObjectKind::Buffer, 1, 2), get_key(ObjectKind::Buffer, 1, 1));
+
+        assert!(registry.delete(get_key(ObjectKind::VertexArray, 1, 1)).is_ok());
+        assert!(registry.check_use(get_key(ObjectKind::VertexArray, 1, 2), "binding").is_ok());
+        assert!(registry.check_use(get_key(ObjectKind::VertexArray, 1, 1), "binding").is_err());
+        assert_eq!(registry.live.len(), 2);
+    }
+
+    #[test]
+    fn deleted_records_are_capped() {
+        let mut registry = Registry::default();
+        for address in 0..MAX_DELETED_OBJECTS + 10 {
+            registry.register((ObjectKind::Sync, address, 0));
+            assert!(registry.delete((ObjectKind::Sync, address, 0)).is_ok());
+        }
+        // Reusing and deleting a name again keeps its record alive past its first deletion.
+        registry.register((ObjectKind::Sync, 10, 0));
+        assert!(registry.delete((ObjectKind::Sync, 10, 0)).is_ok());
+        for address in 0..10 {
+            registry.register((ObjectKind::Texture, address, 0));
+            assert!(registry.delete((ObjectKind::Texture, address, 0)).is_ok());
+        }
+
+        assert_eq!(registry.deleted.len(), MAX_DELETED_OBJECTS);
+        assert!(registry.check_use((ObjectKind::Sync, 0, 0), "waiting").is_ok());
+        assert!(registry.check_use((ObjectKind::Sync, 10, 0), "waiting").is_err());
+        assert!(registry.check_use((ObjectKind::Sync, MAX_DELETED_OBJECTS + 9, 0), "waiting").is_err());
+    }
+}
@@ -0,0 +1,254 @@
+use crate::gl_context::{ GLObject };
+use crate::gl_object_tracker;
+
+use gl::types::*;
+
+use std::cell::{ RefCell };
+
+// The texture units a shader can see at once in GL 3.3 are guaranteed to be at least 16; higher units are still
+// bound correctly, they just aren't cached.
+const TRACKED_TEXTURE_UNITS: usize = 32;
+
+const TRACKED_BUFFER_TARGETS: [GLenum; 8] = [
+    gl::ARRAY_BUFFER,
+    gl::ELEMENT_ARRAY_BUFFER,
+    gl::COPY_READ_BUFFER,
+    gl::COPY_WRITE_BUFFER,
+    gl::PIXEL_PACK_BUFFER,
+    gl::PIXEL_UNPACK_BUFFER,
+    gl::UNIFORM_BUFFER,
+    gl::TEXTURE_BUFFER,
+];
+
+const TRACKED_TEXTURE_TARGETS: [GLenum; 7] = [
+    gl::TEXTURE_2D,
+    gl::TEXTURE_3D,
+    gl::TEXTURE_2D_ARRAY,
+    gl::TEXTURE_CUBE_MAP,
+    gl::TEXTURE_CUBE_MAP_ARRAY,
+    gl::TEXTURE_2D_MULTISAMPLE,
+    gl::TEXTURE_BUFFER,
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct BindCounter {
+    pub issued: u64,
+    pub skipped: u64,
+}
+
+impl BindCounter {
+    fn record(&mut self, skipped: bool) {
+        if skipped {
+            self.skipped += 1;
+        } else {
+            self.issued += 1;
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct BindStatistics {
+    pub programs: BindCounter,
+    pub vertex_arrays: BindCounter,
+    pub buffers: BindCounter,
+    pub textures: BindCounter,
+    pub active_texture_units: BindCounter,
+}
+
+impl BindStatistics {
+    pub fn get_total(&self) -> BindCounter {
+        let counters = [self.programs, self.vertex_arrays, self.buffers, self.textures, self.active_texture_units];
+        BindCounter {
+            issued: counters.iter().map(|counter| counter.issued).sum(),
+            skipped: counters.iter().map(|counter| counter.skipped).sum(),
+        }
+    }
+}
+
+// `None` means the binding is unknown, e.g. after `invalidate`, so the next bind is always issued.
+struct StateTracker {
+    program: Option<GLuint>,
+    vertex_array: Option<GLuint>,
+    buffers: [Option<GLuint>; TRACKED_BUFFER_TARGETS.len()],
+    active_texture_unit: Option<u32>,
+    textures: Vec<[Option<GLuint>; TRACKED_TEXTURE_TARGETS.len()]>,
+    statistics: BindStatistics,
+}
+
+impl StateTracker {
+    fn new() -> StateTracker {
+        StateTracker {
+            program: None,
+            vertex_array: None,
+            buffers: [None; TRACKED_BUFFER_TARGETS.len()],
+            active_texture_unit: None,
+            textures: vec![[None; TRACKED_TEXTURE_TARGETS.len()]; TRACKED_TEXTURE_UNITS],
+            statistics: BindStatistics::default(),
+        }
+    }
+
+    fn invalidate(&mut self) {
+        let statistics = self.statistics;
+        *self = StateTracker::new();
+        self.statistics = statistics;
+    }
+
+    fn set_active_texture_unit(&mut self, unit: u32) {
+        let skipped = self.active_texture_unit == Some(unit);
+        self.statistics.active_texture_units.record(skipped);
+        if !skipped {
+            unsafe {
+                gl::ActiveTexture(gl::TEXTURE0 + unit);
+            }
+            self.active_texture_unit = Some(unit);
+        }
+    }
+
+    fn bind_texture(&mut self, target: GLenum, texture: GLuint) {
+        let slot = match (self.active_texture_unit, get_texture_target_index(target)) {
+            (Some(unit), Some(target_index)) if (unit as usize) < TRACKED_TEXTURE_UNITS => Some(&mut self.textures[unit as usize][target_index]),
+            _ => None,
+        };
+
+        let skipped = slot.as_ref().is_some_and(|binding| **binding == Some(texture));
+        self.statistics.textures.record(skipped);
+        if !skipped {
+            unsafe {
+                gl::BindTexture(target, texture);
+            }
+            if let Some(binding) = slot {
+                *binding = Some(texture);
+            }
+        }
+    }
+}
+
+thread_local! {
+    // GL contexts are current on one thread at a time, so each thread tracks the context it has current.
+    static STATE_TRACKER: RefCell<StateTracker> = RefCell::new(StateTracker::new());
+}
+
+fn with_tracker<R>(function: impl FnOnce(&mut StateTracker) -> R) -> R {
+    STATE_TRACKER.with(|tracker| function(&mut tracker.borrow_mut()))
+}
+
+fn get_buffer_target_index(target: GLenum) -> Option<usize> {
+    TRACKED_BUFFER_TARGETS.iter().position(|tracked_target| *tracked_target == target)
+}
+
+fn get_texture_target_index(target: GLenum) -> Option<usize> {
+    TRACKED_TEXTURE_TARGETS.iter().position(|tracked_target| *tracked_target == target)
+}
+
+pub fn use_program(program: GLuint) {
+    gl_object_tracker::check_use(GLObject::Program(program).get_current_key(), "binding");
+    with_tracker(|tracker| {
+        let skipped = tracker.program == Some(program);
+        tracker.statistics.programs.record(skipped);
+        if !skipped {
+            unsafe {
+                gl::UseProgram(program);
+            }
+            tracker.program = Some(program);
+        }
+    });
+}
+
+// Binding a different vertex array also swaps the element array buffer, since that binding lives in the vertex array.
+pub fn bind_vertex_array(vertex_array: GLuint) {
+    gl_object_tracker::check_use(GLObject::VertexArray(vertex_array).get_current_key(), "binding");
+    with_tracker(|tracker| {
+        let skipped = tracker.vertex_array == Some(vertex_array);
+        tracker.statistics.vertex_arrays.record(skipped);
+        if !skipped {
+            unsafe {
+                gl::BindVertexArray(vertex_array);
+            }
+            tracker.vertex_array = Some(vertex_array);
+            tracker.buffers[get_buffer_target_index(gl::ELEMENT_ARRAY_BUFFER).unwrap()] = None;
+        }
+    });
+}
+
+pub fn bind_buffer(target: GLenum, buffer: GLuint) {
+    gl_object_tracker::check_use(GLObject::Buffer(buffer).get_current_key(), "binding");
+    with_tracker(|tracker| {
+        let target_index = get_buffer_target_index(target);
+        let skipped = target_index.is_some_and(|index| tracker.buffers[index] == Some(buffer));
+        tracker.statistics.buffers.record(skipped);
+        if !skipped {
+            unsafe {
+                gl::BindBuffer(target, buffer);
+            }
+            if let Some(index) = target_index {
+                tracker.buffers[index] = Some(buffer);
+            }
+        }
+    });
+}
+
+// Binds to whichever texture unit is currently active, for uploads and parameter changes.
+pub fn bind_texture(target: GLenum, texture: GLuint) {
+    gl_object_tracker::check_use(GLObject::Texture(texture).get_current_key(), "binding");
+    with_tracker(|tracker| tracker.bind_texture(target, texture));
+}
+
+pub fn bind_texture_unit(unit: u32, target: GLenum, texture: GLuint) {
+    gl_object_tracker::check_use(GLObject::Texture(texture).get_current_key(), "binding");
+    with_tracker(|tracker| {
+        tracker.set_active_texture_unit(unit);
+        tracker.bind_texture(target, texture);
+    });
+}
+
+// Deleting an object unbinds it from the current context, so the cached bindings have to follow.
+// Programs are the exception: one that is in use stays in use until another is bound, so the binding just becomes unknown.
+pub fn forget_program(program: GLuint) {
+    with_tracker(|tracker| {
+        if tracker.program == Some(program) {
+            tracker.program = None;
+        }
+    });
+}
+
+pub fn forget_vertex_array(vertex_array: GLuint) {
+    with_tracker(|tracker| {
+        if tracker.vertex_array == Some(vertex_array) {
+            tracker.vertex_array = Some(0);
+            tracker.buffers[get_buffer_target_index(gl::ELEMENT_ARRAY_BUFFER).unwrap()] = None;
+        }
+    });
+}
+
+pub fn forget_buffer(buffer: GLuint) {
+    with_tracker(|tracker| {
+        for binding in tracker.buffers.iter_mut() {
+            if *binding == Some(buffer) {
+                *binding = Some(0);
+            }
+        }
+    });
+}
+
+pub fn forget_texture(texture: GLuint) {
+    with_tracker(|tracker| {
+        for binding in tracker.textures.iter_mut().flatten() {
+            if *binding == Some(texture) {
+                *binding = Some(0);
+            }
+        }
+    });
+}
+
+// Call after anything binds objects without going through this module, or after making another context current.
+pub fn invalidate() {
+    with_tracker(|tracker| tracker.invalidate());
+}
+
+pub fn get_statistics() -> BindStatistics {
+    with_tracker(|tracker| tracker.statistics)
+}
+
+pub fn reset_statistics() {
+    with_tracker(|tracker| tracker.statistics = BindStatistics::default());
+}
@@ -3,20 +3,51 @@
 mod vector3;
 mod vector2;
 mod vertex;
+mod gl_capabilities;
+mod gl_extensions;
+mod gl_context;
+mod gl_object_tracker;
+mod gl_debug;
+mod gl_state;
+mod shader_diagnostics;
 mod opengl_shader;
+mod opengl_program_cache;
+mod opengl_program_pipeline;
+mod opengl_buffer;
 mod opengl_vertex_buffer;
 mod opengl_vertex_array;
 mod opengl_index_buffer;
+mod mipmap;
 mod opengl_texture;
-
+mod opengl_texture_2d_array;
+mod opengl_texture_cube;
+mod opengl_texture_3d;
+mod texture_container;
+mod opengl_container_texture;
+mod texture_atlas;
+mod opengl_multisample_texture;
+mod opengl_renderbuffer;
+mod opengl_framebuffer;
+mod frame_capture;
+mod post_process;
+mod render_state;
+mod opengl_query;
+mod opengl_fence;
+mod frame_profiler;
+mod occlusion_culling;
+mod resource_loader;
+
+use crate::gl_context::{ GLContext };
 use crate::vector2::{ Vector2 };
 use crate::vector3::{ Vector3 };
 use crate::vertex::{ Vertex };
-use crate::opengl_shader::{ OpenGLShader };
+use crate::opengl_shader::{ OpenGLShader, ShaderSource };
 use crate::opengl_vertex_buffer::{ OpenGLVertexBuffer };
 use crate::opengl_vertex_array::{ OpenGLVertexArray, BufferElement };
 use crate::opengl_index_buffer::{ OpenGLIndexBuffer };
-use crate::opengl_texture::{ OpenGLTexture };
+use crate::opengl_texture::{ OpenGLTexture, Texture, TextureDescriptor, TextureFormat };
+use crate::render_state::{ BlendState, DepthState, RenderState, RenderStateCache };
+use crate::resource_loader::{ ResourceLoader };
 
 extern crate glfw;
 extern crate gl;
@@ -25,16 +56,37 @@ extern crate image;
 
 use glfw::{ Context, Key, Action };
 use gl::types::*;
-use image::GenericImageView;
 
 use std::sync::mpsc::{ Receiver };
 
+const MSAA_SAMPLES: u32 = 4;
+
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        eprintln!("[{} {}] {}", record.level(), record.target(), record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+static STDERR_LOGGER: StderrLogger = StderrLogger;
+
 fn main() {
     let mut glfw = glfw::init(glfw::FAIL_ON_ERRORS)
         .expect("Failed to initialize GLFW!");
 
-    glfw.window_hint(glfw::WindowHint::ContextVersion(4, 4));
+    // Drivers hand out their newest core context for this, and the wrappers pick their code paths from what they get.
+    glfw.window_hint(glfw::WindowHint::ContextVersion(gl_capabilities::MINIMUM_GL_VERSION.0, gl_capabilities::MINIMUM_GL_VERSION.1));
     glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
+    glfw.window_hint(glfw::WindowHint::OpenGlForwardCompat(true));
+    glfw.window_hint(glfw::WindowHint::OpenGlDebugContext(cfg!(debug_assertions)));
+    glfw.window_hint(glfw::WindowHint::Samples(Some(MSAA_SAMPLES)));
 
     let (mut window, events) = glfw.create_window(1280, 720, "Rust OpenGL Window", glfw::WindowMode::Windowed)
         .expect("Failed to create GLFW window!");
@@ -46,15 +98,42 @@ fn main() {
     glfw.set_swap_interval(glfw::SwapInterval::Sync(1));
 
     gl::load_with(|symbol| window.get_proc_address(symbol) as *const _);
+    gl_extensions::load_with(|symbol| window.get_proc_address(symbol) as *const _);
+
+    // Declared after the window so it is dropped first, while the context still exists. Anything dropped after it
+    // is simply forgotten, since destroying the window takes its objects along.
+    let gl_context = GLContext::new(&window);
+
+    // The loader's context lives in a hidden window that shares its objects with the main one.
+    glfw.window_hint(glfw::WindowHint::Visible(false));
+    let (mut loader_window, _) = window.create_shared(1, 1, "Resource Loader", glfw::WindowMode::Windowed)
+        .expect("Failed to create the resource loader window!");
+    let resource_loader = ResourceLoader::new(loader_window.render_context());
+
+    let capabilities = gl_capabilities::get();
+    println!("{} ({}, {})", capabilities.get_version_string(), capabilities.get_vendor(), capabilities.get_renderer());
+    assert!(capabilities.meets_minimum_profile(), "At least GL 3.3 core or GLES 3.0 is required!");
+
+    if cfg!(debug_assertions) {
+        log::set_logger(&STDERR_LOGGER).expect("Failed to install the logger!");
+        log::set_max_level(log::LevelFilter::Debug);
+        gl_debug::enable(true);
+    }
 
     unsafe {
-        gl::Enable(gl::DEPTH_TEST);
-
-        gl::Enable(gl::BLEND);
-        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        gl::Enable(gl::MULTISAMPLE);
     }
 
-    let shader = OpenGLShader::new(include_str!("../texture.vert.glsl"), include_str!("../texture.frag.glsl"));
+    let mut render_state_cache = RenderStateCache::new();
+    let render_state = RenderState::default()
+        .with_depth(DepthState { test_enabled: true, ..DepthState::default() })
+        .with_blend(BlendState::alpha_blending());
+
+    let shader = OpenGLShader::try_new(
+        &ShaderSource::new("texture.vert.glsl", include_str!("../texture.vert.glsl")),
+        &ShaderSource::new("texture.frag.glsl", include_str!("../texture.frag.glsl")),
+    ).unwrap_or_else(|error| panic!("{}", error));
+    shader.set_debug_label("Texture Shader");
 
     let mut vertex_array = OpenGLVertexArray::new();
 
@@ -64,38 +143,57 @@ fn main() {
         Vertex::new(Vector3::new( 0.5, -0.5, 0.0), Vector2::new(1.0, 0.0)),
         Vertex::new(Vector3::new(-0.5, -0.5, 0.0), Vector2::new(0.0, 0.0)),
     ];
+    vertex_array.set_debug_label("Quad");
     let _vertex_buffer = vertex_array.add_vertex_buffer(OpenGLVertexBuffer::new(&vertices), &[BufferElement::Float3, BufferElement::Float2]);
 
     let indices = [
         0, 1, 2,
         0, 2, 3,
     ];
-    let index_buffer = OpenGLIndexBuffer::new(&indices);
+    let mut index_buffer = OpenGLIndexBuffer::new(&indices);
+    index_buffer.set_debug_label("Quad Indices");
 
     let cat_image = image::load_from_memory(include_bytes!("../cat.jpg"))
         .expect("Failed to read image!");
-    let texture = OpenGLTexture::new(&cat_image.flipv().to_rgba8().into_raw(), cat_image.width(), cat_image.height());
+    let texture_descriptor = TextureDescriptor::new(TextureFormat::from_dynamic_image(&cat_image, false));
+    let mut texture_upload = Some(resource_loader.load_texture(cat_image.flipv(), texture_descriptor));
+    let mut texture: Option<OpenGLTexture> = None;
 
     while !window.should_close() {
         process_window_events(&mut window, &events);
+        let frame_debug_group = gl_debug::push_debug_group("Frame");
+
+        render_state_cache.apply(&render_state);
 
         unsafe {
             gl::ClearColor(0.1, 0.1, 0.1, 1.0);
             gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
         }
 
-        shader.bind();
+        if texture_upload.as_mut().is_some_and(|upload| upload.is_ready()) {
+            let uploaded_texture = texture_upload.take().unwrap().wait().unwrap_or_else(|error| panic!("{}", error));
+            uploaded_texture.set_debug_label("cat.jpg");
+            texture = Some(uploaded_texture);
+        }
 
-        shader.set_integer("u_Texture", 0);
-        texture.bind(0);
+        // Nothing is drawn until the loader has finished the texture.
+        if let Some(texture) = &texture {
+            shader.bind();
 
-        vertex_array.bind();
-        index_buffer.bind();
-        unsafe {
-            gl::DrawElements(gl::TRIANGLES, indices.len() as GLsizei, gl::UNSIGNED_INT, std::ptr::null());
+            shader.set_integer("u_Texture", 0);
+            texture.bind(0);
+
+            vertex_array.bind();
+            index_buffer.bind();
+            unsafe {
+                gl::DrawElements(gl::TRIANGLES, indices.len() as GLsizei, gl::UNSIGNED_INT, std::ptr::null());
+            }
+            gl_debug::check_errors("Drawing the textured quad");
         }
 
+        frame_debug_group.pop_debug_group();
         window.swap_buffers();
+        gl_context.flush_destruction_queue();
         glfw.poll_events();
     }
 }
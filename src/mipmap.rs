@@ -0,0 +1,214 @@
+use image::{ RgbaImage };
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MipmapFilter {
+    Box,
+    Kaiser,
+}
+
+impl MipmapFilter {
+    fn get_radius(self) -> f32 {
+        match self {
+            MipmapFilter::Box => 1.0,
+            MipmapFilter::Kaiser => 3.0,
+        }
+    }
+
+    // `distance` is measured in source texels from the centre of the destination texel.
+    fn get_weight(self, distance: f32) -> f32 {
+        match self {
+            MipmapFilter::Box => if distance.abs() < 1.0 { 1.0 } else { 0.0 },
+            MipmapFilter::Kaiser => {
+                const ALPHA: f32 = 4.0;
+                let radius = self.get_radius();
+                if distance.abs() >= radius {
+                    return 0.0;
+                }
+                let window_position = distance / radius;
+                let window = bessel_i0(ALPHA * (1.0 - window_position * window_position).sqrt()) / bessel_i0(ALPHA);
+                return sinc(distance * 0.5) * window;
+            }
+        }
+    }
+}
+
+pub fn get_mip_level_count(width: u32, height: u32) -> u32 {
+    32 - std::cmp::max(std::cmp::max(width, height), 1).leading_zeros()
+}
+
+pub fn build_mip_chain(image: &RgbaImage, filter: MipmapFilter, gamma_correct: bool) -> Vec<RgbaImage> {
+    let level_count = get_mip_level_count(image.width(), image.height());
+
+    let mut levels = Vec::with_capacity(level_count as usize);
+    levels.push(image.clone());
+
+    let mut width = image.width() as usize;
+    let mut height = image.height() as usize;
+    let mut linear = to_linear(image, gamma_correct);
+    for _ in 1..level_count {
+        let new_width = std::cmp::max(width / 2, 1);
+        let new_height = std::cmp::max(height / 2, 1);
+
+        let horizontal = downsample(&linear, width, height, new_width, filter, true);
+        linear = downsample(&horizontal, new_width, height, new_height, filter, false);
+
+        width = new_width;
+        height = new_height;
+        levels.push(from_linear(&linear, width as u32, height as u32, gamma_correct));
+    }
+
+    return levels;
+}
+
+fn downsample(pixels: &[[f32; 4]], width: usize, height: usize, new_size: usize, filter: MipmapFilter, horizontal: bool) -> Vec<[f32; 4]> {
+    let (old_size, other_size) = if horizontal { (width, height) } else { (height, width) };
+    if new_size == old_size {
+        return pixels.to_vec();
+    }
+
+    let scale = old_size as f32 / new_size as f32;
+    let radius = filter.get_radius() * scale * 0.5;
+
+    let mut kernels = Vec::with_capacity(new_size);
+    for destination in 0..new_size {
+        let center = (destination as f32 + 0.5) * scale;
+        let first = (center - radius).floor() as isize;
+        let last = (center + radius).ceil() as isize;
+
+        let mut taps = Vec::new();
+        let mut total_weight = 0.0;
+        for source in first..=last {
+            let distance = (source as f32 + 0.5 - center) * 2.0 / scale;
+            let weight = filter.get_weight(distance);
+            if weight != 0.0 {
+                taps.push((source.clamp(0, old_size as isize - 1) as usize, weight));
+                total_weight += weight;
+            }
+        }
+        for tap in taps.iter_mut() {
+            tap.1 /= total_weight;
+        }
+        kernels.push(taps);
+    }
+
+    let (new_width, new_height) = if horizontal { (new_size, height) } else { (width, new_size) };
+    let mut output = vec![[0.0; 4]; new_width * new_height];
+    for other in 0..other_size {
+        for (destination, taps) in kernels.iter().enumerate() {
+            let mut sum = [0.0; 4];
+            for (source, weight) in taps {
+                let pixel = if horizontal { pixels[other * width + source] } else { pixels[source * width + other] };
+                for channel in 0..4 {
+                    sum[channel] += pixel[channel] * weight;
+                }
+            }
+            let index = if horizontal { other * new_width + destination } else { destination * new_width + other };
+            output[index] = sum;
+        }
+    }
+    return output;
+}
+
+fn to_linear(image: &RgbaImage, gamma_correct: bool) -> Vec<[f32; 4]> {
+    image.pixels()
+        .map(|pixel| {
+            let convert = |value: u8| {
+                let value = value as f32 / 255.0;
+                if gamma_correct { srgb_to_linear(value) } else { value }
+            };
+            [convert(pixel[0]), convert(pixel[1]), convert(pixel[2]), pixel[3] as f32 / 255.0]
+        })
+        .collect()
+}
+
+fn from_linear(pixels: &[[f32; 4]], width: u32, height: u32, gamma_correct: bool) -> RgbaImage {
+    let convert = |value: f32, is_color: bool| {
+        let value = if gamma_correct && is_color { linear_to_srgb(value) } else { value };
+        (value.clamp(0.0, 1.0) * 255.0).round() as u8
+    };
+
+    let mut bytes = Vec::with_capacity(pixels.len() * 4);
+    for pixel in pixels {
+        bytes.push(convert(pixel[0], true));
+        bytes.push(convert(pixel[1], true));
+        bytes.push(convert(pixel[2], true));
+        bytes.push(convert(pixel[3], false));
+    }
+    return RgbaImage::from_raw(width, height, bytes).unwrap();
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-6 {
+        return 1.0;
+    }
+    let x = x * std::f32::consts::PI;
+    return x.sin() / x;
+}
+
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half_x_squared = x * x * 0.25;
+    for k in 1..32 {
+        term *= half_x_squared / (k * k) as f32;
+        sum += term;
+        if term < sum * 1e-8 {
+            break;
+        }
+    }
+    return sum;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_sizes(levels: &[RgbaImage]) -> Vec<(u32, u32)> {
+        levels.iter().map(|level| level.dimensions()).collect()
+    }
+
+    #[test]
+    fn level_sizes_round_down_to_one() {
+        assert_eq!(get_mip_level_count(5, 3), 3);
+        assert_eq!(get_mip_level_count(8, 2), 4);
+        assert_eq!(get_mip_level_count(0, 0), 1);
+
+        let odd = build_mip_chain(&RgbaImage::new(5, 3), MipmapFilter::Box, false);
+        assert_eq!(get_sizes(&odd), vec![(5, 3), (2, 1), (1, 1)]);
+
+        let wide = build_mip_chain(&RgbaImage::new(8, 2), MipmapFilter::Kaiser, true);
+        assert_eq!(get_sizes(&wide), vec![(8, 2), (4, 1), (2, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn box_filter_averages_in_the_chosen_space() {
+        let image = RgbaImage::from_raw(2, 1, vec![0, 0, 0, 255, 255, 255, 255, 255]).unwrap();
+
+        let levels = build_mip_chain(&image, MipmapFilter::Box, false);
+        assert_eq!(levels[1].get_pixel(0, 0).0, [128, 128, 128, 255]);
+
+        // Averaging black and white in linear space gives 50% light, which is much brighter than sRGB 128.
+        let levels = build_mip_chain(&image, MipmapFilter::Box, true);
+        let pixel = levels[1].get_pixel(0, 0).0;
+        assert!((187..=188).contains(&pixel[0]), "{:?}", pixel);
+        assert_eq!(pixel[0], pixel[1]);
+        assert_eq!(pixel[0], pixel[2]);
+        assert_eq!(pixel[3], 255);
+    }
+}
@@ -0,0 +1,401 @@
+use crate::gl_capabilities;
+use crate::gl_debug;
+use crate::opengl_index_buffer::{ OpenGLIndexBuffer };
+use crate::opengl_query::{ ConditionalRenderMode, OpenGLQuery, QueryType };
+use crate::opengl_shader::{ OpenGLShader, ShaderSource };
+use crate::opengl_vertex_array::{ BufferElement, OpenGLVertexArray };
+use crate::opengl_vertex_buffer::{ OpenGLVertexBuffer };
+use crate::render_state::{ BlendState, CompareFunction, CullMode, DepthState, RasterizerState, RenderState, RenderStateCache };
+use crate::vector3::{ Vector3 };
+
+use gl::types::*;
+
+const UNIT_CUBE_VERTICES: [[f32; 3]; 8] = [
+    [0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0],
+    [0.0, 0.0, 1.0], [1.0, 0.0, 1.0], [1.0, 1.0, 1.0], [0.0, 1.0, 1.0],
+];
+
+const UNIT_CUBE_INDICES: [u32; 36] = [
+    0, 2, 1, 0, 3, 2,
+    4, 5, 6, 4, 6, 7,
+    0, 1, 5, 0, 5, 4,
+    3, 6, 2, 3, 7, 6,
+    0, 4, 7, 0, 7, 3,
+    1, 2, 6, 1, 6, 5,
+];
+
+#[derive(Clone, Copy)]
+pub struct BoundingBox {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl BoundingBox {
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> BoundingBox {
+        BoundingBox { min, max }
+    }
+
+    pub fn from_points(points: &[Vector3<f32>]) -> BoundingBox {
+        assert!(!points.is_empty(), "A bounding box needs at least one point!");
+        let mut bounds = BoundingBox::new(points[0], points[0]);
+        for point in &points[1..] {
+            bounds.min = Vector3::new(bounds.min.x.min(point.x), bounds.min.y.min(point.y), bounds.min.z.min(point.z));
+            bounds.max = Vector3::new(bounds.max.x.max(point.x), bounds.max.y.max(point.y), bounds.max.z.max(point.z));
+        }
+        return bounds;
+    }
+
+    pub fn expanded(&self, margin: f32) -> BoundingBox {
+        BoundingBox {
+            min: Vector3::new(self.min.x - margin, self.min.y - margin, self.min.z - margin),
+            max: Vector3::new(self.max.x + margin, self.max.y + margin, self.max.z + margin),
+        }
+    }
+
+    pub fn contains_point(&self, point: Vector3<f32>) -> bool {
+        point.x >= self.min.x && point.x <= self.max.x
+            && point.y >= self.min.y && point.y <= self.max.y
+            && point.z >= self.min.z && point.z <= self.max.z
+    }
+}
+
+// The six planes of a view-projection matrix as `ax + by + cz + d >= 0` for points inside, normals pointing inwards.
+#[derive(Clone, Copy)]
+pub struct Frustum {
+    planes: [[f32; 4]; 6],
+}
+
+impl Frustum {
+    // The matrix is column-major, like the ones passed to `set_matrix4`.
+    pub fn from_view_projection(matrix: &[f32; 16]) -> Frustum {
+        let row = |index: usize| [matrix[index], matrix[4 + index], matrix[8 + index], matrix[12 + index]];
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        let add = |a: [f32; 4], b: [f32; 4]| [a[0] + b[0], a[1] + b[1], a[2] + b[2], a[3] + b[3]];
+        let subtract = |a: [f32; 4], b: [f32; 4]| [a[0] - b[0], a[1] - b[1], a[2] - b[2], a[3] - b[3]];
+
+        return Frustum {
+            planes: [add(w, x), subtract(w, x), add(w, y), subtract(w, y), add(w, z), subtract(w, z)],
+        };
+    }
+
+    // Conservative: boxes near the frustum corners can pass without actually being inside.
+    pub fn intersects_box(&self, bounds: &BoundingBox) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane normal is the last one to leave the inside.
+            let x = if plane[0] >= 0.0 { bounds.max.x } else { bounds.min.x };
+            let y = if plane[1] >= 0.0 { bounds.max.y } else { bounds.min.y };
+            let z = if plane[2] >= 0.0 { bounds.max.z } else { bounds.min.z };
+            plane[0] * x + plane[1] * y + plane[2] * z + plane[3] >= 0.0
+        })
+    }
+}
+
+// Draws bounding boxes for occlusion queries. The boxes are depth tested against whatever is in the depth buffer,
+// but write neither colour nor depth, so only the queries see them.
+pub struct BoundingBoxRenderer {
+    shader: OpenGLShader,
+    vertex_array: OpenGLVertexArray,
+    index_buffer: OpenGLIndexBuffer,
+    render_state: RenderState,
+}
+
+impl BoundingBoxRenderer {
+    pub fn new() -> BoundingBoxRenderer {
+        let shader = OpenGLShader::try_new(
+            &ShaderSource::new("bounding_box.vert.glsl", include_str!("../bounding_box.vert.glsl")),
+            &ShaderSource::new("bounding_box.frag.glsl", include_str!("../bounding_box.frag.glsl")),
+        ).unwrap_or_else(|error| panic!("{}", error));
+        shader.set_debug_label("Bounding Box Shader");
+
+        let mut vertex_array = OpenGLVertexArray::new();
+        vertex_array.set_debug_label("Bounding Box");
+        vertex_array.add_vertex_buffer(OpenGLVertexBuffer::new(&UNIT_CUBE_VERTICES), &[BufferElement::Float3]);
+        let mut index_buffer = OpenGLIndexBuffer::new(&UNIT_CUBE_INDICES);
+        index_buffer.set_debug_label("Bounding Box Indices");
+
+        // Both faces are drawn, so a box still passes when the camera is just behind its front faces.
+        let render_state = RenderState::default()
+            .with_blend(BlendState { color_mask: [false; 4], ..BlendState::default() })
+            .with_depth(DepthState { test_enabled: true, write_enabled: false, function: CompareFunction::LessEqual })
+            .with_rasterizer(RasterizerState { cull_mode: CullMode::None, ..RasterizerState::default() });
+
+        return BoundingBoxRenderer {
+            shader,
+            vertex_array,
+            index_buffer,
+            render_state,
+        };
+    }
+
+    // Call before `draw`. The caller's own render state has to be applied through the cache again afterwards.
+    pub fn begin(&self, render_state_cache: &mut RenderStateCache, view_projection: &[f32; 16]) {
+        render_state_cache.apply(&self.render_state);
+        self.shader.set_matrix4("u_ViewProjection", view_projection);
+        self.shader.bind();
+        self.vertex_array.bind();
+        self.index_buffer.bind();
+    }
+
+    pub fn draw(&self, bounds: &BoundingBox) {
+        self.shader.set_float3("u_Min", [bounds.min.x, bounds.min.y, bounds.min.z]);
+        self.shader.set_float3("u_Max", [bounds.max.x, bounds.max.y, bounds.max.z]);
+        unsafe {
+            gl::DrawElements(gl::TRIANGLES, UNIT_CUBE_INDICES.len() as GLsizei, gl::UNSIGNED_INT, std::ptr::null());
+        }
+        gl_debug::check_errors("Drawing a bounding box");
+    }
+
+    pub fn draw_with_query(&self, bounds: &BoundingBox, query: &mut OpenGLQuery) {
+        assert!(query.get_type().is_occlusion_query(), "Bounding boxes need an occlusion query!");
+        query.begin();
+        self.draw(bounds);
+        query.end();
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ObjectId(usize);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct VisibilityStatistics {
+    pub objects: u32,
+    pub frustum_culled: u32,
+    pub occlusion_culled: u32,
+    pub visible: u32,
+    pub queries_issued: u32,
+}
+
+struct CulledObject {
+    bounds: BoundingBox,
+    query: OpenGLQuery,
+    in_frustum: bool,
+    // The latest query result; objects start out visible so nothing pops in while the first query is in flight.
+    visible: bool,
+    last_query_frame: Option<u64>,
+}
+
+// Query-based visibility with temporal coherence: objects found visible are drawn and only retested every few frames,
+// while occluded ones are tested every frame. Results are read a frame later without stalling, and objects that
+// were occluded can be drawn with conditional rendering so the GPU shows them the moment they come into view.
+//
+// Per frame: `begin_frame`, draw `get_visible_objects`, draw `get_occluded_objects` through `draw_conditionally`,
+// then `issue_queries` with the scene's depth still bound.
+pub struct VisibilitySystem {
+    objects: Vec<Option<CulledObject>>,
+    free_ids: Vec<usize>,
+    box_renderer: BoundingBoxRenderer,
+    query_type: QueryType,
+    visible_retest_interval: u64,
+    // Keeps the camera from clipping into a box it is standing right next to.
+    camera_margin: f32,
+    frame: u64,
+    view_projection: [f32; 16],
+    camera_position: Vector3<f32>,
+    statistics: VisibilityStatistics,
+}
+
+impl VisibilitySystem {
+    pub fn new(visible_retest_interval: u32, camera_margin: f32) -> VisibilitySystem {
+        let query_type = if QueryType::AnySamplesPassedConservative.is_supported() {
+            QueryType::AnySamplesPassedConservative
+        } else {
+            QueryType::AnySamplesPassed
+        };
+
+        return VisibilitySystem {
+            objects: Vec::new(),
+            free_ids: Vec::new(),
+            box_renderer: BoundingBoxRenderer::new(),
+            query_type,
+            visible_retest_interval: std::cmp::max(visible_retest_interval, 1) as u64,
+            camera_margin: camera_margin.max(0.0),
+            frame: 0,
+            view_projection: [0.0; 16],
+            camera_position: Vector3::new(0.0, 0.0, 0.0),
+            statistics: VisibilityStatistics::default(),
+        };
+    }
+
+    pub fn add_object(&mut self, bounds: BoundingBox) -> ObjectId {
+        let object = CulledObject {
+            bounds,
+            query: OpenGLQuery::new(self.query_type),
+            in_frustum: true,
+            visible: true,
+            last_query_frame: None,
+        };
+
+        if let Some(index) = self.free_ids.pop() {
+            self.objects[index] = Some(object);
+            return ObjectId(index);
+        }
+        self.objects.push(Some(object));
+        return ObjectId(self.objects.len() - 1);
+    }
+
+    pub fn remove_object(&mut self, id: ObjectId) {
+        assert!(self.objects[id.0].take().is_some(), "The object has already been removed!");
+        self.free_ids.push(id.0);
+    }
+
+    pub fn set_bounds(&mut self, id: ObjectId, bounds: BoundingBox) {
+        self.get_object_mut(id).bounds = bounds;
+    }
+
+    fn get_object(&self, id: ObjectId) -> &CulledObject {
+        self.objects[id.0].as_ref().expect("The object has been removed!")
+    }
+
+    fn get_object_mut(&mut self, id: ObjectId) -> &mut CulledObject {
+        self.objects[id.0].as_mut().expect("The object has been removed!")
+    }
+
+    // Reads whatever query results have arrived and frustum culls every object.
+    pub fn begin_frame(&mut self, view_projection: &[f32; 16], camera_position: Vector3<f32>) {
+        self.frame += 1;
+        self.view_projection = *view_projection;
+        self.camera_position = camera_position;
+
+        let frustum = Frustum::from_view_projection(view_projection);
+        let camera_margin = self.camera_margin;
+        let mut statistics = VisibilityStatistics::default();
+        for object in self.objects.iter_mut().flatten() {
+            if let Some(samples) = object.query.try_get_result() {
+                object.visible = samples > 0;
+            }
+
+            let was_in_frustum = object.in_frustum;
+            object.in_frustum = frustum.intersects_box(&object.bounds);
+            if object.in_frustum && !was_in_frustum {
+                // The last result is from another point of view, so the object is drawn until a new one says otherwise.
+                object.visible = true;
+                object.last_query_frame = None;
+            }
+
+            statistics.objects += 1;
+            if !object.in_frustum {
+                statistics.frustum_culled += 1;
+            } else if object.visible || object.bounds.expanded(camera_margin).contains_point(camera_position) {
+                statistics.visible += 1;
+            } else {
+                statistics.occlusion_culled += 1;
+            }
+        }
+        self.statistics = statistics;
+    }
+
+    pub fn is_visible(&self, id: ObjectId) -> bool {
+        let object = self.get_object(id);
+        object.in_frustum && (object.visible || self.contains_camera(object))
+    }
+
+    fn contains_camera(&self, object: &CulledObject) -> bool {
+        object.bounds.expanded(self.camera_margin).contains_point(self.camera_position)
+    }
+
+    pub fn get_visible_objects(&self) -> Vec<ObjectId> {
+        self.get_object_ids().filter(|id| self.is_visible(*id)).collect()
+    }
+
+    // Objects in the frustum that were hidden when last tested.
+    pub fn get_occluded_objects(&self) -> Vec<ObjectId> {
+        self.get_object_ids().filter(|id| self.get_object(*id).in_frustum && !self.is_visible(*id)).collect()
+    }
+
+    fn get_object_ids(&self) -> impl Iterator<Item = ObjectId> + '_ {
+        self.objects.iter().enumerate().filter(|(_, object)| object.is_some()).map(|(index, _)| ObjectId(index))
+    }
+
+    // Runs `draw` so that the GPU skips it if the object's last query saw nothing. Without conditional rendering
+    // the object is skipped on the CPU instead, and shows up a frame after its query passes.
+    pub fn draw_conditionally<F: FnOnce()>(&self, id: ObjectId, mode: ConditionalRenderMode, draw: F) {
+        let object = self.get_object(id);
+        if !object.query.is_issued() || self.is_visible(id) {
+            draw();
+            return;
+        }
+        if gl_capabilities::get().has_conditional_render() {
+            let _conditional_render = object.query.begin_conditional_render(mode);
+            draw();
+        }
+    }
+
+    // Tests occluded objects every frame and visible ones every `visible_retest_interval` frames, spread out by id
+    // so the queries don't all land on the same frame. Objects the camera is inside of are never tested.
+    pub fn issue_queries(&mut self, render_state_cache: &mut RenderStateCache) {
+        let _debug_group = gl_debug::push_debug_group("Occlusion Queries");
+        let (frame, interval, camera_margin, camera_position) = (self.frame, self.visible_retest_interval, self.camera_margin, self.camera_position);
+
+        let mut began = false;
+        for (index, object) in self.objects.iter_mut().enumerate() {
+            let object = match object {
+                Some(object) if object.in_frustum && !object.query.is_pending() => object,
+                _ => continue,
+            };
+            if object.bounds.expanded(camera_margin).contains_point(camera_position) {
+                object.visible = true;
+                continue;
+            }
+
+            let is_due = match object.last_query_frame {
+                None => true,
+                Some(_) if !object.visible => true,
+                Some(last_query_frame) => frame - last_query_frame >= interval && (frame + index as u64).is_multiple_of(interval),
+            };
+            if !is_due {
+                continue;
+            }
+
+            if !began {
+                self.box_renderer.begin(render_state_cache, &self.view_projection);
+                began = true;
+            }
+            self.box_renderer.draw_with_query(&object.bounds, &mut object.query);
+            object.last_query_frame = Some(frame);
+            self.statistics.queries_issued += 1;
+        }
+    }
+
+    pub fn get_statistics(&self) -> VisibilityStatistics {
+        self.statistics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A column-major orthographic projection of the cube from -1 to 1 on every axis, which is just the identity.
+    const IDENTITY: [f32; 16] = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0];
+
+    fn create_box(min: [f32; 3], max: [f32; 3]) -> BoundingBox {
+        BoundingBox::new(Vector3::new(min[0], min[1], min[2]), Vector3::new(max[0], max[1], max[2]))
+    }
+
+    #[test]
+    fn frustum_culls_boxes_outside_clip_space() {
+        let frustum = Frustum::from_view_projection(&IDENTITY);
+        assert!(frustum.intersects_box(&create_box([-0.5, -0.5, -0.5], [0.5, 0.5, 0.5])));
+        assert!(frustum.intersects_box(&create_box([0.5, 0.5, 0.5], [2.0, 2.0, 2.0])));
+        assert!(!frustum.intersects_box(&create_box([1.5, -0.5, -0.5], [2.0, 0.5, 0.5])));
+        assert!(!frustum.intersects_box(&create_box([-0.5, -0.5, -3.0], [0.5, 0.5, -2.0])));
+    }
+
+    #[test]
+    fn frustum_follows_translation() {
+        // Moves everything 2 units along x, so a box at x = -2 ends up in the middle of clip space.
+        let mut translation = IDENTITY;
+        translation[12] = 2.0;
+        let frustum = Frustum::from_view_projection(&translation);
+        assert!(frustum.intersects_box(&create_box([-2.5, -0.5, -0.5], [-1.5, 0.5, 0.5])));
+        assert!(!frustum.intersects_box(&create_box([-0.5, -0.5, -0.5], [0.5, 0.5, 0.5])));
+    }
+
+    #[test]
+    fn bounding_box_from_points() {
+        let bounds = BoundingBox::from_points(&[Vector3::new(1.0, -2.0, 3.0), Vector3::new(-1.0, 2.0, 0.0)]);
+        assert!(bounds.contains_point(Vector3::new(0.0, 0.0, 1.5)));
+        assert!(!bounds.contains_point(Vector3::new(0.0, 0.0, 3.5)));
+        assert!(bounds.expanded(1.0).contains_point(Vector3::new(0.0, 0.0, 3.5)));
+    }
+}
@@ -0,0 +1,103 @@
+use crate::gl_context::{ ContextHandle, GLObject };
+use crate::gl_debug;
+use crate::gl_extensions;
+use crate::gl_state;
+
+use gl::types::*;
+
+// Shared storage handling for the buffer wrappers. With direct state access the storage is immutable, so data that
+// outgrows it gets a new buffer under a new id; the owners of anything that refers to the buffer, like a vertex
+// array, have to attach it again. Without it, uploads go through GL_COPY_WRITE_BUFFER so they never disturb the
+// array or element array bindings of whatever vertex array is bound.
+pub struct BufferStorage {
+    pub id: GLuint,
+    pub capacity: usize,
+    // Decided when the storage is allocated, so it stays right if direct state access is turned off later.
+    pub immutable: bool,
+    // Kept so a buffer that is recreated to grow gets its label back.
+    label: Option<String>,
+    context: ContextHandle,
+}
+
+impl BufferStorage {
+    pub unsafe fn new(size: usize, data: *const GLvoid, usage: GLenum) -> BufferStorage {
+        let mut buffer = 0;
+        let immutable = gl_extensions::has_direct_state_access();
+        if immutable {
+            gl::CreateBuffers(1, &mut buffer);
+            gl::NamedBufferStorage(buffer, size as GLsizeiptr, data, gl::DYNAMIC_STORAGE_BIT);
+        } else {
+            gl::GenBuffers(1, &mut buffer);
+            gl_state::bind_buffer(gl::COPY_WRITE_BUFFER, buffer);
+            gl::BufferData(gl::COPY_WRITE_BUFFER, size as GLsizeiptr, data, usage);
+        }
+
+        gl_debug::check_errors("Creating a buffer");
+
+        return BufferStorage {
+            id: buffer,
+            capacity: size,
+            immutable,
+            label: None,
+            context: ContextHandle::adopt(GLObject::Buffer(buffer)),
+        };
+    }
+
+    // Data that fits is written in place. Anything larger reallocates mutable storage under the same id, but replaces
+    // immutable storage with a new buffer, so check `id` afterwards.
+    pub unsafe fn set_data(&mut self, size: usize, data: *const GLvoid, usage: GLenum) {
+        if size > self.capacity && self.immutable {
+            self.delete();
+            let label = self.label.take();
+            *self = BufferStorage::new(size, data, usage);
+            if let Some(label) = label {
+                self.set_debug_label(&label);
+            }
+            return;
+        }
+
+        let has_direct_state_access = gl_extensions::has_direct_state_access();
+        if !has_direct_state_access {
+            gl_state::bind_buffer(gl::COPY_WRITE_BUFFER, self.id);
+        }
+        if size <= self.capacity {
+            if has_direct_state_access {
+                gl::NamedBufferSubData(self.id, 0, size as GLsizeiptr, data);
+            } else {
+                gl::BufferSubData(gl::COPY_WRITE_BUFFER, 0, size as GLsizeiptr, data);
+            }
+        } else {
+            if has_direct_state_access {
+                gl::NamedBufferData(self.id, size as GLsizeiptr, data, usage);
+            } else {
+                gl::BufferData(gl::COPY_WRITE_BUFFER, size as GLsizeiptr, data, usage);
+            }
+            self.capacity = size;
+        }
+        gl_debug::check_errors("Uploading buffer data");
+    }
+
+    pub fn set_debug_label(&mut self, label: &str) {
+        gl_debug::set_object_label(gl::BUFFER, self.id, label);
+        self.label = Some(label.to_string());
+    }
+
+    // Hands the buffer over without deleting it, e.g. to another context of the same share group.
+    pub fn into_raw(self) -> (GLuint, usize, bool) {
+        (self.id, self.capacity, self.immutable)
+    }
+
+    pub fn from_raw(id: GLuint, capacity: usize, immutable: bool) -> BufferStorage {
+        BufferStorage {
+            id,
+            capacity,
+            immutable,
+            label: None,
+            context: ContextHandle::adopt(GLObject::Buffer(id)),
+        }
+    }
+
+    pub fn delete(&self) {
+        self.context.delete(GLObject::Buffer(self.id));
+    }
+}
@@ -0,0 +1,221 @@
+use crate::gl_context::{ ContextHandle, GLObject };
+use crate::gl_extensions;
+use crate::gl_state;
+use crate::opengl_texture::{ self, Texture, TextureDescriptor };
+use crate::texture_container::{ ContainerError, ContainerFormat, TextureContainer };
+
+use gl::types::*;
+
+pub struct OpenGLContainerTexture {
+    id: GLuint,
+    context: ContextHandle,
+    target: GLenum,
+    width: u32,
+    height: u32,
+    format: ContainerFormat,
+}
+
+impl OpenGLContainerTexture {
+    pub fn new(container: &TextureContainer, descriptor: &TextureDescriptor) -> Result<OpenGLContainerTexture, ContainerError> {
+        if container.depth != 1 {
+            return Err(ContainerError::Unsupported(format!("3D textures ({} slices deep)", container.depth)));
+        }
+
+        let target = match (container.is_array, container.face_count) {
+            (false, 1) => gl::TEXTURE_2D,
+            (false, _) => gl::TEXTURE_CUBE_MAP,
+            (true, 1) => gl::TEXTURE_2D_ARRAY,
+            (true, _) => gl::TEXTURE_CUBE_MAP_ARRAY,
+        };
+
+        unsafe {
+            let texture = opengl_texture::create_texture(target);
+            descriptor.apply_sampler_parameters(texture, target);
+            opengl_texture::set_texture_parameter(texture, target, gl::TEXTURE_MAX_LEVEL, container.levels.len() as GLint - 1);
+
+            let previous_alignment = opengl_texture::set_pixel_store(gl::UNPACK_ALIGNMENT, 1);
+            if gl_extensions::has_direct_state_access() {
+                upload_levels_direct(texture, target, container);
+            } else {
+                gl_state::bind_texture(target, texture);
+                upload_levels(target, container);
+            }
+            opengl_texture::set_pixel_store(gl::UNPACK_ALIGNMENT, previous_alignment);
+
+            return Ok(OpenGLContainerTexture {
+                id: texture,
+                context: ContextHandle::adopt(GLObject::Texture(texture)),
+                target,
+                width: container.width,
+                height: container.height,
+                format: container.format,
+            });
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8], descriptor: &TextureDescriptor) -> Result<OpenGLContainerTexture, ContainerError> {
+        let container = TextureContainer::parse(bytes)?;
+        return OpenGLContainerTexture::new(&container, descriptor);
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn get_format(&self) -> ContainerFormat {
+        self.format
+    }
+}
+
+// Uploads every level to the texture bound to `target`.
+unsafe fn upload_levels(target: GLenum, container: &TextureContainer) {
+    for (level, mip_level) in container.levels.iter().enumerate() {
+        if target == gl::TEXTURE_2D_ARRAY || target == gl::TEXTURE_CUBE_MAP_ARRAY {
+            upload_image_3d(target, level, mip_level.width, mip_level.height, container.layer_count * container.face_count, container.format, &mip_level.data);
+            continue;
+        }
+
+        for face in 0..container.face_count {
+            let image_target = if target == gl::TEXTURE_CUBE_MAP { gl::TEXTURE_CUBE_MAP_POSITIVE_X + face } else { target };
+            let image = container.get_image(level, 0, face);
+            upload_image_2d(image_target, level, mip_level.width, mip_level.height, container.format, image);
+        }
+    }
+}
+
+// Allocates immutable storage for every level and uploads them without binding the texture.
+unsafe fn upload_levels_direct(texture: GLuint, target: GLenum, container: &TextureContainer) {
+    let internal_format = match container.format {
+        ContainerFormat::Compressed(compressed_format) => compressed_format.get_internal_format(),
+        ContainerFormat::Uncompressed { format, .. } => format.get_internal_format(),
+    };
+    let level_count = container.levels.len() as GLsizei;
+    let layer_count = container.layer_count * container.face_count;
+    if target == gl::TEXTURE_2D || target == gl::TEXTURE_CUBE_MAP {
+        gl::TextureStorage2D(texture, level_count, internal_format, container.width as GLsizei, container.height as GLsizei);
+    } else {
+        gl::TextureStorage3D(texture, level_count, internal_format, container.width as GLsizei, container.height as GLsizei, layer_count as GLsizei);
+    }
+
+    for (level, mip_level) in container.levels.iter().enumerate() {
+        match target {
+            gl::TEXTURE_2D => upload_sub_image_direct(texture, false, level, mip_level.width, mip_level.height, 0, 1, container.format, &mip_level.data),
+            // DSA treats cube maps as six layers, so faces are addressed like array layers.
+            gl::TEXTURE_CUBE_MAP => {
+                for face in 0..container.face_count {
+                    let image = container.get_image(level, 0, face);
+                    upload_sub_image_direct(texture, true, level, mip_level.width, mip_level.height, face, 1, container.format, image);
+                }
+            }
+            _ => upload_sub_image_direct(texture, true, level, mip_level.width, mip_level.height, 0, layer_count, container.format, &mip_level.data),
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe fn upload_sub_image_direct(texture: GLuint, layered: bool, level: usize, width: u32, height: u32, layer: u32, layer_count: u32, format: ContainerFormat, data: &[u8]) {
+    let (level, width, height) = (level as GLint, width as GLsizei, height as GLsizei);
+    let pixels = data.as_ptr() as *const GLvoid;
+    match format {
+        ContainerFormat::Compressed(compressed_format) => {
+            let (internal_format, size) = (compressed_format.get_internal_format(), data.len() as GLsizei);
+            if layered {
+                gl::CompressedTextureSubImage3D(texture, level, 0, 0, layer as GLint, width, height, layer_count as GLsizei, internal_format, size, pixels);
+            } else {
+                gl::CompressedTextureSubImage2D(texture, level, 0, 0, width, height, internal_format, size, pixels);
+            }
+        }
+
+        ContainerFormat::Uncompressed { format, pixel_type, .. } => {
+            if layered {
+                gl::TextureSubImage3D(texture, level, 0, 0, layer as GLint, width, height, layer_count as GLsizei, format.get_pixel_format(), pixel_type, pixels);
+            } else {
+                gl::TextureSubImage2D(texture, level, 0, 0, width, height, format.get_pixel_format(), pixel_type, pixels);
+            }
+        }
+    }
+}
+
+unsafe fn upload_image_2d(target: GLenum, level: usize, width: u32, height: u32, format: ContainerFormat, data: &[u8]) {
+    match format {
+        ContainerFormat::Compressed(compressed_format) => {
+            gl::CompressedTexImage2D(
+                target,
+                level as GLint,
+                compressed_format.get_internal_format(),
+                width as GLsizei,
+                height as GLsizei,
+                0,
+                data.len() as GLsizei,
+                data.as_ptr() as *const GLvoid,
+            );
+        }
+
+        ContainerFormat::Uncompressed { format, pixel_type, .. } => {
+            gl::TexImage2D(
+                target,
+                level as GLint,
+                format.get_internal_format() as GLint,
+                width as GLsizei,
+                height as GLsizei,
+                0,
+                format.get_pixel_format(),
+                pixel_type,
+                data.as_ptr() as *const GLvoid,
+            );
+        }
+    }
+}
+
+unsafe fn upload_image_3d(target: GLenum, level: usize, width: u32, height: u32, depth: u32, format: ContainerFormat, data: &[u8]) {
+    match format {
+        ContainerFormat::Compressed(compressed_format) => {
+            gl::CompressedTexImage3D(
+                target,
+                level as GLint,
+                compressed_format.get_internal_format(),
+                width as GLsizei,
+                height as GLsizei,
+                depth as GLsizei,
+                0,
+                data.len() as GLsizei,
+                data.as_ptr() as *const GLvoid,
+            );
+        }
+
+        ContainerFormat::Uncompressed { format, pixel_type, .. } => {
+            gl::TexImage3D(
+                target,
+                level as GLint,
+                format.get_internal_format() as GLint,
+                width as GLsizei,
+                height as GLsizei,
+                depth as GLsizei,
+                0,
+                format.get_pixel_format(),
+                pixel_type,
+                data.as_ptr() as *const GLvoid,
+            );
+        }
+    }
+}
+
+impl Texture for OpenGLContainerTexture {
+    fn get_id(&self) -> GLuint {
+        self.id
+    }
+
+    fn get_target(&self) -> GLenum {
+        self.target
+    }
+}
+
+impl Drop for OpenGLContainerTexture {
+    fn drop(&mut self) {
+        self.context.delete(GLObject::Texture(self.id));
+    }
+}
@@ -0,0 +1,73 @@
+use crate::gl_context::{ ContextHandle, GLObject };
+use crate::gl_debug;
+use crate::gl_object_tracker;
+
+use gl::types::*;
+
+use std::time::{ Duration };
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FenceStatus {
+    Signaled,
+    TimedOut,
+    // The wait itself failed, e.g. because the context was lost. Waiting again won't help.
+    Failed,
+}
+
+// Signals once the GPU has finished every command issued before it. Sync objects are shared by all contexts of a
+// share group, so a fence can be created on a loader thread and checked on the render thread.
+pub struct OpenGLFence {
+    sync: GLsync,
+}
+
+// The sync object is only ever touched through GL, which allows it from any context of the share group.
+unsafe impl Send for OpenGLFence {}
+
+impl OpenGLFence {
+    pub fn new() -> OpenGLFence {
+        unsafe {
+            let sync = gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+            // Other contexts only see the fence signal once it has actually been submitted.
+            gl::Flush();
+            gl_debug::check_errors("Creating a fence");
+            gl_object_tracker::register(GLObject::Sync(sync).get_current_key());
+            return OpenGLFence { sync };
+        }
+    }
+
+    // Never blocks.
+    pub fn is_signaled(&self) -> bool {
+        let mut status = gl::UNSIGNALED as GLint;
+        unsafe {
+            gl::GetSynciv(self.sync, gl::SYNC_STATUS, 1, std::ptr::null_mut(), &mut status);
+        }
+        return status == gl::SIGNALED as GLint;
+    }
+
+    // Blocks the calling thread for at most `timeout`.
+    pub fn wait(&self, timeout: Duration) -> FenceStatus {
+        let result = unsafe { gl::ClientWaitSync(self.sync, 0, timeout.as_nanos().min(u64::MAX as u128) as GLuint64) };
+        return match result {
+            gl::ALREADY_SIGNALED | gl::CONDITION_SATISFIED => FenceStatus::Signaled,
+            gl::TIMEOUT_EXPIRED => FenceStatus::TimedOut,
+            _ => FenceStatus::Failed,
+        };
+    }
+
+    // Makes the current context's GPU queue wait for the fence instead of the CPU, so later commands are ordered
+    // after it without stalling.
+    pub fn wait_on_gpu(&self) {
+        unsafe {
+            gl::WaitSync(self.sync, 0, gl::TIMEOUT_IGNORED);
+        }
+    }
+}
+
+impl Drop for OpenGLFence {
+    fn drop(&mut self) {
+        match ContextHandle::try_current() {
+            Some(context) => context.delete(GLObject::Sync(self.sync)),
+            None => log::warn!(target: "gl", "A fence was dropped on a thread without a GL context and leaked"),
+        }
+    }
+}
@@ -0,0 +1,437 @@
+use crate::gl_capabilities;
+use crate::gl_context::{ ContextHandle, GLObject };
+use crate::gl_debug;
+use crate::opengl_multisample_texture::{ OpenGLMultisampleTexture };
+use crate::opengl_renderbuffer::{ OpenGLRenderbuffer };
+use crate::opengl_texture::{ OpenGLTexture, Texture, TextureDescriptor, TextureFilter, TextureFormat };
+
+use gl::types::*;
+
+use std::cell::{ Cell };
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FramebufferError {
+    Undefined,
+    IncompleteAttachment,
+    MissingAttachment,
+    IncompleteDrawBuffer,
+    IncompleteReadBuffer,
+    Unsupported,
+    IncompleteMultisample,
+    IncompleteLayerTargets,
+    Unknown(GLenum),
+}
+
+impl FramebufferError {
+    fn from_status(status: GLenum) -> FramebufferError {
+        match status {
+            gl::FRAMEBUFFER_UNDEFINED => FramebufferError::Undefined,
+            gl::FRAMEBUFFER_INCOMPLETE_ATTACHMENT => FramebufferError::IncompleteAttachment,
+            gl::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT => FramebufferError::MissingAttachment,
+            gl::FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER => FramebufferError::IncompleteDrawBuffer,
+            gl::FRAMEBUFFER_INCOMPLETE_READ_BUFFER => FramebufferError::IncompleteReadBuffer,
+            gl::FRAMEBUFFER_UNSUPPORTED => FramebufferError::Unsupported,
+            gl::FRAMEBUFFER_INCOMPLETE_MULTISAMPLE => FramebufferError::IncompleteMultisample,
+            gl::FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS => FramebufferError::IncompleteLayerTargets,
+            _ => FramebufferError::Unknown(status),
+        }
+    }
+}
+
+impl std::fmt::Display for FramebufferError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FramebufferError::Undefined => write!(f, "The default framebuffer doesn't exist"),
+            FramebufferError::IncompleteAttachment => write!(f, "A framebuffer attachment is incomplete"),
+            FramebufferError::MissingAttachment => write!(f, "The framebuffer has no attachments"),
+            FramebufferError::IncompleteDrawBuffer => write!(f, "A draw buffer names a missing attachment"),
+            FramebufferError::IncompleteReadBuffer => write!(f, "The read buffer names a missing attachment"),
+            FramebufferError::Unsupported => write!(f, "The combination of attachment formats is not supported"),
+            FramebufferError::IncompleteMultisample => write!(f, "The attachments don't all have the same sample count"),
+            FramebufferError::IncompleteLayerTargets => write!(f, "The attachments don't all have the same layer count"),
+            FramebufferError::Unknown(status) => write!(f, "Unknown framebuffer status 0x{:X}", status),
+        }
+    }
+}
+
+impl std::error::Error for FramebufferError {}
+
+pub enum ColorAttachment {
+    Texture(OpenGLTexture),
+    MultisampleTexture(OpenGLMultisampleTexture),
+    // Multisampled colour on contexts without multisample textures. It can only be resolved, never sampled.
+    Renderbuffer(OpenGLRenderbuffer),
+}
+
+impl ColorAttachment {
+    // `None` for renderbuffers.
+    pub fn get_texture(&self) -> Option<&dyn Texture> {
+        match self {
+            ColorAttachment::Texture(texture) => Some(texture),
+            ColorAttachment::MultisampleTexture(texture) => Some(texture),
+            ColorAttachment::Renderbuffer(_) => None,
+        }
+    }
+
+    fn get_format(&self) -> TextureFormat {
+        match self {
+            ColorAttachment::Texture(texture) => texture.get_format(),
+            ColorAttachment::MultisampleTexture(texture) => texture.get_format(),
+            ColorAttachment::Renderbuffer(renderbuffer) => renderbuffer.get_format(),
+        }
+    }
+
+    fn get_size(&self) -> (u32, u32) {
+        match self {
+            ColorAttachment::Texture(texture) => (texture.get_width(), texture.get_height()),
+            ColorAttachment::MultisampleTexture(texture) => (texture.get_width(), texture.get_height()),
+            ColorAttachment::Renderbuffer(renderbuffer) => (renderbuffer.get_width(), renderbuffer.get_height()),
+        }
+    }
+
+    fn get_samples(&self) -> u32 {
+        match self {
+            ColorAttachment::Texture(_) => 0,
+            ColorAttachment::MultisampleTexture(texture) => texture.get_samples(),
+            ColorAttachment::Renderbuffer(renderbuffer) => renderbuffer.get_samples(),
+        }
+    }
+}
+
+pub enum DepthStencilAttachment {
+    Renderbuffer(OpenGLRenderbuffer),
+    // Depth textures can be sampled afterwards, which is what shadow maps need.
+    Texture(OpenGLTexture),
+}
+
+impl DepthStencilAttachment {
+    fn get_format(&self) -> TextureFormat {
+        match self {
+            DepthStencilAttachment::Renderbuffer(renderbuffer) => renderbuffer.get_format(),
+            DepthStencilAttachment::Texture(texture) => texture.get_format(),
+        }
+    }
+
+    fn get_samples(&self) -> u32 {
+        match self {
+            DepthStencilAttachment::Renderbuffer(renderbuffer) => renderbuffer.get_samples(),
+            DepthStencilAttachment::Texture(_) => 0,
+        }
+    }
+}
+
+pub struct OpenGLFramebuffer {
+    id: GLuint,
+    context: ContextHandle,
+    width: u32,
+    height: u32,
+    color_attachments: Vec<ColorAttachment>,
+    depth_stencil_attachment: Option<DepthStencilAttachment>,
+    // The framebuffer and viewport that were active before `bind`, put back by `un_bind`.
+    previous_state: Cell<Option<(GLint, [GLint; 4])>>,
+}
+
+impl OpenGLFramebuffer {
+    pub fn new(width: u32, height: u32) -> OpenGLFramebuffer {
+        unsafe {
+            let mut framebuffer = 0;
+            gl::GenFramebuffers(1, &mut framebuffer);
+
+            return OpenGLFramebuffer {
+                id: framebuffer,
+                context: ContextHandle::adopt(GLObject::Framebuffer(framebuffer)),
+                width,
+                height,
+                color_attachments: Vec::new(),
+                depth_stencil_attachment: None,
+                previous_state: Cell::new(None),
+            };
+        }
+    }
+
+    // Creates one colour texture per descriptor and an optional depth/stencil renderbuffer, then checks completeness.
+    pub fn with_attachments(width: u32, height: u32, color_descriptors: &[TextureDescriptor], depth_stencil_format: Option<TextureFormat>) -> Result<OpenGLFramebuffer, FramebufferError> {
+        let mut framebuffer = OpenGLFramebuffer::new(width, height);
+        for descriptor in color_descriptors {
+            framebuffer.add_color_attachment(OpenGLTexture::with_descriptor::<u8>(&[], width, height, descriptor));
+        }
+        if let Some(format) = depth_stencil_format {
+            framebuffer.set_depth_stencil_attachment(DepthStencilAttachment::Renderbuffer(OpenGLRenderbuffer::new(width, height, format)));
+        }
+        framebuffer.check_status()?;
+        return Ok(framebuffer);
+    }
+
+    // The multisampled counterpart of `with_attachments`. Draw into it, then `resolve_to` a single-sample framebuffer to sample the result.
+    // Colour goes into multisample textures where the context has them and into renderbuffers otherwise.
+    pub fn with_multisample_attachments(width: u32, height: u32, samples: u32, color_formats: &[TextureFormat], depth_stencil_format: Option<TextureFormat>) -> Result<OpenGLFramebuffer, FramebufferError> {
+        assert!(samples > 0, "Use `with_attachments` for single-sample framebuffers!");
+
+        let mut framebuffer = OpenGLFramebuffer::new(width, height);
+        let has_multisample_textures = gl_capabilities::get().has_multisample_textures();
+        for format in color_formats {
+            if has_multisample_textures {
+                framebuffer.attach_color(ColorAttachment::MultisampleTexture(OpenGLMultisampleTexture::new(width, height, *format, samples)));
+            } else {
+                framebuffer.attach_color(ColorAttachment::Renderbuffer(OpenGLRenderbuffer::with_samples(width, height, *format, samples)));
+            }
+        }
+        if let Some(format) = depth_stencil_format {
+            framebuffer.set_depth_stencil_attachment(DepthStencilAttachment::Renderbuffer(OpenGLRenderbuffer::with_samples(width, height, format, samples)));
+        }
+        framebuffer.check_status()?;
+        return Ok(framebuffer);
+    }
+
+    // Creates a single-sample framebuffer with the same colour formats, ready to be the target of `resolve_to`.
+    pub fn create_resolve_target(&self, descriptor: &TextureDescriptor) -> Result<OpenGLFramebuffer, FramebufferError> {
+        let color_descriptors: Vec<TextureDescriptor> = self.color_attachments.iter()
+            .map(|attachment| TextureDescriptor { format: attachment.get_format(), ..*descriptor })
+            .collect();
+        return OpenGLFramebuffer::with_attachments(self.width, self.height, &color_descriptors, None);
+    }
+
+    pub fn add_color_attachment(&mut self, texture: OpenGLTexture) -> &OpenGLTexture {
+        match self.attach_color(ColorAttachment::Texture(texture)) {
+            ColorAttachment::Texture(texture) => texture,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn add_multisample_color_attachment(&mut self, texture: OpenGLMultisampleTexture) -> &OpenGLMultisampleTexture {
+        match self.attach_color(ColorAttachment::MultisampleTexture(texture)) {
+            ColorAttachment::MultisampleTexture(texture) => texture,
+            _ => unreachable!(),
+        }
+    }
+
+    fn attach_color(&mut self, color_attachment: ColorAttachment) -> &ColorAttachment {
+        assert!(!color_attachment.get_format().is_depth(), "Depth textures can't be colour attachments!");
+        assert!(color_attachment.get_size() == (self.width, self.height), "The attachment doesn't match the framebuffer size!");
+        assert!(self.color_attachments.len() < get_max_color_attachments() as usize, "Too many colour attachments!");
+
+        let attachment = gl::COLOR_ATTACHMENT0 + self.color_attachments.len() as GLenum;
+        self.color_attachments.push(color_attachment);
+
+        let draw_buffers: Vec<GLenum> = (0..self.color_attachments.len() as GLenum).map(|index| gl::COLOR_ATTACHMENT0 + index).collect();
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.id);
+            match self.color_attachments.last().unwrap() {
+                ColorAttachment::Renderbuffer(renderbuffer) => gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, attachment, gl::RENDERBUFFER, renderbuffer.get_id()),
+                color_attachment => {
+                    let texture = color_attachment.get_texture().unwrap();
+                    gl::FramebufferTexture2D(gl::FRAMEBUFFER, attachment, texture.get_target(), texture.get_id(), 0);
+                }
+            }
+            gl::DrawBuffers(draw_buffers.len() as GLsizei, draw_buffers.as_ptr());
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+        gl_debug::check_errors("Attaching a colour attachment");
+
+        return self.color_attachments.last().unwrap();
+    }
+
+    pub fn set_depth_stencil_attachment(&mut self, attachment: DepthStencilAttachment) {
+        let format = attachment.get_format();
+        assert!(format.is_depth(), "Only depth formats can be depth/stencil attachments!");
+        let attachment_point = if format.get_pixel_format() == gl::DEPTH_STENCIL { gl::DEPTH_STENCIL_ATTACHMENT } else { gl::DEPTH_ATTACHMENT };
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.id);
+            // Clear both points so switching between depth and depth/stencil formats doesn't leave a stale stencil attachment behind.
+            gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, gl::RENDERBUFFER, 0);
+            match &attachment {
+                DepthStencilAttachment::Renderbuffer(renderbuffer) => {
+                    assert!(renderbuffer.get_width() == self.width && renderbuffer.get_height() == self.height, "The attachment doesn't match the framebuffer size!");
+                    gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, attachment_point, gl::RENDERBUFFER, renderbuffer.get_id());
+                }
+                DepthStencilAttachment::Texture(texture) => {
+                    assert!(texture.get_width() == self.width && texture.get_height() == self.height, "The attachment doesn't match the framebuffer size!");
+                    gl::FramebufferTexture2D(gl::FRAMEBUFFER, attachment_point, gl::TEXTURE_2D, texture.get_id(), 0);
+                }
+            }
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+        gl_debug::check_errors("Attaching a depth/stencil attachment");
+
+        self.depth_stencil_attachment = Some(attachment);
+    }
+
+    pub fn check_status(&self) -> Result<(), FramebufferError> {
+        let status = unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.id);
+            let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            status
+        };
+
+        if status == gl::FRAMEBUFFER_COMPLETE {
+            return Ok(());
+        }
+        return Err(FramebufferError::from_status(status));
+    }
+
+    // Binds the framebuffer for drawing and sets the viewport to cover it, remembering what was active before.
+    pub fn bind(&self) {
+        unsafe {
+            let mut previous_framebuffer = 0;
+            let mut previous_viewport = [0; 4];
+            gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut previous_framebuffer);
+            gl::GetIntegerv(gl::VIEWPORT, previous_viewport.as_mut_ptr());
+            self.previous_state.set(Some((previous_framebuffer, previous_viewport)));
+
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.id);
+            gl::Viewport(0, 0, self.width as GLsizei, self.height as GLsizei);
+        }
+    }
+
+    pub fn un_bind(&self) {
+        let (previous_framebuffer, previous_viewport) = self.previous_state.take().unwrap_or((0, [0; 4]));
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, previous_framebuffer as GLuint);
+            if previous_viewport[2] > 0 && previous_viewport[3] > 0 {
+                gl::Viewport(previous_viewport[0], previous_viewport[1], previous_viewport[2], previous_viewport[3]);
+            }
+        }
+    }
+
+    // Copies the whole framebuffer into `destination`, or into the default framebuffer of the given size when it is `None`.
+    // Colour is read from attachment 0 and written to every draw buffer of the destination.
+    pub fn blit_to(&self, destination: Option<&OpenGLFramebuffer>, destination_size: (u32, u32), mask: GLbitfield, filter: TextureFilter) {
+        assert!(!filter.uses_mipmaps(), "Blits can't use mipmap filters!");
+        assert!(mask & (gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT) == 0 || filter == TextureFilter::Nearest, "Depth and stencil blits must use nearest filtering!");
+
+        self.with_blit_bindings(destination.map_or(0, |framebuffer| framebuffer.id), || unsafe {
+            if mask & gl::COLOR_BUFFER_BIT != 0 {
+                gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+            }
+            self.blit(destination_size, mask, filter);
+        });
+    }
+
+    // A same-size copy of colour, depth and stencil, which is also how multisampled framebuffers are resolved. Each
+    // colour attachment goes to the destination's attachment of the same index.
+    pub fn resolve_to(&self, destination: &OpenGLFramebuffer) {
+        assert!(destination.width == self.width && destination.height == self.height, "Resolving needs framebuffers of the same size!");
+        assert_eq!(destination.get_samples(), 0, "Can't resolve into a multisampled framebuffer!");
+
+        let size = (self.width, self.height);
+        self.with_blit_bindings(destination.id, || unsafe {
+            let attachment_count = self.color_attachments.len().min(destination.color_attachments.len());
+            for index in 0..attachment_count as GLenum {
+                let attachment = gl::COLOR_ATTACHMENT0 + index;
+                gl::ReadBuffer(attachment);
+                gl::DrawBuffers(1, &attachment);
+                self.blit(size, gl::COLOR_BUFFER_BIT, TextureFilter::Nearest);
+            }
+            gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+            let draw_buffers: Vec<GLenum> = (0..destination.color_attachments.len() as GLenum).map(|index| gl::COLOR_ATTACHMENT0 + index).collect();
+            gl::DrawBuffers(draw_buffers.len() as GLsizei, draw_buffers.as_ptr());
+
+            if self.depth_stencil_attachment.is_some() && destination.depth_stencil_attachment.is_some() {
+                self.blit(size, gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT, TextureFilter::Nearest);
+            }
+        });
+    }
+
+    // Binds this framebuffer for reading and `destination_id` for drawing while `blit` runs.
+    fn with_blit_bindings<F: FnOnce()>(&self, destination_id: GLuint, blit: F) {
+        unsafe {
+            let mut previous_read_framebuffer = 0;
+            let mut previous_draw_framebuffer = 0;
+            gl::GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut previous_read_framebuffer);
+            gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut previous_draw_framebuffer);
+
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.id);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, destination_id);
+            blit();
+
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, previous_read_framebuffer as GLuint);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, previous_draw_framebuffer as GLuint);
+        }
+    }
+
+    unsafe fn blit(&self, destination_size: (u32, u32), mask: GLbitfield, filter: TextureFilter) {
+        gl::BlitFramebuffer(
+            0,
+            0,
+            self.width as GLint,
+            self.height as GLint,
+            0,
+            0,
+            destination_size.0 as GLint,
+            destination_size.1 as GLint,
+            mask,
+            filter.get_gl_filter(),
+        );
+    }
+
+    pub fn get_color_attachment(&self, index: usize) -> &OpenGLTexture {
+        match &self.color_attachments[index] {
+            ColorAttachment::Texture(texture) => texture,
+            ColorAttachment::MultisampleTexture(_) | ColorAttachment::Renderbuffer(_) => panic!("Colour attachment {} is multisampled, resolve it first!", index),
+        }
+    }
+
+    pub fn get_color_attachments(&self) -> &[ColorAttachment] {
+        &self.color_attachments
+    }
+
+    // 0 for single-sample framebuffers.
+    pub fn get_samples(&self) -> u32 {
+        self.color_attachments.iter()
+            .map(|attachment| attachment.get_samples())
+            .chain(self.depth_stencil_attachment.iter().map(|attachment| attachment.get_samples()))
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn get_color_attachment_count(&self) -> usize {
+        self.color_attachments.len()
+    }
+
+    pub fn get_depth_stencil_attachment(&self) -> Option<&DepthStencilAttachment> {
+        self.depth_stencil_attachment.as_ref()
+    }
+
+    pub fn get_id(&self) -> GLuint {
+        self.id
+    }
+
+    // Labels the framebuffer and every attachment it owns, e.g. `Shadow Map` and `Shadow Map Depth`.
+    pub fn set_debug_label(&self, label: &str) {
+        gl_debug::ensure_object_exists(gl::FRAMEBUFFER, self.id);
+        gl_debug::set_object_label(gl::FRAMEBUFFER, self.id, label);
+
+        for (index, attachment) in self.color_attachments.iter().enumerate() {
+            let attachment_label = format!("{} Color {}", label, index);
+            match attachment {
+                ColorAttachment::Renderbuffer(renderbuffer) => renderbuffer.set_debug_label(&attachment_label),
+                attachment => attachment.get_texture().unwrap().set_debug_label(&attachment_label),
+            }
+        }
+        match &self.depth_stencil_attachment {
+            Some(DepthStencilAttachment::Renderbuffer(renderbuffer)) => renderbuffer.set_debug_label(&format!("{} Depth", label)),
+            Some(DepthStencilAttachment::Texture(texture)) => texture.set_debug_label(&format!("{} Depth", label)),
+            None => {}
+        }
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+}
+
+impl Drop for OpenGLFramebuffer {
+    fn drop(&mut self) {
+        self.context.delete(GLObject::Framebuffer(self.id));
+    }
+}
+
+pub fn get_max_color_attachments() -> GLint {
+    gl_capabilities::get().get_limits().max_color_attachments as GLint
+}
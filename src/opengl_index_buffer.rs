@@ -1,57 +1,57 @@
+use crate::gl_state;
+use crate::opengl_buffer::{ BufferStorage };
+
 use gl::types::*;
 
 pub struct OpenGLIndexBuffer {
-    id: GLuint,
+    storage: BufferStorage,
 }
 
 impl OpenGLIndexBuffer {
     pub fn new(data: &[u32]) -> OpenGLIndexBuffer {
         unsafe {
-            let mut index_buffer = 0;
-            gl::GenBuffers(1, &mut index_buffer);
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, index_buffer);
-            gl::BufferData(
-                gl::ELEMENT_ARRAY_BUFFER,
-                (data.len() * std::mem::size_of::<u32>()) as GLsizeiptr,
+            let storage = BufferStorage::new(
+                data.len() * std::mem::size_of::<u32>(),
                 &data[0] as *const u32 as *const GLvoid,
                 gl::STATIC_DRAW,
             );
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0);
 
             return OpenGLIndexBuffer {
-                id: index_buffer,
+                storage,
             };
         }
     }
 
+    // Data larger than the buffer can move it to a new id, so bind it again before the next draw.
     pub fn set_data(&mut self, data: &[u32]) {
         unsafe {
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.id);
-            gl::BufferData(
-                gl::ELEMENT_ARRAY_BUFFER,
-                (data.len() * std::mem::size_of::<u32>()) as GLsizeiptr,
+            self.storage.set_data(
+                data.len() * std::mem::size_of::<u32>(),
                 &data[0] as *const u32 as *const GLvoid,
                 gl::STATIC_DRAW,
             );
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0);
         }
     }
 
+    pub fn get_id(&self) -> GLuint {
+        self.storage.id
+    }
+
+    pub fn set_debug_label(&mut self, label: &str) {
+        self.storage.set_debug_label(label);
+    }
+
     pub fn bind(&self) {
-        unsafe {
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.id);
-        }
+        gl_state::bind_buffer(gl::ELEMENT_ARRAY_BUFFER, self.storage.id);
     }
 
     pub fn un_bind(&self) {
-        unsafe {
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0);
-        }
+        gl_state::bind_buffer(gl::ELEMENT_ARRAY_BUFFER, 0);
     }
 }
 
 impl Drop for OpenGLIndexBuffer {
     fn drop(&mut self) {
-        unsafe { gl::DeleteBuffers(1, &self.id); }
+        self.storage.delete();
     }
 }
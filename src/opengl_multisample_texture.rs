@@ -0,0 +1,94 @@
+use crate::gl_capabilities;
+use crate::gl_context::{ ContextHandle, GLObject };
+use crate::gl_extensions;
+use crate::opengl_renderbuffer;
+use crate::opengl_texture::{ self, Texture, TextureFormat };
+
+use gl::types::*;
+
+// Multisample textures can't be filtered or mipmapped, only fetched per sample with `texelFetch` or resolved with a blit.
+pub struct OpenGLMultisampleTexture {
+    id: GLuint,
+    context: ContextHandle,
+    width: u32,
+    height: u32,
+    format: TextureFormat,
+    samples: u32,
+}
+
+impl OpenGLMultisampleTexture {
+    // Counts above GL_MAX_SAMPLES are clamped. Needs GL 3.2 or GLES 3.1, use a multisampled renderbuffer without them.
+    pub fn new(width: u32, height: u32, format: TextureFormat, samples: u32) -> OpenGLMultisampleTexture {
+        assert!(gl_capabilities::get().has_multisample_textures(), "Multisample textures need GL 3.2 or GLES 3.1!");
+        let samples = std::cmp::max(std::cmp::min(samples, opengl_renderbuffer::get_max_samples()), 1);
+        unsafe {
+            let texture = opengl_texture::create_texture(gl::TEXTURE_2D_MULTISAMPLE);
+            if gl_extensions::has_direct_state_access() {
+                gl::TextureStorage2DMultisample(texture, samples as GLsizei, format.get_internal_format(), width as GLsizei, height as GLsizei, gl::TRUE);
+            } else if gl_capabilities::get().has_texture_storage_multisample() {
+                gl::TexStorage2DMultisample(
+                    gl::TEXTURE_2D_MULTISAMPLE,
+                    samples as GLsizei,
+                    format.get_internal_format(),
+                    width as GLsizei,
+                    height as GLsizei,
+                    gl::TRUE,
+                );
+            } else {
+                gl::TexImage2DMultisample(
+                    gl::TEXTURE_2D_MULTISAMPLE,
+                    samples as GLsizei,
+                    format.get_internal_format(),
+                    width as GLsizei,
+                    height as GLsizei,
+                    gl::TRUE,
+                );
+            }
+
+            return OpenGLMultisampleTexture {
+                id: texture,
+                context: ContextHandle::adopt(GLObject::Texture(texture)),
+                width,
+                height,
+                format,
+                samples,
+            };
+        }
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn get_format(&self) -> TextureFormat {
+        self.format
+    }
+
+    pub fn get_samples(&self) -> u32 {
+        self.samples
+    }
+}
+
+impl Texture for OpenGLMultisampleTexture {
+    fn get_id(&self) -> GLuint {
+        self.id
+    }
+
+    fn get_target(&self) -> GLenum {
+        gl::TEXTURE_2D_MULTISAMPLE
+    }
+
+    fn generate_mipmaps(&self) {
+        panic!("Multisample textures can't have mipmaps!");
+    }
+}
+
+impl Drop for OpenGLMultisampleTexture {
+    fn drop(&mut self) {
+        self.context.delete(GLObject::Texture(self.id));
+    }
+}
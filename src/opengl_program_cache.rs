@@ -0,0 +1,163 @@
+use crate::gl_capabilities;
+use crate::opengl_shader::{ ShaderSource };
+
+use gl::types::*;
+use std::convert::{ TryFrom, TryInto };
+use std::io::{ Read, Write };
+use std::path::{ PathBuf };
+
+// Bumped whenever the layout changes, so older files are ignored and rewritten.
+const CACHE_FILE_MAGIC: &[u8; 4] = b"GLP2";
+
+pub struct OpenGLProgramCache {
+    directory: PathBuf,
+}
+
+impl OpenGLProgramCache {
+    pub fn new<P: Into<PathBuf>>(directory: P) -> OpenGLProgramCache {
+        OpenGLProgramCache {
+            directory: directory.into(),
+        }
+    }
+
+    pub fn is_supported() -> bool {
+        unsafe {
+            let mut format_count = 0;
+            gl::GetIntegerv(gl::NUM_PROGRAM_BINARY_FORMATS, &mut format_count);
+            return format_count > 0;
+        }
+    }
+
+    pub fn get_key(&self, sources: &[(&ShaderSource, GLenum)]) -> ProgramCacheKey {
+        let capabilities = gl_capabilities::get();
+        return ProgramCacheKey::new(&[capabilities.get_vendor(), capabilities.get_renderer(), capabilities.get_version_string()], sources);
+    }
+
+    pub fn load(&self, key: &ProgramCacheKey) -> Option<(GLenum, Vec<u8>)> {
+        let mut file = std::fs::File::open(self.get_path(key)).ok()?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).ok()?;
+        return decode_cache_file(contents, key);
+    }
+
+    pub fn store(&self, key: &ProgramCacheKey, binary_format: GLenum, binary: &[u8]) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.directory)?;
+
+        let path = self.get_path(key);
+        let temporary_path = path.with_extension("tmp");
+        {
+            let mut file = std::fs::File::create(&temporary_path)?;
+            file.write_all(&encode_cache_file(key, binary_format, binary))?;
+        }
+        return std::fs::rename(temporary_path, path);
+    }
+
+    pub fn remove(&self, key: &ProgramCacheKey) {
+        let _ = std::fs::remove_file(self.get_path(key));
+    }
+
+    fn get_path(&self, key: &ProgramCacheKey) -> PathBuf {
+        self.directory.join(format!("{:016x}.bin", key.hash))
+    }
+}
+
+// Everything a program binary depends on. Files are named by the hash, and the full material is stored in the file
+// and compared on load, so two programs whose hashes collide never load each other's binary.
+pub struct ProgramCacheKey {
+    hash: u64,
+    material: Vec<u8>,
+}
+
+impl ProgramCacheKey {
+    // `driver` identifies the driver, e.g. its vendor, renderer and version strings.
+    pub fn new(driver: &[&str], sources: &[(&ShaderSource, GLenum)]) -> ProgramCacheKey {
+        let mut material = Vec::new();
+        for string in driver {
+            material.extend_from_slice(string.as_bytes());
+            material.push(0);
+        }
+        for (source, stage) in sources {
+            material.extend_from_slice(&stage.to_le_bytes());
+            material.extend_from_slice(source.source.as_bytes());
+            material.push(0);
+            for (name, value) in source.defines {
+                material.extend_from_slice(format!("{}={}\n", name, value).as_bytes());
+            }
+            material.push(0);
+        }
+
+        return ProgramCacheKey {
+            hash: fnv1a(FNV_OFFSET_BASIS, &material),
+            material,
+        };
+    }
+
+    pub fn get_hash(&self) -> u64 {
+        self.hash
+    }
+}
+
+// Magic, binary format, key material length and key material, followed by the binary.
+fn encode_cache_file(key: &ProgramCacheKey, binary_format: GLenum, binary: &[u8]) -> Vec<u8> {
+    let mut contents = Vec::with_capacity(16 + key.material.len() + binary.len());
+    contents.extend_from_slice(CACHE_FILE_MAGIC);
+    contents.extend_from_slice(&binary_format.to_le_bytes());
+    contents.extend_from_slice(&(key.material.len() as u64).to_le_bytes());
+    contents.extend_from_slice(&key.material);
+    contents.extend_from_slice(binary);
+    return contents;
+}
+
+fn decode_cache_file(mut contents: Vec<u8>, key: &ProgramCacheKey) -> Option<(GLenum, Vec<u8>)> {
+    if contents.len() < 16 || &contents[0..4] != CACHE_FILE_MAGIC {
+        return None;
+    }
+    let binary_format = u32::from_le_bytes(contents[4..8].try_into().unwrap());
+    let material_length = usize::try_from(u64::from_le_bytes(contents[8..16].try_into().unwrap())).ok()?;
+    let binary_offset = 16usize.checked_add(material_length)?;
+    if contents.len() < binary_offset || contents[16..binary_offset] != key.material[..] {
+        return None;
+    }
+    return Some((binary_format, contents.split_off(binary_offset)));
+}
+
+// FNV-1a is used instead of `DefaultHasher` because the keys have to stay stable across Rust versions.
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    return hash;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_key(driver: &str, source: &str) -> ProgramCacheKey {
+        let vertex = ShaderSource::new("Vertex", source);
+        return ProgramCacheKey::new(&["Vendor", driver, "4.6"], &[(&vertex, gl::VERTEX_SHADER)]);
+    }
+
+    #[test]
+    fn cache_files_round_trip() {
+        let key = create_key("Renderer", "void main() {}");
+        let contents = encode_cache_file(&key, 7, &[1, 2, 3]);
+        assert_eq!(decode_cache_file(contents, &key), Some((7, vec![1, 2, 3])));
+    }
+
+    #[test]
+    fn cache_files_of_other_keys_are_rejected() {
+        let key = create_key("Renderer", "void main() {}");
+        let contents = encode_cache_file(&key, 7, &[1, 2, 3]);
+        // Same file name, as if the hashes had collided.
+        let mut other_key = create_key("Other Renderer", "void main() {}");
+        other_key.hash = key.get_hash();
+        assert_eq!(decode_cache_file(contents.clone(), &other_key), None);
+        assert_eq!(decode_cache_file(contents[..20].to_vec(), &key), None);
+        assert_ne!(key.get_hash(), create_key("Renderer", "void main() { }").get_hash());
+    }
+}
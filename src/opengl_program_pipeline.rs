@@ -0,0 +1,90 @@
+use crate::gl_capabilities;
+use crate::gl_context::{ ContextHandle, GLObject };
+use crate::gl_debug;
+use crate::gl_state;
+use crate::opengl_shader::{ OpenGLShader };
+
+use gl::types::*;
+
+pub struct OpenGLProgramPipeline {
+    id: GLuint,
+    context: ContextHandle,
+}
+
+impl OpenGLProgramPipeline {
+    pub fn new() -> OpenGLProgramPipeline {
+        assert!(gl_capabilities::get().has_separate_shader_objects(), "Program pipelines need GL 4.1, GLES 3.1 or GL_ARB_separate_shader_objects!");
+        unsafe {
+            let mut program_pipeline = 0;
+            gl::GenProgramPipelines(1, &mut program_pipeline);
+            gl_debug::check_errors("Creating a program pipeline");
+
+            return OpenGLProgramPipeline {
+                id: program_pipeline,
+                context: ContextHandle::adopt(GLObject::ProgramPipeline(program_pipeline)),
+            };
+        }
+    }
+
+    pub fn use_program_stages(&mut self, shader: &OpenGLShader) {
+        assert!(shader.is_separable(), "Only separable shaders can be used in a program pipeline!");
+        unsafe {
+            gl::UseProgramStages(self.id, shader.get_stages(), shader.get_id());
+        }
+        gl_debug::check_errors("Setting program pipeline stages");
+    }
+
+    pub fn clear_program_stages(&mut self, stages: GLbitfield) {
+        unsafe {
+            gl::UseProgramStages(self.id, stages, 0);
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        unsafe {
+            gl::ValidateProgramPipeline(self.id);
+
+            let mut pipeline_valid = gl::FALSE as GLint;
+            gl::GetProgramPipelineiv(self.id, gl::VALIDATE_STATUS, &mut pipeline_valid);
+            if pipeline_valid == gl::TRUE as GLint {
+                return Ok(());
+            }
+
+            let mut info_log_length = 0;
+            gl::GetProgramPipelineiv(self.id, gl::INFO_LOG_LENGTH, &mut info_log_length);
+            if info_log_length <= 1 {
+                return Err(String::new());
+            }
+
+            let mut info_log = vec![0u8; info_log_length as usize];
+            gl::GetProgramPipelineInfoLog(self.id, info_log_length, std::ptr::null_mut(), info_log.as_mut_ptr() as *mut GLchar);
+            info_log.truncate(info_log_length as usize - 1);
+            return Err(String::from_utf8_lossy(&info_log).into_owned());
+        }
+    }
+
+    pub fn set_debug_label(&self, label: &str) {
+        gl_debug::ensure_object_exists(gl::PROGRAM_PIPELINE, self.id);
+        gl_debug::set_object_label(gl::PROGRAM_PIPELINE, self.id, label);
+    }
+
+    pub fn bind(&self) {
+        // A program bound with glUseProgram takes precedence over the pipeline.
+        gl_state::use_program(0);
+        unsafe {
+            gl::BindProgramPipeline(self.id);
+        }
+    }
+
+    pub fn un_bind(&self) {
+        unsafe {
+            gl::BindProgramPipeline(0);
+        }
+    }
+}
+
+impl Drop for OpenGLProgramPipeline {
+    fn drop(&mut self) {
+        self.context.delete(GLObject::ProgramPipeline(self.id));
+    }
+}
@@ -0,0 +1,273 @@
+use crate::gl_capabilities;
+use crate::gl_context::{ ContextHandle, GLObject };
+use crate::gl_debug;
+use crate::gl_extensions;
+
+use gl::types::*;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum QueryType {
+    // Nanoseconds of GPU time between `begin` and `end`. These can't be nested, use timestamps for that.
+    TimeElapsed,
+    // The GPU clock in nanoseconds once every earlier command has finished, written by `record_timestamp`.
+    Timestamp,
+    SamplesPassed,
+    // Occlusion queries that only answer whether anything passed, which lets the GPU stop counting early.
+    AnySamplesPassed,
+    // May report false positives, in exchange for being even cheaper.
+    AnySamplesPassedConservative,
+    PrimitivesGenerated,
+}
+
+impl QueryType {
+    pub fn get_gl_target(self) -> GLenum {
+        match self {
+            QueryType::TimeElapsed => gl::TIME_ELAPSED,
+            QueryType::Timestamp => gl::TIMESTAMP,
+            QueryType::SamplesPassed => gl::SAMPLES_PASSED,
+            QueryType::AnySamplesPassed => gl::ANY_SAMPLES_PASSED,
+            QueryType::AnySamplesPassedConservative => gl::ANY_SAMPLES_PASSED_CONSERVATIVE,
+            QueryType::PrimitivesGenerated => gl::PRIMITIVES_GENERATED,
+        }
+    }
+
+    // Only timer results need 64 bits, and only timer queries can read them that way on GLES.
+    pub fn is_timer_query(self) -> bool {
+        matches!(self, QueryType::TimeElapsed | QueryType::Timestamp)
+    }
+
+    pub fn is_occlusion_query(self) -> bool {
+        matches!(self, QueryType::SamplesPassed | QueryType::AnySamplesPassed | QueryType::AnySamplesPassedConservative)
+    }
+
+    // GLES only has timer queries through EXT_disjoint_timer_query, and counts no exact samples at all.
+    pub fn is_supported(self) -> bool {
+        let capabilities = gl_capabilities::get();
+        match self {
+            QueryType::TimeElapsed | QueryType::Timestamp => capabilities.has_timer_queries(),
+            QueryType::SamplesPassed => !capabilities.is_gles(),
+            QueryType::AnySamplesPassed => true,
+            QueryType::AnySamplesPassedConservative => capabilities.is_version_at_least((4, 3), (3, 0)) || capabilities.has_extension("GL_ARB_ES3_compatibility"),
+            QueryType::PrimitivesGenerated => capabilities.is_version_at_least((3, 0), (3, 2)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ConditionalRenderMode {
+    // The GPU waits for the query result before deciding.
+    Wait,
+    // The GPU may draw anyway if the result isn't ready yet.
+    NoWait,
+    ByRegionWait,
+    ByRegionNoWait,
+}
+
+impl ConditionalRenderMode {
+    pub fn get_gl_mode(self) -> GLenum {
+        match self {
+            ConditionalRenderMode::Wait => gl::QUERY_WAIT,
+            ConditionalRenderMode::NoWait => gl::QUERY_NO_WAIT,
+            ConditionalRenderMode::ByRegionWait => gl::QUERY_BY_REGION_WAIT,
+            ConditionalRenderMode::ByRegionNoWait => gl::QUERY_BY_REGION_NO_WAIT,
+        }
+    }
+}
+
+pub struct OpenGLQuery {
+    id: GLuint,
+    context: ContextHandle,
+    query_type: QueryType,
+    active: bool,
+    issued: bool,
+    // Set once the query has been issued, cleared when its result is read.
+    pending: bool,
+}
+
+impl OpenGLQuery {
+    pub fn new(query_type: QueryType) -> OpenGLQuery {
+        assert!(query_type.is_supported(), "{:?} queries are not supported by this context!", query_type);
+        unsafe {
+            let mut query = 0;
+            if gl_extensions::has_direct_state_access() {
+                gl::CreateQueries(query_type.get_gl_target(), 1, &mut query);
+            } else {
+                gl::GenQueries(1, &mut query);
+            }
+            gl_debug::check_errors("Creating a query");
+
+            return OpenGLQuery {
+                id: query,
+                context: ContextHandle::adopt(GLObject::Query(query)),
+                query_type,
+                active: false,
+                issued: false,
+                pending: false,
+            };
+        }
+    }
+
+    pub fn begin(&mut self) {
+        assert!(self.query_type != QueryType::Timestamp, "Timestamp queries are recorded with record_timestamp!");
+        assert!(!self.active, "The query has already begun!");
+        unsafe {
+            gl::BeginQuery(self.query_type.get_gl_target(), self.id);
+        }
+        gl_debug::check_errors("Beginning a query");
+        self.active = true;
+    }
+
+    pub fn end(&mut self) {
+        assert!(self.active, "The query hasn't begun!");
+        unsafe {
+            gl::EndQuery(self.query_type.get_gl_target());
+        }
+        self.active = false;
+        self.issued = true;
+        self.pending = true;
+    }
+
+    pub fn record_timestamp(&mut self) {
+        assert!(self.query_type == QueryType::Timestamp, "Only timestamp queries can record a timestamp!");
+        unsafe {
+            gl::QueryCounter(self.id, gl::TIMESTAMP);
+        }
+        self.issued = true;
+        self.pending = true;
+    }
+
+    // Has been issued at least once, so its result can drive conditional rendering.
+    pub fn is_issued(&self) -> bool {
+        self.issued
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending
+    }
+
+    pub fn is_result_available(&self) -> bool {
+        if !self.pending {
+            return false;
+        }
+
+        let mut available = gl::FALSE as GLuint;
+        unsafe {
+            gl::GetQueryObjectuiv(self.id, gl::QUERY_RESULT_AVAILABLE, &mut available);
+        }
+        return available == gl::TRUE as GLuint;
+    }
+
+    // Never stalls; `None` while the GPU hasn't got to the query yet or when it was never issued.
+    pub fn try_get_result(&mut self) -> Option<u64> {
+        if !self.is_result_available() {
+            return None;
+        }
+        return Some(self.get_result());
+    }
+
+    // Waits for the GPU to finish the query, which stalls the pipeline if it hasn't.
+    pub fn get_result(&mut self) -> u64 {
+        assert!(self.pending, "The query has no result to read!");
+        let mut result = 0;
+        unsafe {
+            if self.query_type.is_timer_query() {
+                gl::GetQueryObjectui64v(self.id, gl::QUERY_RESULT, &mut result);
+            } else {
+                let mut count = 0;
+                gl::GetQueryObjectuiv(self.id, gl::QUERY_RESULT, &mut count);
+                result = count as u64;
+            }
+        }
+        self.pending = false;
+        return result;
+    }
+
+    // Draws until the guard is dropped are discarded by the GPU when the query saw no samples, without the CPU
+    // ever reading the result.
+    pub fn begin_conditional_render(&self, mode: ConditionalRenderMode) -> ConditionalRender {
+        assert!(self.query_type.is_occlusion_query(), "Only occlusion queries can drive conditional rendering!");
+        assert!(self.issued && !self.active, "The query has to be issued before it can drive conditional rendering!");
+        assert!(gl_capabilities::get().has_conditional_render(), "Conditional rendering is not supported by this context!");
+        unsafe {
+            gl::BeginConditionalRender(self.id, mode.get_gl_mode());
+        }
+        return ConditionalRender { _private: () };
+    }
+
+    pub fn get_id(&self) -> GLuint {
+        self.id
+    }
+
+    pub fn get_type(&self) -> QueryType {
+        self.query_type
+    }
+
+    // Without direct state access a query only becomes an object once it has been issued, so earlier labels are dropped.
+    pub fn set_debug_label(&self, label: &str) {
+        if unsafe { gl::IsQuery(self.id) } == gl::TRUE {
+            gl_debug::set_object_label(gl::QUERY, self.id, label);
+        }
+    }
+}
+
+impl Drop for OpenGLQuery {
+    fn drop(&mut self) {
+        self.context.delete(GLObject::Query(self.id));
+    }
+}
+
+#[must_use = "Conditional rendering ends as soon as the guard is dropped"]
+pub struct ConditionalRender {
+    _private: (),
+}
+
+impl Drop for ConditionalRender {
+    fn drop(&mut self) {
+        unsafe {
+            gl::EndConditionalRender();
+        }
+    }
+}
+
+// Measures something every frame without waiting for the GPU: each frame issues into one query while the other,
+// issued a frame earlier, is read back. If the GPU falls more than a frame behind, that frame's result is skipped.
+pub struct DoubleBufferedQuery {
+    queries: [OpenGLQuery; 2],
+    current: usize,
+    latest_result: Option<u64>,
+}
+
+impl DoubleBufferedQuery {
+    pub fn new(query_type: QueryType) -> DoubleBufferedQuery {
+        DoubleBufferedQuery {
+            queries: [OpenGLQuery::new(query_type), OpenGLQuery::new(query_type)],
+            current: 0,
+            latest_result: None,
+        }
+    }
+
+    pub fn begin(&mut self) {
+        self.queries[self.current].begin();
+    }
+
+    pub fn end(&mut self) {
+        self.queries[self.current].end();
+    }
+
+    pub fn record_timestamp(&mut self) {
+        self.queries[self.current].record_timestamp();
+    }
+
+    // Call once per frame after the query has been issued.
+    pub fn swap(&mut self) {
+        self.current = 1 - self.current;
+        if let Some(result) = self.queries[self.current].try_get_result() {
+            self.latest_result = Some(result);
+        }
+    }
+
+    // The newest result the GPU has delivered, usually from the previous frame.
+    pub fn get_latest_result(&self) -> Option<u64> {
+        self.latest_result
+    }
+}
@@ -0,0 +1,78 @@
+use crate::gl_capabilities;
+use crate::gl_context::{ ContextHandle, GLObject };
+use crate::gl_debug;
+use crate::opengl_texture::{ TextureFormat };
+
+use gl::types::*;
+
+// Renderbuffers can't be sampled, so they are the cheaper choice for depth and stencil buffers that are only tested against.
+pub struct OpenGLRenderbuffer {
+    id: GLuint,
+    context: ContextHandle,
+    width: u32,
+    height: u32,
+    format: TextureFormat,
+    samples: u32,
+}
+
+impl OpenGLRenderbuffer {
+    pub fn new(width: u32, height: u32, format: TextureFormat) -> OpenGLRenderbuffer {
+        OpenGLRenderbuffer::with_samples(width, height, format, 0)
+    }
+
+    // A sample count of 0 gives a regular single-sample renderbuffer. Counts above GL_MAX_SAMPLES are clamped.
+    pub fn with_samples(width: u32, height: u32, format: TextureFormat, samples: u32) -> OpenGLRenderbuffer {
+        let samples = std::cmp::min(samples, get_max_samples());
+        unsafe {
+            let mut renderbuffer = 0;
+            gl::GenRenderbuffers(1, &mut renderbuffer);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, renderbuffer);
+            gl::RenderbufferStorageMultisample(gl::RENDERBUFFER, samples as GLsizei, format.get_internal_format(), width as GLsizei, height as GLsizei);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+            gl_debug::check_errors("Creating a renderbuffer");
+
+            return OpenGLRenderbuffer {
+                id: renderbuffer,
+                context: ContextHandle::adopt(GLObject::Renderbuffer(renderbuffer)),
+                width,
+                height,
+                format,
+                samples,
+            };
+        }
+    }
+
+    pub fn get_id(&self) -> GLuint {
+        self.id
+    }
+
+    pub fn set_debug_label(&self, label: &str) {
+        gl_debug::set_object_label(gl::RENDERBUFFER, self.id, label);
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn get_format(&self) -> TextureFormat {
+        self.format
+    }
+
+    pub fn get_samples(&self) -> u32 {
+        self.samples
+    }
+}
+
+impl Drop for OpenGLRenderbuffer {
+    fn drop(&mut self) {
+        self.context.delete(GLObject::Renderbuffer(self.id));
+    }
+}
+
+pub fn get_max_samples() -> u32 {
+    gl_capabilities::get().get_limits().max_samples
+}
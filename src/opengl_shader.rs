@@ -1,86 +1,547 @@
+use crate::shader_diagnostics::{ self, ShaderDiagnostic };
+use crate::opengl_program_cache::{ OpenGLProgramCache };
+use crate::gl_capabilities;
+use crate::gl_context::{ ContextHandle, GLObject };
+use crate::gl_debug;
+use crate::gl_extensions;
+use crate::gl_state;
+
 use gl::types::*;
 use std::ffi::{ CString };
 
+#[derive(Clone, Copy)]
+pub struct ShaderSource<'a> {
+    pub name: &'a str,
+    pub source: &'a str,
+    pub defines: &'a [(&'a str, &'a str)],
+}
+
+impl<'a> ShaderSource<'a> {
+    pub fn new(name: &'a str, source: &'a str) -> ShaderSource<'a> {
+        ShaderSource {
+            name,
+            source,
+            defines: &[],
+        }
+    }
+
+    pub fn with_defines(self, defines: &'a [(&'a str, &'a str)]) -> ShaderSource<'a> {
+        ShaderSource {
+            defines,
+            ..self
+        }
+    }
+
+    pub fn get_preprocessed_source(&self) -> String {
+        if self.defines.is_empty() {
+            return self.source.to_string();
+        }
+
+        let mut defines = String::new();
+        for (name, value) in self.defines {
+            defines.push_str(&format!("#define {} {}\n", name, value));
+        }
+
+        // The defines go after `#version`, followed by a `#line` so diagnostics still point at the original lines.
+        let mut output = String::with_capacity(self.source.len() + defines.len() + 16);
+        let mut inserted = false;
+        for (index, line) in self.source.lines().enumerate() {
+            output.push_str(line);
+            output.push('\n');
+            if !inserted && line.trim_start().starts_with("#version") {
+                output.push_str(&defines);
+                output.push_str(&format!("#line {}\n", index + 2));
+                inserted = true;
+            }
+        }
+        if !inserted {
+            return format!("{}#line 1\n{}", defines, self.source);
+        }
+        return output;
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct SpirvModule<'a> {
+    pub name: &'a str,
+    pub binary: &'a [u8],
+    pub entry_point: &'a str,
+    pub specialization_constants: &'a [(u32, u32)],
+}
+
+impl<'a> SpirvModule<'a> {
+    pub fn new(name: &'a str, binary: &'a [u8]) -> SpirvModule<'a> {
+        SpirvModule {
+            name,
+            binary,
+            entry_point: "main",
+            specialization_constants: &[],
+        }
+    }
+
+    pub fn with_entry_point(self, entry_point: &'a str) -> SpirvModule<'a> {
+        SpirvModule {
+            entry_point,
+            ..self
+        }
+    }
+
+    pub fn with_specialization_constants(self, specialization_constants: &'a [(u32, u32)]) -> SpirvModule<'a> {
+        SpirvModule {
+            specialization_constants,
+            ..self
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Compile {
+        stage: GLenum,
+        source_name: String,
+        source: String,
+        info_log: String,
+        diagnostics: Vec<ShaderDiagnostic>,
+    },
+    Link(String),
+    Spirv(String),
+}
+
+impl std::fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ShaderError::Compile { stage, source_name, source, info_log, diagnostics } => {
+                writeln!(f, "{} Shader Compilation Failed:", get_stage_name(*stage))?;
+                if diagnostics.is_empty() {
+                    return write!(f, "{}", info_log);
+                }
+                for diagnostic in diagnostics {
+                    write!(f, "{}", shader_diagnostics::render_diagnostic(diagnostic, source_name, source))?;
+                }
+                return Ok(());
+            }
+
+            ShaderError::Link(info_log) => write!(f, "Shader Linking Failed:\n{}", info_log),
+
+            ShaderError::Spirv(message) => write!(f, "SPIR-V Shader Loading Failed:\n{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+pub fn get_stage_name(stage: GLenum) -> &'static str {
+    match stage {
+        gl::VERTEX_SHADER => "Vertex",
+        gl::FRAGMENT_SHADER => "Fragment",
+        gl::GEOMETRY_SHADER => "Geometry",
+        gl::TESS_CONTROL_SHADER => "Tessellation Control",
+        gl::TESS_EVALUATION_SHADER => "Tessellation Evaluation",
+        gl::COMPUTE_SHADER => "Compute",
+        _ => "Unknown",
+    }
+}
+
+pub fn get_stage_bit(stage: GLenum) -> GLbitfield {
+    match stage {
+        gl::VERTEX_SHADER => gl::VERTEX_SHADER_BIT,
+        gl::FRAGMENT_SHADER => gl::FRAGMENT_SHADER_BIT,
+        gl::GEOMETRY_SHADER => gl::GEOMETRY_SHADER_BIT,
+        gl::TESS_CONTROL_SHADER => gl::TESS_CONTROL_SHADER_BIT,
+        gl::TESS_EVALUATION_SHADER => gl::TESS_EVALUATION_SHADER_BIT,
+        gl::COMPUTE_SHADER => gl::COMPUTE_SHADER_BIT,
+        _ => panic!("Unknown shader stage {:#x}!", stage),
+    }
+}
+
 pub struct OpenGLShader {
     id: GLuint,
+    context: ContextHandle,
+    stages: GLbitfield,
+    separable: bool,
 }
 
 impl OpenGLShader {
     pub fn new(vertex_source: &str, fragment_source: &str) -> OpenGLShader {
+        let vertex = ShaderSource::new("vertex shader", vertex_source);
+        let fragment = ShaderSource::new("fragment shader", fragment_source);
+        match OpenGLShader::try_new(&vertex, &fragment) {
+            Ok(shader) => shader,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    pub fn try_new(vertex: &ShaderSource, fragment: &ShaderSource) -> Result<OpenGLShader, ShaderError> {
+        unsafe {
+            return OpenGLShader::link_program(vertex, fragment, false).map(OpenGLShader::from_linked_program);
+        }
+    }
+
+    pub fn try_new_cached(vertex: &ShaderSource, fragment: &ShaderSource, cache: &OpenGLProgramCache) -> Result<OpenGLShader, ShaderError> {
+        if !OpenGLProgramCache::is_supported() {
+            return OpenGLShader::try_new(vertex, fragment);
+        }
+
+        let key = cache.get_key(&[(vertex, gl::VERTEX_SHADER), (fragment, gl::FRAGMENT_SHADER)]);
+        unsafe {
+            if let Some((binary_format, binary)) = cache.load(&key) {
+                let shader_program = gl::CreateProgram();
+                gl::ProgramBinary(shader_program, binary_format, binary.as_ptr() as *const GLvoid, binary.len() as GLsizei);
+
+                let mut shader_linked = gl::FALSE as GLint;
+                gl::GetProgramiv(shader_program, gl::LINK_STATUS, &mut shader_linked);
+                if shader_linked == gl::TRUE as GLint {
+                    return Ok(OpenGLShader::from_linked_program(shader_program));
+                }
+
+                gl::DeleteProgram(shader_program);
+                cache.remove(&key);
+            }
+
+            let shader_program = OpenGLShader::link_program(vertex, fragment, true)?;
+
+            let mut binary_length = 0;
+            gl::GetProgramiv(shader_program, gl::PROGRAM_BINARY_LENGTH, &mut binary_length);
+            if binary_length > 0 {
+                let mut binary = vec![0u8; binary_length as usize];
+                let mut binary_format = 0;
+                gl::GetProgramBinary(shader_program, binary_length, &mut binary_length, &mut binary_format, binary.as_mut_ptr() as *mut GLvoid);
+                binary.truncate(binary_length as usize);
+
+                if let Err(error) = cache.store(&key, binary_format, &binary) {
+                    log::warn!(target: "gl", "Failed to write the program binary cache: {}", error);
+                }
+            }
+
+            return Ok(OpenGLShader::from_linked_program(shader_program));
+        }
+    }
+
+    pub fn try_new_spirv(vertex: &SpirvModule, fragment: &SpirvModule) -> Result<OpenGLShader, ShaderError> {
+        if !gl_capabilities::get().has_spir_v() || !gl_extensions::is_specialize_shader_loaded() {
+            return Err(ShaderError::Spirv("GL_ARB_gl_spirv is not supported by this context".to_string()));
+        }
+
+        unsafe {
+            let vertex_shader = OpenGLShader::create_spirv_shader(vertex, gl::VERTEX_SHADER)?;
+            let fragment_shader = match OpenGLShader::create_spirv_shader(fragment, gl::FRAGMENT_SHADER) {
+                Ok(fragment_shader) => fragment_shader,
+                Err(error) => {
+                    gl::DeleteShader(vertex_shader);
+                    return Err(error);
+                }
+            };
+
+            return OpenGLShader::link_shaders(vertex_shader, fragment_shader, false).map(OpenGLShader::from_linked_program);
+        }
+    }
+
+    pub fn try_new_separable(stage: GLenum, source: &ShaderSource) -> Result<OpenGLShader, ShaderError> {
+        if !gl_capabilities::get().has_separate_shader_objects() {
+            return Err(ShaderError::Link("Separable programs need GL 4.1, GLES 3.1 or GL_ARB_separate_shader_objects".to_string()));
+        }
+
         unsafe {
-            let vertex_shader = OpenGLShader::create_shader(vertex_source, gl::VERTEX_SHADER);
-            let fragment_shader = OpenGLShader::create_shader(fragment_source, gl::FRAGMENT_SHADER);
+            let shader = OpenGLShader::create_shader(source, stage)?;
 
             let shader_program = gl::CreateProgram();
-            gl::AttachShader(shader_program, vertex_shader);
-            gl::AttachShader(shader_program, fragment_shader);
+            gl::ProgramParameteri(shader_program, gl::PROGRAM_SEPARABLE, gl::TRUE as GLint);
+            gl::AttachShader(shader_program, shader);
             gl::LinkProgram(shader_program);
+            gl_debug::check_errors("Linking a program");
 
             let mut shader_linked = gl::FALSE as GLint;
             gl::GetProgramiv(shader_program, gl::LINK_STATUS, &mut shader_linked);
+
+            gl::DetachShader(shader_program, shader);
+            gl::DeleteShader(shader);
+
             if shader_linked != gl::TRUE as GLint {
-                let mut info_log_length = 0;
-                gl::GetProgramiv(shader_program, gl::INFO_LOG_LENGTH, &mut info_log_length);
+                let info_log = OpenGLShader::get_program_info_log(shader_program);
+                gl::DeleteProgram(shader_program);
+                return Err(ShaderError::Link(info_log));
+            }
+
+            return Ok(OpenGLShader {
+                id: shader_program,
+                context: ContextHandle::adopt(GLObject::Program(shader_program)),
+                stages: get_stage_bit(stage),
+                separable: true,
+            });
+        }
+    }
+
+    fn from_linked_program(id: GLuint) -> OpenGLShader {
+        OpenGLShader {
+            id,
+            context: ContextHandle::adopt(GLObject::Program(id)),
+            stages: gl::VERTEX_SHADER_BIT | gl::FRAGMENT_SHADER_BIT,
+            separable: false,
+        }
+    }
+
+    pub fn get_id(&self) -> GLuint {
+        self.id
+    }
+
+    pub fn set_debug_label(&self, label: &str) {
+        gl_debug::set_object_label(gl::PROGRAM, self.id, label);
+    }
+
+    pub fn get_stages(&self) -> GLbitfield {
+        self.stages
+    }
+
+    pub fn is_separable(&self) -> bool {
+        self.separable
+    }
 
-                let mut info_log = Vec::with_capacity(info_log_length as usize);
-                info_log.set_len(info_log_length as usize - 1);
-                gl::GetProgramInfoLog(shader_program, info_log_length, std::ptr::null_mut(), info_log.as_mut_ptr() as *mut GLchar);
-                panic!("Shader Linking Failed:\n{}", std::str::from_utf8(&info_log).unwrap());
+    unsafe fn link_program(vertex: &ShaderSource, fragment: &ShaderSource, retrievable: bool) -> Result<GLuint, ShaderError> {
+        let vertex_shader = OpenGLShader::create_shader(vertex, gl::VERTEX_SHADER)?;
+        let fragment_shader = match OpenGLShader::create_shader(fragment, gl::FRAGMENT_SHADER) {
+            Ok(fragment_shader) => fragment_shader,
+            Err(error) => {
+                gl::DeleteShader(vertex_shader);
+                return Err(error);
             }
+        };
 
-            gl::DetachShader(shader_program, vertex_shader);
-            gl::DeleteShader(vertex_shader);
+        return OpenGLShader::link_shaders(vertex_shader, fragment_shader, retrievable);
+    }
 
-            gl::DetachShader(shader_program, fragment_shader);
-            gl::DeleteShader(fragment_shader);
+    unsafe fn link_shaders(vertex_shader: GLuint, fragment_shader: GLuint, retrievable: bool) -> Result<GLuint, ShaderError> {
+        let shader_program = gl::CreateProgram();
+        if retrievable {
+            gl::ProgramParameteri(shader_program, gl::PROGRAM_BINARY_RETRIEVABLE_HINT, gl::TRUE as GLint);
+        }
+        gl::AttachShader(shader_program, vertex_shader);
+        gl::AttachShader(shader_program, fragment_shader);
+        gl::LinkProgram(shader_program);
+        gl_debug::check_errors("Linking a program");
 
-            return OpenGLShader {
-                id: shader_program
-            };
+        let mut shader_linked = gl::FALSE as GLint;
+        gl::GetProgramiv(shader_program, gl::LINK_STATUS, &mut shader_linked);
+
+        gl::DetachShader(shader_program, vertex_shader);
+        gl::DeleteShader(vertex_shader);
+
+        gl::DetachShader(shader_program, fragment_shader);
+        gl::DeleteShader(fragment_shader);
+
+        if shader_linked != gl::TRUE as GLint {
+            let info_log = OpenGLShader::get_program_info_log(shader_program);
+            gl::DeleteProgram(shader_program);
+            return Err(ShaderError::Link(info_log));
         }
+
+        return Ok(shader_program);
     }
 
     pub fn bind(&self) {
-        unsafe { gl::UseProgram(self.id); }
+        gl_state::use_program(self.id);
     }
 
     pub fn un_bind(&self) {
-        unsafe { gl::UseProgram(0); }
+        gl_state::use_program(0);
     }
 
+    // Without separate shader objects there is no glProgramUniform*, so setting a uniform binds the shader and leaves it bound.
     pub fn set_integer(&self, name: &str, value: i32) {
-        unsafe {
-            let location = gl::GetUniformLocation(self.id, name.as_ptr() as *const _);
-            if location != -1 {
-                gl::ProgramUniform1i(self.id, location, value);
+        if let Some(location) = self.get_uniform_location(name) {
+            unsafe {
+                if gl_capabilities::get().has_separate_shader_objects() {
+                    gl::ProgramUniform1i(self.id, location, value);
+                } else {
+                    self.bind();
+                    gl::Uniform1i(location, value);
+                }
             }
         }
     }
 
-    unsafe fn create_shader(shader_source: &str, shader_type: GLenum) -> GLuint {
+    pub fn set_float(&self, name: &str, value: f32) {
+        if let Some(location) = self.get_uniform_location(name) {
+            unsafe {
+                if gl_capabilities::get().has_separate_shader_objects() {
+                    gl::ProgramUniform1f(self.id, location, value);
+                } else {
+                    self.bind();
+                    gl::Uniform1f(location, value);
+                }
+            }
+        }
+    }
+
+    pub fn set_float2(&self, name: &str, value: [f32; 2]) {
+        if let Some(location) = self.get_uniform_location(name) {
+            unsafe {
+                if gl_capabilities::get().has_separate_shader_objects() {
+                    gl::ProgramUniform2f(self.id, location, value[0], value[1]);
+                } else {
+                    self.bind();
+                    gl::Uniform2f(location, value[0], value[1]);
+                }
+            }
+        }
+    }
+
+    pub fn set_float3(&self, name: &str, value: [f32; 3]) {
+        if let Some(location) = self.get_uniform_location(name) {
+            unsafe {
+                if gl_capabilities::get().has_separate_shader_objects() {
+                    gl::ProgramUniform3f(self.id, location, value[0], value[1], value[2]);
+                } else {
+                    self.bind();
+                    gl::Uniform3f(location, value[0], value[1], value[2]);
+                }
+            }
+        }
+    }
+
+    pub fn set_float4(&self, name: &str, value: [f32; 4]) {
+        if let Some(location) = self.get_uniform_location(name) {
+            unsafe {
+                if gl_capabilities::get().has_separate_shader_objects() {
+                    gl::ProgramUniform4f(self.id, location, value[0], value[1], value[2], value[3]);
+                } else {
+                    self.bind();
+                    gl::Uniform4f(location, value[0], value[1], value[2], value[3]);
+                }
+            }
+        }
+    }
+
+    // Column-major, the same layout GLSL uses.
+    pub fn set_matrix4(&self, name: &str, value: &[f32; 16]) {
+        if let Some(location) = self.get_uniform_location(name) {
+            unsafe {
+                if gl_capabilities::get().has_separate_shader_objects() {
+                    gl::ProgramUniformMatrix4fv(self.id, location, 1, gl::FALSE, value.as_ptr());
+                } else {
+                    self.bind();
+                    gl::UniformMatrix4fv(location, 1, gl::FALSE, value.as_ptr());
+                }
+            }
+        }
+    }
+
+    fn get_uniform_location(&self, name: &str) -> Option<GLint> {
+        let c_string_name = CString::new(name).unwrap();
+        let location = unsafe { gl::GetUniformLocation(self.id, c_string_name.as_ptr()) };
+        if location == -1 {
+            return None;
+        }
+        return Some(location);
+    }
+
+    unsafe fn create_shader(shader_source: &ShaderSource, shader_type: GLenum) -> Result<GLuint, ShaderError> {
         let shader = gl::CreateShader(shader_type);
-        let c_string_shader_source = CString::new(shader_source.as_bytes()).unwrap();
+        let translated_source = gl_capabilities::get().translate_shader_source(&shader_source.get_preprocessed_source(), shader_type);
+        let c_string_shader_source = CString::new(translated_source).unwrap();
         gl::ShaderSource(shader, 1, &c_string_shader_source.as_ptr(), std::ptr::null());
         gl::CompileShader(shader);
+        gl_debug::check_errors("Compiling a shader");
 
         let mut shader_compiled = gl::FALSE as GLint;
         gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut shader_compiled);
+
+        let info_log = OpenGLShader::get_shader_info_log(shader);
+        let diagnostics = shader_diagnostics::parse_info_log(&info_log);
+
         if shader_compiled != gl::TRUE as GLint {
-            let mut info_log_length = 0;
-            gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut info_log_length);
+            gl::DeleteShader(shader);
+            return Err(ShaderError::Compile {
+                stage: shader_type,
+                source_name: shader_source.name.to_string(),
+                source: shader_source.source.to_string(),
+                info_log,
+                diagnostics,
+            });
+        }
+
+        for diagnostic in &diagnostics {
+            eprint!("{}", shader_diagnostics::render_diagnostic(diagnostic, shader_source.name, shader_source.source));
+        }
+
+        return Ok(shader);
+    }
+
+    unsafe fn create_spirv_shader(module: &SpirvModule, shader_type: GLenum) -> Result<GLuint, ShaderError> {
+        const SPIRV_MAGIC_NUMBER: u32 = 0x07230203;
+        if !module.binary.len().is_multiple_of(4) || module.binary.len() < 20 {
+            return Err(ShaderError::Spirv(format!("{} is not a valid SPIR-V module", module.name)));
+        }
+        let magic_number = u32::from_le_bytes([module.binary[0], module.binary[1], module.binary[2], module.binary[3]]);
+        if magic_number != SPIRV_MAGIC_NUMBER {
+            return Err(ShaderError::Spirv(format!("{} has an invalid SPIR-V magic number {:#010x}", module.name, magic_number)));
+        }
+
+        let shader = gl::CreateShader(shader_type);
+        gl::ShaderBinary(
+            1,
+            &shader,
+            gl_extensions::SHADER_BINARY_FORMAT_SPIR_V,
+            module.binary.as_ptr() as *const GLvoid,
+            module.binary.len() as GLsizei,
+        );
+
+        let c_string_entry_point = CString::new(module.entry_point).unwrap();
+        let constant_indices: Vec<GLuint> = module.specialization_constants.iter().map(|(index, _)| *index).collect();
+        let constant_values: Vec<GLuint> = module.specialization_constants.iter().map(|(_, value)| *value).collect();
+        gl_extensions::SpecializeShader(
+            shader,
+            c_string_entry_point.as_ptr(),
+            constant_indices.len() as GLuint,
+            constant_indices.as_ptr(),
+            constant_values.as_ptr(),
+        );
+
+        let mut shader_compiled = gl::FALSE as GLint;
+        gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut shader_compiled);
+        if shader_compiled != gl::TRUE as GLint {
+            let info_log = OpenGLShader::get_shader_info_log(shader);
+            gl::DeleteShader(shader);
+            return Err(ShaderError::Compile {
+                stage: shader_type,
+                source_name: module.name.to_string(),
+                source: String::new(),
+                diagnostics: shader_diagnostics::parse_info_log(&info_log),
+                info_log,
+            });
+        }
+
+        return Ok(shader);
+    }
+
+    unsafe fn get_shader_info_log(shader: GLuint) -> String {
+        let mut info_log_length = 0;
+        gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut info_log_length);
+        if info_log_length <= 1 {
+            return String::new();
+        }
+
+        let mut info_log = vec![0u8; info_log_length as usize];
+        gl::GetShaderInfoLog(shader, info_log_length, std::ptr::null_mut(), info_log.as_mut_ptr() as *mut GLchar);
+        info_log.truncate(info_log_length as usize - 1);
+        return String::from_utf8_lossy(&info_log).into_owned();
+    }
 
-            let mut info_log = Vec::with_capacity(info_log_length as usize);
-            info_log.set_len(info_log_length as usize - 1);
-            gl::GetShaderInfoLog(shader, info_log_length, std::ptr::null_mut(), info_log.as_mut_ptr() as *mut GLchar);
-            panic!("{} Shader Compilation Failed:\n{}", if shader_type == gl::VERTEX_SHADER { "Vertex" } else { "Fragment" }, std::str::from_utf8(&info_log).unwrap());
+    unsafe fn get_program_info_log(program: GLuint) -> String {
+        let mut info_log_length = 0;
+        gl::GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut info_log_length);
+        if info_log_length <= 1 {
+            return String::new();
         }
 
-        return shader;
+        let mut info_log = vec![0u8; info_log_length as usize];
+        gl::GetProgramInfoLog(program, info_log_length, std::ptr::null_mut(), info_log.as_mut_ptr() as *mut GLchar);
+        info_log.truncate(info_log_length as usize - 1);
+        return String::from_utf8_lossy(&info_log).into_owned();
     }
 }
 
 impl Drop for OpenGLShader {
     fn drop(&mut self) {
-        unsafe { gl::DeleteProgram(self.id); }
+        self.context.delete(GLObject::Program(self.id));
     }
 }
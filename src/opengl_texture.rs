@@ -1,62 +1,665 @@
+use crate::gl_capabilities;
+use crate::gl_context::{ ContextHandle, GLObject };
+use crate::gl_debug;
+use crate::gl_extensions;
+use crate::gl_state;
+use crate::mipmap::{ self, MipmapFilter };
+
+use image::{ DynamicImage, RgbaImage };
+
 use gl::types::*;
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TextureFormat {
+    R8,
+    RG8,
+    RGB8,
+    RGBA8,
+    SRGB8,
+    SRGB8Alpha8,
+    R16,
+    RG16,
+    RGB16,
+    RGBA16,
+    R16F,
+    RG16F,
+    RGB16F,
+    RGBA16F,
+    R32F,
+    RG32F,
+    RGB32F,
+    RGBA32F,
+    Depth16,
+    Depth24,
+    Depth32F,
+    Depth24Stencil8,
+    Depth32FStencil8,
+}
+
+impl TextureFormat {
+    pub fn get_internal_format(self) -> GLenum {
+        match self {
+            TextureFormat::R8 => gl::R8,
+            TextureFormat::RG8 => gl::RG8,
+            TextureFormat::RGB8 => gl::RGB8,
+            TextureFormat::RGBA8 => gl::RGBA8,
+            TextureFormat::SRGB8 => gl::SRGB8,
+            TextureFormat::SRGB8Alpha8 => gl::SRGB8_ALPHA8,
+            TextureFormat::R16 => gl::R16,
+            TextureFormat::RG16 => gl::RG16,
+            TextureFormat::RGB16 => gl::RGB16,
+            TextureFormat::RGBA16 => gl::RGBA16,
+            TextureFormat::R16F => gl::R16F,
+            TextureFormat::RG16F => gl::RG16F,
+            TextureFormat::RGB16F => gl::RGB16F,
+            TextureFormat::RGBA16F => gl::RGBA16F,
+            TextureFormat::R32F => gl::R32F,
+            TextureFormat::RG32F => gl::RG32F,
+            TextureFormat::RGB32F => gl::RGB32F,
+            TextureFormat::RGBA32F => gl::RGBA32F,
+            TextureFormat::Depth16 => gl::DEPTH_COMPONENT16,
+            TextureFormat::Depth24 => gl::DEPTH_COMPONENT24,
+            TextureFormat::Depth32F => gl::DEPTH_COMPONENT32F,
+            TextureFormat::Depth24Stencil8 => gl::DEPTH24_STENCIL8,
+            TextureFormat::Depth32FStencil8 => gl::DEPTH32F_STENCIL8,
+        }
+    }
+
+    pub fn get_pixel_format(self) -> GLenum {
+        match self {
+            TextureFormat::R8 | TextureFormat::R16 | TextureFormat::R16F | TextureFormat::R32F => gl::RED,
+            TextureFormat::RG8 | TextureFormat::RG16 | TextureFormat::RG16F | TextureFormat::RG32F => gl::RG,
+            TextureFormat::RGB8 | TextureFormat::SRGB8 | TextureFormat::RGB16 | TextureFormat::RGB16F | TextureFormat::RGB32F => gl::RGB,
+            TextureFormat::RGBA8 | TextureFormat::SRGB8Alpha8 | TextureFormat::RGBA16 | TextureFormat::RGBA16F | TextureFormat::RGBA32F => gl::RGBA,
+            TextureFormat::Depth16 | TextureFormat::Depth24 | TextureFormat::Depth32F => gl::DEPTH_COMPONENT,
+            TextureFormat::Depth24Stencil8 | TextureFormat::Depth32FStencil8 => gl::DEPTH_STENCIL,
+        }
+    }
+
+    pub fn get_pixel_type(self) -> GLenum {
+        match self {
+            TextureFormat::R8 | TextureFormat::RG8 | TextureFormat::RGB8 | TextureFormat::RGBA8 => gl::UNSIGNED_BYTE,
+            TextureFormat::SRGB8 | TextureFormat::SRGB8Alpha8 => gl::UNSIGNED_BYTE,
+            TextureFormat::R16 | TextureFormat::RG16 | TextureFormat::RGB16 | TextureFormat::RGBA16 => gl::UNSIGNED_SHORT,
+            TextureFormat::R16F | TextureFormat::RG16F | TextureFormat::RGB16F | TextureFormat::RGBA16F => gl::FLOAT,
+            TextureFormat::R32F | TextureFormat::RG32F | TextureFormat::RGB32F | TextureFormat::RGBA32F => gl::FLOAT,
+            TextureFormat::Depth16 => gl::UNSIGNED_SHORT,
+            TextureFormat::Depth24 => gl::UNSIGNED_INT,
+            TextureFormat::Depth32F => gl::FLOAT,
+            TextureFormat::Depth24Stencil8 => gl::UNSIGNED_INT_24_8,
+            TextureFormat::Depth32FStencil8 => gl::FLOAT_32_UNSIGNED_INT_24_8_REV,
+        }
+    }
+
+    pub fn get_pixel_size(self) -> usize {
+        match self {
+            TextureFormat::R8 => 1,
+            TextureFormat::RG8 => 2,
+            TextureFormat::RGB8 | TextureFormat::SRGB8 => 3,
+            TextureFormat::RGBA8 | TextureFormat::SRGB8Alpha8 => 4,
+            TextureFormat::R16 => 2,
+            TextureFormat::RG16 => 4,
+            TextureFormat::RGB16 => 6,
+            TextureFormat::RGBA16 => 8,
+            TextureFormat::R16F | TextureFormat::R32F => 4,
+            TextureFormat::RG16F | TextureFormat::RG32F => 8,
+            TextureFormat::RGB16F | TextureFormat::RGB32F => 12,
+            TextureFormat::RGBA16F | TextureFormat::RGBA32F => 16,
+            TextureFormat::Depth16 => 2,
+            TextureFormat::Depth24 | TextureFormat::Depth32F | TextureFormat::Depth24Stencil8 => 4,
+            TextureFormat::Depth32FStencil8 => 8,
+        }
+    }
+
+    pub fn is_depth(self) -> bool {
+        self.get_pixel_format() == gl::DEPTH_COMPONENT || self.get_pixel_format() == gl::DEPTH_STENCIL
+    }
+
+    // Picks the format that keeps the full precision of the decoded image. 8-bit colour images are assumed to be sRGB encoded when `srgb` is set.
+    pub fn from_dynamic_image(image: &DynamicImage, srgb: bool) -> TextureFormat {
+        match image {
+            DynamicImage::ImageLuma8(_) => TextureFormat::R8,
+            DynamicImage::ImageLumaA8(_) => TextureFormat::RG8,
+            DynamicImage::ImageRgb8(_) => if srgb { TextureFormat::SRGB8 } else { TextureFormat::RGB8 },
+            DynamicImage::ImageRgba8(_) => if srgb { TextureFormat::SRGB8Alpha8 } else { TextureFormat::RGBA8 },
+            DynamicImage::ImageLuma16(_) => TextureFormat::R16,
+            DynamicImage::ImageLumaA16(_) => TextureFormat::RG16,
+            DynamicImage::ImageRgb16(_) => TextureFormat::RGB16,
+            DynamicImage::ImageRgba16(_) => TextureFormat::RGBA16,
+            DynamicImage::ImageRgb32F(_) => TextureFormat::RGB32F,
+            DynamicImage::ImageRgba32F(_) => TextureFormat::RGBA32F,
+            _ => if srgb { TextureFormat::SRGB8Alpha8 } else { TextureFormat::RGBA8 },
+        }
+    }
+
+    // Two formats can be filled from the same client memory when they share the pixel format and type, e.g. RGB16F and RGB32F both upload floats.
+    pub fn is_upload_compatible(self, other: TextureFormat) -> bool {
+        self.get_pixel_format() == other.get_pixel_format() && self.get_pixel_type() == other.get_pixel_type()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TextureWrap {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+    ClampToBorder,
+}
+
+impl TextureWrap {
+    pub fn get_gl_wrap(self) -> GLenum {
+        match self {
+            TextureWrap::Repeat => gl::REPEAT,
+            TextureWrap::MirroredRepeat => gl::MIRRORED_REPEAT,
+            TextureWrap::ClampToEdge => gl::CLAMP_TO_EDGE,
+            TextureWrap::ClampToBorder => gl::CLAMP_TO_BORDER,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TextureFilter {
+    Nearest,
+    Linear,
+    NearestMipmapNearest,
+    LinearMipmapNearest,
+    NearestMipmapLinear,
+    LinearMipmapLinear,
+}
+
+impl TextureFilter {
+    pub fn get_gl_filter(self) -> GLenum {
+        match self {
+            TextureFilter::Nearest => gl::NEAREST,
+            TextureFilter::Linear => gl::LINEAR,
+            TextureFilter::NearestMipmapNearest => gl::NEAREST_MIPMAP_NEAREST,
+            TextureFilter::LinearMipmapNearest => gl::LINEAR_MIPMAP_NEAREST,
+            TextureFilter::NearestMipmapLinear => gl::NEAREST_MIPMAP_LINEAR,
+            TextureFilter::LinearMipmapLinear => gl::LINEAR_MIPMAP_LINEAR,
+        }
+    }
+
+    pub fn uses_mipmaps(self) -> bool {
+        !matches!(self, TextureFilter::Nearest | TextureFilter::Linear)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TextureDescriptor {
+    pub format: TextureFormat,
+    pub wrap_s: TextureWrap,
+    pub wrap_t: TextureWrap,
+    pub wrap_r: TextureWrap,
+    pub min_filter: TextureFilter,
+    pub mag_filter: TextureFilter,
+    pub border_color: [f32; 4],
+    pub max_anisotropy: f32,
+    pub lod_bias: f32,
+    pub generate_mipmaps: bool,
+}
+
+impl TextureDescriptor {
+    pub fn new(format: TextureFormat) -> TextureDescriptor {
+        TextureDescriptor {
+            format,
+            ..TextureDescriptor::default()
+        }
+    }
+
+    pub fn with_wrap(self, wrap: TextureWrap) -> TextureDescriptor {
+        TextureDescriptor {
+            wrap_s: wrap,
+            wrap_t: wrap,
+            wrap_r: wrap,
+            ..self
+        }
+    }
+
+    pub fn with_filter(self, min_filter: TextureFilter, mag_filter: TextureFilter) -> TextureDescriptor {
+        TextureDescriptor {
+            min_filter,
+            mag_filter,
+            ..self
+        }
+    }
+
+    pub fn with_generated_mipmaps(self) -> TextureDescriptor {
+        TextureDescriptor {
+            generate_mipmaps: true,
+            ..self
+        }
+    }
+
+    pub unsafe fn apply_sampler_parameters(&self, texture: GLuint, target: GLenum) {
+        assert!(!self.mag_filter.uses_mipmaps(), "The magnification filter can't use mipmaps!");
+
+        set_texture_parameter(texture, target, gl::TEXTURE_WRAP_S, self.wrap_s.get_gl_wrap() as GLint);
+        set_texture_parameter(texture, target, gl::TEXTURE_WRAP_T, self.wrap_t.get_gl_wrap() as GLint);
+        set_texture_parameter(texture, target, gl::TEXTURE_WRAP_R, self.wrap_r.get_gl_wrap() as GLint);
+
+        set_texture_parameter(texture, target, gl::TEXTURE_MIN_FILTER, self.min_filter.get_gl_filter() as GLint);
+        set_texture_parameter(texture, target, gl::TEXTURE_MAG_FILTER, self.mag_filter.get_gl_filter() as GLint);
+
+        let capabilities = gl_capabilities::get();
+        if capabilities.has_texture_border_clamp() {
+            set_texture_parameter_floats(texture, target, gl::TEXTURE_BORDER_COLOR, &self.border_color);
+        }
+        if capabilities.has_texture_lod_bias() {
+            set_texture_parameter_float(texture, target, gl::TEXTURE_LOD_BIAS, self.lod_bias);
+        }
+
+        if self.max_anisotropy > 1.0 && capabilities.has_anisotropic_filtering() {
+            let max_anisotropy = self.max_anisotropy.min(capabilities.get_limits().max_anisotropy);
+            set_texture_parameter_float(texture, target, gl_extensions::TEXTURE_MAX_ANISOTROPY, max_anisotropy);
+        }
+    }
+}
+
+impl Default for TextureDescriptor {
+    fn default() -> TextureDescriptor {
+        TextureDescriptor {
+            format: TextureFormat::RGBA8,
+            wrap_s: TextureWrap::ClampToEdge,
+            wrap_t: TextureWrap::ClampToEdge,
+            wrap_r: TextureWrap::ClampToEdge,
+            min_filter: TextureFilter::Nearest,
+            mag_filter: TextureFilter::Nearest,
+            border_color: [0.0, 0.0, 0.0, 0.0],
+            max_anisotropy: 1.0,
+            lod_bias: 0.0,
+            generate_mipmaps: false,
+        }
+    }
+}
+
+// Pixel data is always tightly packed, so the pack/unpack alignment has to match the actual row size.
+pub fn get_row_alignment(row_size: usize) -> GLint {
+    match row_size {
+        _ if row_size.is_multiple_of(8) => 8,
+        _ if row_size.is_multiple_of(4) => 4,
+        _ if row_size.is_multiple_of(2) => 2,
+        _ => 1,
+    }
+}
+
+pub unsafe fn set_pixel_store(parameter: GLenum, value: GLint) -> GLint {
+    let mut previous_value = 0;
+    gl::GetIntegerv(parameter, &mut previous_value);
+    gl::PixelStorei(parameter, value);
+    return previous_value;
+}
+
+// Texture storage goes through the helpers below, which use direct state access when the context has it
+// and otherwise bind the texture to the active unit first. Without DSA a new texture is left bound.
+pub unsafe fn create_texture(target: GLenum) -> GLuint {
+    let mut texture = 0;
+    if gl_extensions::has_direct_state_access() {
+        gl::CreateTextures(target, 1, &mut texture);
+    } else {
+        gl::GenTextures(1, &mut texture);
+        gl_state::bind_texture(target, texture);
+    }
+    return texture;
+}
+
+pub unsafe fn set_texture_parameter(texture: GLuint, target: GLenum, parameter: GLenum, value: GLint) {
+    if gl_extensions::has_direct_state_access() {
+        gl::TextureParameteri(texture, parameter, value);
+    } else {
+        gl_state::bind_texture(target, texture);
+        gl::TexParameteri(target, parameter, value);
+    }
+}
+
+pub unsafe fn set_texture_parameter_float(texture: GLuint, target: GLenum, parameter: GLenum, value: GLfloat) {
+    if gl_extensions::has_direct_state_access() {
+        gl::TextureParameterf(texture, parameter, value);
+    } else {
+        gl_state::bind_texture(target, texture);
+        gl::TexParameterf(target, parameter, value);
+    }
+}
+
+pub unsafe fn set_texture_parameter_floats(texture: GLuint, target: GLenum, parameter: GLenum, values: &[GLfloat]) {
+    if gl_extensions::has_direct_state_access() {
+        gl::TextureParameterfv(texture, parameter, values.as_ptr());
+    } else {
+        gl_state::bind_texture(target, texture);
+        gl::TexParameterfv(target, parameter, values.as_ptr());
+    }
+}
+
+fn is_layered_target(target: GLenum) -> bool {
+    matches!(target, gl::TEXTURE_3D | gl::TEXTURE_2D_ARRAY | gl::TEXTURE_CUBE_MAP_ARRAY)
+}
+
+// Allocates immutable storage for every level and limits sampling to them. `depth` is ignored for 2D and cube targets.
+pub unsafe fn allocate_texture_storage(texture: GLuint, target: GLenum, level_count: u32, format: TextureFormat, width: u32, height: u32, depth: u32) {
+    set_texture_parameter(texture, target, gl::TEXTURE_MAX_LEVEL, level_count as GLint - 1);
+
+    if !gl_capabilities::get().has_texture_storage() {
+        allocate_mutable_texture_storage(texture, target, level_count, format, width, height, depth);
+        return;
+    }
+
+    let (levels, internal_format) = (level_count as GLsizei, format.get_internal_format());
+    let (width, height, depth) = (width as GLsizei, height as GLsizei, depth as GLsizei);
+    match (gl_extensions::has_direct_state_access(), is_layered_target(target)) {
+        (true, true) => gl::TextureStorage3D(texture, levels, internal_format, width, height, depth),
+        (true, false) => gl::TextureStorage2D(texture, levels, internal_format, width, height),
+        (false, true) => {
+            gl_state::bind_texture(target, texture);
+            gl::TexStorage3D(target, levels, internal_format, width, height, depth);
+        }
+        (false, false) => {
+            gl_state::bind_texture(target, texture);
+            gl::TexStorage2D(target, levels, internal_format, width, height);
+        }
+    }
+    gl_debug::check_errors("Allocating texture storage");
+}
+
+// GL 3.3 has no glTexStorage, so every level is specified on its own. Only the array layers keep their size down the chain.
+unsafe fn allocate_mutable_texture_storage(texture: GLuint, target: GLenum, level_count: u32, format: TextureFormat, width: u32, height: u32, depth: u32) {
+    gl_state::bind_texture(target, texture);
+    let (internal_format, pixel_format, pixel_type) = (format.get_internal_format() as GLint, format.get_pixel_format(), format.get_pixel_type());
+    for level in 0..level_count {
+        let level_width = std::cmp::max(width >> level, 1) as GLsizei;
+        let level_height = std::cmp::max(height >> level, 1) as GLsizei;
+        let level_depth = if target == gl::TEXTURE_3D { std::cmp::max(depth >> level, 1) } else { depth } as GLsizei;
+
+        if is_layered_target(target) {
+            gl::TexImage3D(target, level as GLint, internal_format, level_width, level_height, level_depth, 0, pixel_format, pixel_type, std::ptr::null());
+        } else if target == gl::TEXTURE_CUBE_MAP {
+            for face in 0..6 {
+                gl::TexImage2D(gl::TEXTURE_CUBE_MAP_POSITIVE_X + face, level as GLint, internal_format, level_width, level_height, 0, pixel_format, pixel_type, std::ptr::null());
+            }
+        } else {
+            gl::TexImage2D(target, level as GLint, internal_format, level_width, level_height, 0, pixel_format, pixel_type, std::ptr::null());
+        }
+    }
+    gl_debug::check_errors("Allocating texture storage");
+}
+
+// Uploads a tightly packed box of pixels. For cube maps the z offset is the face index, in the GL order.
+pub unsafe fn upload_texture_image<T>(texture: GLuint, target: GLenum, level: u32, offset: [u32; 3], size: [u32; 3], format: TextureFormat, pixels: &[T]) {
+    let row_size = size[0] as usize * format.get_pixel_size();
+    assert_eq!(std::mem::size_of_val(pixels), row_size * size[1] as usize * size[2] as usize);
+
+    let previous_alignment = set_pixel_store(gl::UNPACK_ALIGNMENT, get_row_alignment(row_size));
+    let (x, y, z) = (offset[0] as GLint, offset[1] as GLint, offset[2] as GLint);
+    let (width, height, depth) = (size[0] as GLsizei, size[1] as GLsizei, size[2] as GLsizei);
+    let (pixel_format, pixel_type, data) = (format.get_pixel_format(), format.get_pixel_type(), pixels.as_ptr() as *const GLvoid);
+
+    if gl_extensions::has_direct_state_access() {
+        // DSA treats cube maps as six layers, so faces are addressed like array layers.
+        if is_layered_target(target) || target == gl::TEXTURE_CUBE_MAP {
+            gl::TextureSubImage3D(texture, level as GLint, x, y, z, width, height, depth, pixel_format, pixel_type, data);
+        } else {
+            gl::TextureSubImage2D(texture, level as GLint, x, y, width, height, pixel_format, pixel_type, data);
+        }
+    } else {
+        gl_state::bind_texture(target, texture);
+        if is_layered_target(target) {
+            gl::TexSubImage3D(target, level as GLint, x, y, z, width, height, depth, pixel_format, pixel_type, data);
+        } else if target == gl::TEXTURE_CUBE_MAP {
+            assert_eq!(depth, 1, "Cube map faces have to be uploaded one at a time!");
+            gl::TexSubImage2D(gl::TEXTURE_CUBE_MAP_POSITIVE_X + offset[2], level as GLint, x, y, width, height, pixel_format, pixel_type, data);
+        } else {
+            gl::TexSubImage2D(target, level as GLint, x, y, width, height, pixel_format, pixel_type, data);
+        }
+    }
+
+    set_pixel_store(gl::UNPACK_ALIGNMENT, previous_alignment);
+    gl_debug::check_errors("Uploading texture data");
+}
+
+pub unsafe fn generate_texture_mipmaps(texture: GLuint, target: GLenum) {
+    if gl_extensions::has_direct_state_access() {
+        gl::GenerateTextureMipmap(texture);
+    } else {
+        gl_state::bind_texture(target, texture);
+        gl::GenerateMipmap(target);
+    }
+}
+
+// Reads a whole level into tightly packed pixels of the given format and type.
+pub unsafe fn read_texture_image<T>(texture: GLuint, target: GLenum, level: u32, row_size: usize, pixel_format: GLenum, pixel_type: GLenum, pixels: &mut [T]) {
+    assert!(gl_capabilities::get().has_texture_image_readback(), "GLES can't read texture images back directly!");
+
+    let previous_alignment = set_pixel_store(gl::PACK_ALIGNMENT, get_row_alignment(row_size));
+    if gl_extensions::has_direct_state_access() {
+        let buffer_size = std::mem::size_of_val(pixels) as GLsizei;
+        gl::GetTextureImage(texture, level as GLint, pixel_format, pixel_type, buffer_size, pixels.as_mut_ptr() as *mut GLvoid);
+    } else {
+        gl_state::bind_texture(target, texture);
+        gl::GetTexImage(target, level as GLint, pixel_format, pixel_type, pixels.as_mut_ptr() as *mut GLvoid);
+    }
+    set_pixel_store(gl::PACK_ALIGNMENT, previous_alignment);
+}
+
+// `DynamicImage` is non-exhaustive, so anything this doesn't know the layout of goes through an RGBA8 conversion first.
+fn has_native_format(image: &DynamicImage) -> bool {
+    matches!(
+        image,
+        DynamicImage::ImageLuma8(_) | DynamicImage::ImageLumaA8(_) | DynamicImage::ImageRgb8(_) | DynamicImage::ImageRgba8(_)
+            | DynamicImage::ImageLuma16(_) | DynamicImage::ImageLumaA16(_) | DynamicImage::ImageRgb16(_) | DynamicImage::ImageRgba16(_)
+            | DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+    )
+}
+
+pub trait Texture {
+    fn get_id(&self) -> GLuint;
+
+    fn get_target(&self) -> GLenum;
+
+    fn bind(&self, index: u32) {
+        gl_state::bind_texture_unit(index, self.get_target(), self.get_id());
+    }
+
+    fn un_bind(&self, index: u32) {
+        gl_state::bind_texture_unit(index, self.get_target(), 0);
+    }
+
+    fn generate_mipmaps(&self) {
+        unsafe {
+            generate_texture_mipmaps(self.get_id(), self.get_target());
+        }
+    }
+
+    fn set_debug_label(&self, label: &str) {
+        gl_debug::set_object_label(gl::TEXTURE, self.get_id(), label);
+    }
+}
+
+// A texture on its way to another context of the same share group, e.g. from a loader thread. Unlike `OpenGLTexture`
+// it can be sent between threads, and becomes a texture of the receiving thread's context with `from_shared`.
+pub struct SharedTexture {
+    id: GLuint,
+    width: u32,
+    height: u32,
+    format: TextureFormat,
+}
+
+impl Drop for SharedTexture {
+    fn drop(&mut self) {
+        match ContextHandle::try_current() {
+            Some(context) => context.delete(GLObject::Texture(self.id)),
+            None => log::warn!(target: "gl", "Texture {} was dropped on a thread without a GL context and leaked", self.id),
+        }
+    }
+}
+
 pub struct OpenGLTexture {
     id: GLuint,
+    context: ContextHandle,
+    width: u32,
+    height: u32,
+    format: TextureFormat,
 }
 
 impl OpenGLTexture {
     pub fn new(rgba_pixels: &[u8], width: u32, height: u32) -> OpenGLTexture {
-        assert_eq!(rgba_pixels.len(), width as usize * height as usize * 4);
+        OpenGLTexture::with_descriptor(rgba_pixels, width, height, &TextureDescriptor::default())
+    }
+
+    pub fn with_descriptor<T>(pixels: &[T], width: u32, height: u32, descriptor: &TextureDescriptor) -> OpenGLTexture {
+        let level_count = if descriptor.generate_mipmaps { mipmap::get_mip_level_count(width, height) } else { 1 };
+        let texture = OpenGLTexture::allocate(width, height, level_count, descriptor);
+        if !pixels.is_empty() {
+            texture.upload_level(0, pixels);
+        }
+
+        if descriptor.generate_mipmaps {
+            texture.generate_mipmaps();
+        }
+
+        return texture;
+    }
+
+    pub fn with_mip_chain<T>(levels: &[&[T]], width: u32, height: u32, descriptor: &TextureDescriptor) -> OpenGLTexture {
+        assert!(!levels.is_empty(), "A mip chain needs at least one level!");
+        assert!(levels.len() as u32 <= mipmap::get_mip_level_count(width, height), "Too many mip levels for the texture size!");
+
+        let texture = OpenGLTexture::allocate(width, height, levels.len() as u32, descriptor);
+        for (level, pixels) in levels.iter().enumerate() {
+            texture.upload_level(level as u32, pixels);
+        }
+        return texture;
+    }
+
+    pub fn from_rgba_image(image: &RgbaImage, descriptor: &TextureDescriptor, mipmap_filter: MipmapFilter, gamma_correct: bool) -> OpenGLTexture {
+        assert!(
+            descriptor.format.get_pixel_format() == gl::RGBA && descriptor.format.get_pixel_type() == gl::UNSIGNED_BYTE,
+            "RGBA images need an 8 bit RGBA format, not {:?}!", descriptor.format
+        );
+
+        let mip_chain = mipmap::build_mip_chain(image, mipmap_filter, gamma_correct);
+        let levels: Vec<&[u8]> = mip_chain.iter().map(|level| level.as_raw().as_slice()).collect();
+        return OpenGLTexture::with_mip_chain(&levels, image.width(), image.height(), descriptor);
+    }
+
+    // The descriptor's format only has to be upload compatible with the image, so HDR images can be stored as RGB16F to halve the memory.
+    pub fn from_dynamic_image(image: &DynamicImage, descriptor: &TextureDescriptor) -> OpenGLTexture {
+        let converted_image;
+        let image = if has_native_format(image) {
+            image
+        } else {
+            converted_image = DynamicImage::ImageRgba8(image.to_rgba8());
+            &converted_image
+        };
+        let image_format = TextureFormat::from_dynamic_image(image, false);
+        assert!(
+            descriptor.format.is_upload_compatible(image_format),
+            "Texture format {:?} can't be filled from a {:?} image!", descriptor.format, image.color()
+        );
+
+        let texture = OpenGLTexture::with_descriptor(image.as_bytes(), image.width(), image.height(), descriptor);
+        if image_format.get_pixel_format() == gl::RED || image_format.get_pixel_format() == gl::RG {
+            let alpha = if image_format.get_pixel_format() == gl::RG { gl::GREEN } else { gl::ONE };
+            // GLES only has the per-channel swizzle parameters.
+            let swizzle = [(gl::TEXTURE_SWIZZLE_R, gl::RED), (gl::TEXTURE_SWIZZLE_G, gl::RED), (gl::TEXTURE_SWIZZLE_B, gl::RED), (gl::TEXTURE_SWIZZLE_A, alpha)];
+            unsafe {
+                for (parameter, channel) in swizzle.iter() {
+                    set_texture_parameter(texture.id, gl::TEXTURE_2D, *parameter, *channel as GLint);
+                }
+            }
+        }
+        return texture;
+    }
+
+    fn allocate(width: u32, height: u32, level_count: u32, descriptor: &TextureDescriptor) -> OpenGLTexture {
         unsafe {
-            let mut texture = 0;
-            gl::GenTextures(1, &mut texture);
-            gl::BindTexture(gl::TEXTURE_2D, texture);
-
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as GLint);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as GLint);
-
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as GLint);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as GLint);
-
-            gl::TexImage2D(
-                gl::TEXTURE_2D,
-                0,
-                gl::RGBA8 as GLint,
-                width as GLsizei,
-                height as GLsizei,
-                0,
-                gl::RGBA,
-                gl::UNSIGNED_BYTE,
-                &rgba_pixels[0] as *const u8 as *const GLvoid
-            );
-
-            gl::BindTexture(gl::TEXTURE_2D, 0);
+            let texture = create_texture(gl::TEXTURE_2D);
+            descriptor.apply_sampler_parameters(texture, gl::TEXTURE_2D);
+            allocate_texture_storage(texture, gl::TEXTURE_2D, level_count, descriptor.format, width, height, 1);
 
             return OpenGLTexture {
                 id: texture,
+                context: ContextHandle::adopt(GLObject::Texture(texture)),
+                width,
+                height,
+                format: descriptor.format,
             };
         }
     }
 
-    pub fn bind(&self, index: u32) {
+    fn upload_level<T>(&self, level: u32, pixels: &[T]) {
+        let level_width = std::cmp::max(self.width >> level, 1);
+        let level_height = std::cmp::max(self.height >> level, 1);
+        self.upload_region(level, 0, 0, level_width, level_height, pixels);
+    }
+
+    pub fn update_region<T>(&mut self, x: u32, y: u32, width: u32, height: u32, pixels: &[T]) {
+        assert!(x + width <= self.width && y + height <= self.height, "Texture region out of range!");
+        self.upload_region(0, x, y, width, height, pixels);
+    }
+
+    fn upload_region<T>(&self, level: u32, x: u32, y: u32, width: u32, height: u32, pixels: &[T]) {
         unsafe {
-            gl::ActiveTexture(gl::TEXTURE0 + index);
-            gl::BindTexture(gl::TEXTURE_2D, self.id);
+            upload_texture_image(self.id, gl::TEXTURE_2D, level, [x, y, 0], [width, height, 1], self.format, pixels);
         }
     }
 
-    pub fn un_bind(&self, index: u32) {
+    pub fn read_pixels(&self) -> RgbaImage {
+        assert!(!self.format.is_depth(), "Depth textures can't be read back as RGBA!");
+
+        let mut pixels = vec![0u8; self.width as usize * self.height as usize * 4];
         unsafe {
-            gl::ActiveTexture(gl::TEXTURE0 + index);
-            gl::BindTexture(gl::TEXTURE_2D, 0);
+            read_texture_image(self.id, gl::TEXTURE_2D, 0, self.width as usize * 4, gl::RGBA, gl::UNSIGNED_BYTE, &mut pixels);
         }
+        return RgbaImage::from_raw(self.width, self.height, pixels).unwrap();
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn get_format(&self) -> TextureFormat {
+        self.format
+    }
+
+    // The receiving context may only use the texture once the commands that filled it have completed, see `OpenGLFence`.
+    pub fn into_shared(self) -> SharedTexture {
+        let texture = std::mem::ManuallyDrop::new(self);
+        // The context handle is the only field that needs dropping.
+        drop(unsafe { std::ptr::read(&texture.context) });
+        return SharedTexture {
+            id: texture.id,
+            width: texture.width,
+            height: texture.height,
+            format: texture.format,
+        };
+    }
+
+    pub fn from_shared(shared: SharedTexture) -> OpenGLTexture {
+        let shared = std::mem::ManuallyDrop::new(shared);
+        return OpenGLTexture {
+            id: shared.id,
+            context: ContextHandle::adopt(GLObject::Texture(shared.id)),
+            width: shared.width,
+            height: shared.height,
+            format: shared.format,
+        };
+    }
+}
+
+impl Texture for OpenGLTexture {
+    fn get_id(&self) -> GLuint {
+        self.id
+    }
+
+    fn get_target(&self) -> GLenum {
+        gl::TEXTURE_2D
     }
 }
 
 impl Drop for OpenGLTexture {
     fn drop(&mut self) {
-        unsafe {
-            gl::DeleteTextures(1, &self.id);
-        }
+        self.context.delete(GLObject::Texture(self.id));
     }
 }
@@ -0,0 +1,87 @@
+use crate::gl_context::{ ContextHandle, GLObject };
+use crate::opengl_texture::{ self, Texture, TextureDescriptor, TextureFormat };
+use crate::mipmap;
+
+use gl::types::*;
+
+pub struct OpenGLTexture2DArray {
+    id: GLuint,
+    context: ContextHandle,
+    width: u32,
+    height: u32,
+    layer_count: u32,
+    format: TextureFormat,
+}
+
+impl OpenGLTexture2DArray {
+    pub fn new(width: u32, height: u32, layer_count: u32, descriptor: &TextureDescriptor) -> OpenGLTexture2DArray {
+        let level_count = if descriptor.generate_mipmaps { mipmap::get_mip_level_count(width, height) } else { 1 };
+        unsafe {
+            let texture = opengl_texture::create_texture(gl::TEXTURE_2D_ARRAY);
+            descriptor.apply_sampler_parameters(texture, gl::TEXTURE_2D_ARRAY);
+            opengl_texture::allocate_texture_storage(texture, gl::TEXTURE_2D_ARRAY, level_count, descriptor.format, width, height, layer_count);
+
+            return OpenGLTexture2DArray {
+                id: texture,
+                context: ContextHandle::adopt(GLObject::Texture(texture)),
+                width,
+                height,
+                layer_count,
+                format: descriptor.format,
+            };
+        }
+    }
+
+    pub fn with_layers<T>(layers: &[&[T]], width: u32, height: u32, descriptor: &TextureDescriptor) -> OpenGLTexture2DArray {
+        let texture = OpenGLTexture2DArray::new(width, height, layers.len() as u32, descriptor);
+        for (layer, pixels) in layers.iter().enumerate() {
+            texture.set_layer(layer as u32, pixels);
+        }
+
+        if descriptor.generate_mipmaps {
+            texture.generate_mipmaps();
+        }
+
+        return texture;
+    }
+
+    pub fn set_layer<T>(&self, layer: u32, pixels: &[T]) {
+        assert!(layer < self.layer_count, "Texture array layer out of range!");
+        assert_eq!(std::mem::size_of_val(pixels), self.width as usize * self.height as usize * self.format.get_pixel_size());
+        unsafe {
+            opengl_texture::upload_texture_image(self.id, gl::TEXTURE_2D_ARRAY, 0, [0, 0, layer], [self.width, self.height, 1], self.format, pixels);
+        }
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn get_layer_count(&self) -> u32 {
+        self.layer_count
+    }
+
+    pub fn get_format(&self) -> TextureFormat {
+        self.format
+    }
+}
+
+impl Texture for OpenGLTexture2DArray {
+    fn get_id(&self) -> GLuint {
+        self.id
+    }
+
+    fn get_target(&self) -> GLenum {
+        gl::TEXTURE_2D_ARRAY
+    }
+}
+
+impl Drop for OpenGLTexture2DArray {
+    fn drop(&mut self) {
+        self.context.delete(GLObject::Texture(self.id));
+    }
+}
@@ -0,0 +1,79 @@
+use crate::gl_context::{ ContextHandle, GLObject };
+use crate::opengl_texture::{ self, Texture, TextureDescriptor, TextureFormat };
+use crate::mipmap;
+
+use gl::types::*;
+
+pub struct OpenGLTexture3D {
+    id: GLuint,
+    context: ContextHandle,
+    width: u32,
+    height: u32,
+    depth: u32,
+    format: TextureFormat,
+}
+
+impl OpenGLTexture3D {
+    pub fn new<T>(pixels: &[T], width: u32, height: u32, depth: u32, descriptor: &TextureDescriptor) -> OpenGLTexture3D {
+        let format = descriptor.format;
+        if !pixels.is_empty() {
+            assert_eq!(std::mem::size_of_val(pixels), width as usize * height as usize * depth as usize * format.get_pixel_size());
+        }
+
+        let level_count = if descriptor.generate_mipmaps { mipmap::get_mip_level_count(std::cmp::max(width, depth), height) } else { 1 };
+        unsafe {
+            let texture = opengl_texture::create_texture(gl::TEXTURE_3D);
+            descriptor.apply_sampler_parameters(texture, gl::TEXTURE_3D);
+            opengl_texture::allocate_texture_storage(texture, gl::TEXTURE_3D, level_count, format, width, height, depth);
+
+            if !pixels.is_empty() {
+                opengl_texture::upload_texture_image(texture, gl::TEXTURE_3D, 0, [0, 0, 0], [width, height, depth], format, pixels);
+            }
+
+            if descriptor.generate_mipmaps {
+                opengl_texture::generate_texture_mipmaps(texture, gl::TEXTURE_3D);
+            }
+
+            return OpenGLTexture3D {
+                id: texture,
+                context: ContextHandle::adopt(GLObject::Texture(texture)),
+                width,
+                height,
+                depth,
+                format,
+            };
+        }
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn get_depth(&self) -> u32 {
+        self.depth
+    }
+
+    pub fn get_format(&self) -> TextureFormat {
+        self.format
+    }
+}
+
+impl Texture for OpenGLTexture3D {
+    fn get_id(&self) -> GLuint {
+        self.id
+    }
+
+    fn get_target(&self) -> GLenum {
+        gl::TEXTURE_3D
+    }
+}
+
+impl Drop for OpenGLTexture3D {
+    fn drop(&mut self) {
+        self.context.delete(GLObject::Texture(self.id));
+    }
+}
@@ -0,0 +1,142 @@
+use crate::gl_context::{ ContextHandle, GLObject };
+use crate::opengl_texture::{ self, Texture, TextureDescriptor, TextureFormat };
+use crate::mipmap;
+
+use gl::types::*;
+
+pub struct OpenGLTextureCube {
+    id: GLuint,
+    context: ContextHandle,
+    size: u32,
+    format: TextureFormat,
+}
+
+impl OpenGLTextureCube {
+    // Faces are in the GL order: +X, -X, +Y, -Y, +Z, -Z.
+    pub fn from_faces<T>(faces: [&[T]; 6], size: u32, descriptor: &TextureDescriptor) -> OpenGLTextureCube {
+        let format = descriptor.format;
+        let level_count = if descriptor.generate_mipmaps { mipmap::get_mip_level_count(size, size) } else { 1 };
+        unsafe {
+            let texture = opengl_texture::create_texture(gl::TEXTURE_CUBE_MAP);
+            descriptor.apply_sampler_parameters(texture, gl::TEXTURE_CUBE_MAP);
+            opengl_texture::allocate_texture_storage(texture, gl::TEXTURE_CUBE_MAP, level_count, format, size, size, 6);
+
+            for (face, pixels) in faces.iter().enumerate() {
+                if pixels.is_empty() {
+                    continue;
+                }
+                opengl_texture::upload_texture_image(texture, gl::TEXTURE_CUBE_MAP, 0, [0, 0, face as u32], [size, size, 1], format, pixels);
+            }
+
+            if descriptor.generate_mipmaps {
+                opengl_texture::generate_texture_mipmaps(texture, gl::TEXTURE_CUBE_MAP);
+            }
+
+            return OpenGLTextureCube {
+                id: texture,
+                context: ContextHandle::adopt(GLObject::Texture(texture)),
+                size,
+                format,
+            };
+        }
+    }
+
+    pub fn from_equirectangular(pixels: &[[f32; 3]], width: u32, height: u32, size: u32, descriptor: &TextureDescriptor) -> OpenGLTextureCube {
+        assert!(descriptor.format == TextureFormat::RGB16F || descriptor.format == TextureFormat::RGB32F, "Equirectangular cubemaps need an RGB float format!");
+
+        let faces = equirectangular_to_faces(pixels, width, height, size);
+        return OpenGLTextureCube::from_faces([&faces[0], &faces[1], &faces[2], &faces[3], &faces[4], &faces[5]], size, descriptor);
+    }
+
+    pub fn from_equirectangular_hdr(hdr_bytes: &[u8], size: u32, descriptor: &TextureDescriptor) -> image::ImageResult<OpenGLTextureCube> {
+        let decoder = image::codecs::hdr::HdrDecoder::new(hdr_bytes)?;
+        let metadata = decoder.metadata();
+        let pixels: Vec<[f32; 3]> = decoder.read_image_hdr()?.into_iter().map(|pixel| pixel.0).collect();
+        return Ok(OpenGLTextureCube::from_equirectangular(&pixels, metadata.width, metadata.height, size, descriptor));
+    }
+
+    pub fn get_size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn get_format(&self) -> TextureFormat {
+        self.format
+    }
+}
+
+impl Texture for OpenGLTextureCube {
+    fn get_id(&self) -> GLuint {
+        self.id
+    }
+
+    fn get_target(&self) -> GLenum {
+        gl::TEXTURE_CUBE_MAP
+    }
+}
+
+impl Drop for OpenGLTextureCube {
+    fn drop(&mut self) {
+        self.context.delete(GLObject::Texture(self.id));
+    }
+}
+
+pub fn equirectangular_to_faces(pixels: &[[f32; 3]], width: u32, height: u32, size: u32) -> Vec<Vec<[f32; 3]>> {
+    assert_eq!(pixels.len(), width as usize * height as usize);
+
+    let mut faces = Vec::with_capacity(6);
+    for face in 0..6 {
+        let mut face_pixels = Vec::with_capacity(size as usize * size as usize);
+        for y in 0..size {
+            for x in 0..size {
+                let s = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                let t = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                let direction = match face {
+                    0 => [1.0, -t, -s],
+                    1 => [-1.0, -t, s],
+                    2 => [s, 1.0, t],
+                    3 => [s, -1.0, -t],
+                    4 => [s, -t, 1.0],
+                    _ => [-s, -t, -1.0],
+                };
+                face_pixels.push(sample_equirectangular(pixels, width, height, direction));
+            }
+        }
+        faces.push(face_pixels);
+    }
+    return faces;
+}
+
+fn sample_equirectangular(pixels: &[[f32; 3]], width: u32, height: u32, direction: [f32; 3]) -> [f32; 3] {
+    let length = (direction[0] * direction[0] + direction[1] * direction[1] + direction[2] * direction[2]).sqrt();
+    let (x, y, z) = (direction[0] / length, direction[1] / length, direction[2] / length);
+
+    let u = 0.5 + z.atan2(x) / (2.0 * std::f32::consts::PI);
+    let v = y.clamp(-1.0, 1.0).acos() / std::f32::consts::PI;
+
+    let pixel_x = u * width as f32 - 0.5;
+    let pixel_y = v * height as f32 - 0.5;
+    let x0 = pixel_x.floor();
+    let y0 = pixel_y.floor();
+    let fraction_x = pixel_x - x0;
+    let fraction_y = pixel_y - y0;
+
+    let fetch = |x: i64, y: i64| {
+        let x = x.rem_euclid(width as i64) as usize;
+        let y = y.clamp(0, height as i64 - 1) as usize;
+        pixels[y * width as usize + x]
+    };
+
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let top_left = fetch(x0, y0);
+    let top_right = fetch(x0 + 1, y0);
+    let bottom_left = fetch(x0, y0 + 1);
+    let bottom_right = fetch(x0 + 1, y0 + 1);
+
+    let mut result = [0.0; 3];
+    for channel in 0..3 {
+        let top = top_left[channel] + (top_right[channel] - top_left[channel]) * fraction_x;
+        let bottom = bottom_left[channel] + (bottom_right[channel] - bottom_left[channel]) * fraction_x;
+        result[channel] = top + (bottom - top) * fraction_y;
+    }
+    return result;
+}
@@ -1,3 +1,7 @@
+use crate::gl_context::{ ContextHandle, GLObject };
+use crate::gl_debug;
+use crate::gl_extensions;
+use crate::gl_state;
 use crate::opengl_vertex_buffer::{ OpenGLVertexBuffer };
 
 use gl::types::*;
@@ -41,6 +45,7 @@ impl BufferElement {
 
 pub struct OpenGLVertexArray {
     id: GLuint,
+    context: ContextHandle,
     vertex_buffers: Vec<(OpenGLVertexBuffer, Vec<BufferElement>)>,
 }
 
@@ -48,36 +53,62 @@ impl OpenGLVertexArray {
     pub fn new() -> OpenGLVertexArray {
         unsafe {
             let mut vertex_array = 0;
-            gl::GenVertexArrays(1, &mut vertex_array);
+            if gl_extensions::has_direct_state_access() {
+                gl::CreateVertexArrays(1, &mut vertex_array);
+            } else {
+                gl::GenVertexArrays(1, &mut vertex_array);
+            }
 
             return OpenGLVertexArray {
                 id: vertex_array,
+                context: ContextHandle::adopt(GLObject::VertexArray(vertex_array)),
                 vertex_buffers: Vec::new(),
             };
         }
     }
 
+    // Each buffer holds its own interleaved layout; attribute locations continue from the previous buffer's.
     pub fn add_vertex_buffer<'a>(&'a mut self, buffer: OpenGLVertexBuffer, layout: &[BufferElement]) -> &'a OpenGLVertexBuffer {
+        self.attach_vertex_buffer(self.vertex_buffers.len(), &buffer, layout);
+        gl_debug::check_errors("Adding a vertex buffer");
         self.vertex_buffers.push((buffer, layout.to_vec()));
+        return &self.vertex_buffers.last().unwrap().0;
+    }
 
-        let mut stride = 0;
-        for (_, layout) in &self.vertex_buffers {
-            for element in layout {
-                stride += element.get_stride();
-            }
+    // Replaces the data of the buffer added at `index`, attaching it again if growing it moved it to a new id.
+    pub fn set_vertex_buffer_data<T>(&mut self, index: usize, data: &[T]) {
+        let buffer = &mut self.vertex_buffers[index].0;
+        let previous_id = buffer.get_id();
+        buffer.set_data(data);
+        if buffer.get_id() != previous_id {
+            let (buffer, layout) = &self.vertex_buffers[index];
+            self.attach_vertex_buffer(index, buffer, layout);
+            gl_debug::check_errors("Attaching a vertex buffer");
         }
+    }
+
+    fn attach_vertex_buffer(&self, binding_index: usize, buffer: &OpenGLVertexBuffer, layout: &[BufferElement]) {
+        let first_attribute: usize = self.vertex_buffers[..binding_index].iter().map(|(_, layout)| layout.len()).sum();
+        let stride: usize = layout.iter().map(|element| element.get_stride()).sum();
 
-        self.bind();
+        unsafe {
+            if gl_extensions::has_direct_state_access() {
+                gl::VertexArrayVertexBuffer(self.id, binding_index as GLuint, buffer.get_id(), 0, stride as GLsizei);
+            } else {
+                self.bind();
+                buffer.bind();
+            }
 
-        let mut index = 0;
-        let mut offset = 0;
-        for (vertex_buffer, layout) in &self.vertex_buffers {
-            vertex_buffer.bind();
-            for element in layout {
-                unsafe {
-                    gl::EnableVertexAttribArray(index);
+            let mut offset = 0;
+            for (attribute, element) in (first_attribute as GLuint..).zip(layout) {
+                if gl_extensions::has_direct_state_access() {
+                    gl::EnableVertexArrayAttrib(self.id, attribute);
+                    gl::VertexArrayAttribFormat(self.id, attribute, element.get_count() as GLint, element.get_gl_type(), gl::FALSE, offset as GLuint);
+                    gl::VertexArrayAttribBinding(self.id, attribute, binding_index as GLuint);
+                } else {
+                    gl::EnableVertexAttribArray(attribute);
                     gl::VertexAttribPointer(
-                        index,
+                        attribute,
                         element.get_count() as GLint,
                         element.get_gl_type(),
                         gl::FALSE,
@@ -85,34 +116,27 @@ impl OpenGLVertexArray {
                         offset as *const GLvoid,
                     );
                 }
-                index += 1;
                 offset += element.get_stride();
             }
-            vertex_buffer.un_bind();
         }
+    }
 
-        self.un_bind();
-        
-        return &self.vertex_buffers.last().unwrap().0;
+    pub fn set_debug_label(&self, label: &str) {
+        gl_debug::ensure_object_exists(gl::VERTEX_ARRAY, self.id);
+        gl_debug::set_object_label(gl::VERTEX_ARRAY, self.id, label);
     }
 
     pub fn bind(&self) {
-        unsafe {
-            gl::BindVertexArray(self.id);
-        }
+        gl_state::bind_vertex_array(self.id);
     }
 
     pub fn un_bind(&self) {
-        unsafe {
-            gl::BindVertexArray(0);
-        }
+        gl_state::bind_vertex_array(0);
     }
 }
 
 impl Drop for OpenGLVertexArray {
     fn drop(&mut self) {
-        unsafe {
-            gl::DeleteVertexArrays(1, &self.id);
-        }
+        self.context.delete(GLObject::VertexArray(self.id));
     }
 }
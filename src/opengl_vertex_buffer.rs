@@ -1,57 +1,88 @@
+use crate::gl_context::{ ContextHandle, GLObject };
+use crate::gl_state;
+use crate::opengl_buffer::{ BufferStorage };
+
 use gl::types::*;
 
-pub struct OpenGLVertexBuffer {
+// A vertex buffer on its way to another context of the same share group, see `SharedTexture`.
+pub struct SharedVertexBuffer {
     id: GLuint,
+    capacity: usize,
+    immutable: bool,
+}
+
+impl Drop for SharedVertexBuffer {
+    fn drop(&mut self) {
+        match ContextHandle::try_current() {
+            Some(context) => context.delete(GLObject::Buffer(self.id)),
+            None => log::warn!(target: "gl", "Buffer {} was dropped on a thread without a GL context and leaked", self.id),
+        }
+    }
+}
+
+pub struct OpenGLVertexBuffer {
+    storage: BufferStorage,
 }
 
 impl OpenGLVertexBuffer {
     pub fn new<T>(data: &[T]) -> OpenGLVertexBuffer {
         unsafe {
-            let mut vertex_buffer = 0;
-            gl::GenBuffers(1, &mut vertex_buffer);
-            gl::BindBuffer(gl::ARRAY_BUFFER, vertex_buffer);
-            gl::BufferData(
-                gl::ARRAY_BUFFER,
-                (data.len() * std::mem::size_of_val(&data[0])) as GLsizeiptr,
+            let storage = BufferStorage::new(
+                data.len() * std::mem::size_of_val(&data[0]),
                 &data[0] as *const T as *const GLvoid,
                 gl::STATIC_DRAW,
             );
-            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
 
             return OpenGLVertexBuffer {
-                id: vertex_buffer,
+                storage,
             };
         }
     }
 
+    // Data larger than the buffer can move it to a new id. Once it is part of a vertex array, update it through
+    // `OpenGLVertexArray::set_vertex_buffer_data`, which attaches it again.
     pub fn set_data<T>(&mut self, data: &[T]) {
         unsafe {
-            gl::BindBuffer(gl::ARRAY_BUFFER, self.id);
-            gl::BufferData(
-                gl::ARRAY_BUFFER,
-                (data.len() * std::mem::size_of_val(&data[0])) as GLsizeiptr,
+            self.storage.set_data(
+                data.len() * std::mem::size_of_val(&data[0]),
                 &data[0] as *const T as *const GLvoid,
                 gl::DYNAMIC_DRAW,
             );
-            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
         }
     }
 
+    pub fn into_shared(self) -> SharedVertexBuffer {
+        let buffer = std::mem::ManuallyDrop::new(self);
+        let (id, capacity, immutable) = unsafe { std::ptr::read(&buffer.storage) }.into_raw();
+        return SharedVertexBuffer { id, capacity, immutable };
+    }
+
+    pub fn from_shared(shared: SharedVertexBuffer) -> OpenGLVertexBuffer {
+        let shared = std::mem::ManuallyDrop::new(shared);
+        return OpenGLVertexBuffer {
+            storage: BufferStorage::from_raw(shared.id, shared.capacity, shared.immutable),
+        };
+    }
+
+    pub fn get_id(&self) -> GLuint {
+        self.storage.id
+    }
+
+    pub fn set_debug_label(&mut self, label: &str) {
+        self.storage.set_debug_label(label);
+    }
+
     pub fn bind(&self) {
-        unsafe {
-            gl::BindBuffer(gl::ARRAY_BUFFER, self.id);
-        }
+        gl_state::bind_buffer(gl::ARRAY_BUFFER, self.storage.id);
     }
 
     pub fn un_bind(&self) {
-        unsafe {
-            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
-        }
+        gl_state::bind_buffer(gl::ARRAY_BUFFER, 0);
     }
 }
 
 impl Drop for OpenGLVertexBuffer {
     fn drop(&mut self) {
-        unsafe { gl::DeleteBuffers(1, &self.id); }
+        self.storage.delete();
     }
 }
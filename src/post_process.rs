@@ -0,0 +1,386 @@
+use crate::gl_debug;
+use crate::opengl_framebuffer::{ FramebufferError, OpenGLFramebuffer };
+use crate::opengl_shader::{ OpenGLShader, ShaderError, ShaderSource };
+use crate::opengl_texture::{ OpenGLTexture, Texture, TextureDescriptor, TextureFilter, TextureFormat };
+use crate::opengl_texture_3d::{ OpenGLTexture3D };
+use crate::opengl_vertex_array::{ OpenGLVertexArray };
+use crate::render_state::{ RenderState, RenderStateCache };
+
+use gl::types::*;
+
+use std::rc::{ Rc };
+
+const FULLSCREEN_VERTEX_SOURCE: &str = include_str!("../fullscreen.vert.glsl");
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum UniformValue {
+    Integer(i32),
+    Float(f32),
+    Float2([f32; 2]),
+    Float3([f32; 3]),
+    Float4([f32; 4]),
+}
+
+impl UniformValue {
+    fn apply(self, shader: &OpenGLShader, name: &str) {
+        match self {
+            UniformValue::Integer(value) => shader.set_integer(name, value),
+            UniformValue::Float(value) => shader.set_float(name, value),
+            UniformValue::Float2(value) => shader.set_float2(name, value),
+            UniformValue::Float3(value) => shader.set_float3(name, value),
+            UniformValue::Float4(value) => shader.set_float4(name, value),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum EffectInput {
+    // The output of the effect before this one, or the scene for the first effect.
+    Previous,
+    // The scene as it was rendered, before any effect ran.
+    Scene,
+    Texture(Rc<dyn Texture>),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ToneMapOperator {
+    Reinhard,
+    Aces,
+}
+
+// Where an effect draws to. `None` is the default framebuffer, covering `size`.
+pub struct EffectTarget<'a> {
+    pub framebuffer: Option<&'a OpenGLFramebuffer>,
+    pub size: (u32, u32),
+}
+
+impl EffectTarget<'_> {
+    fn bind(&self) {
+        match self.framebuffer {
+            Some(framebuffer) => framebuffer.bind(),
+            None => unsafe {
+                gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+                gl::Viewport(0, 0, self.size.0 as GLsizei, self.size.1 as GLsizei);
+            },
+        }
+    }
+
+    fn un_bind(&self) {
+        if let Some(framebuffer) = self.framebuffer {
+            framebuffer.un_bind();
+        }
+    }
+}
+
+pub struct EffectContext<'a> {
+    pub previous: &'a OpenGLTexture,
+    pub scene: &'a OpenGLTexture,
+    fullscreen_vertex_array: &'a OpenGLVertexArray,
+}
+
+pub trait PostProcessEffect {
+    fn render(&mut self, context: &EffectContext, target: &EffectTarget);
+
+    // Shown as the debug group around the effect in frame captures.
+    fn get_name(&self) -> &str {
+        "Effect"
+    }
+}
+
+// A single full-screen pass. The fragment shader receives `v_TexCoord` at location 0, and every named input is bound as a sampler.
+pub struct ShaderEffect {
+    name: String,
+    shader: OpenGLShader,
+    inputs: Vec<(String, EffectInput)>,
+    parameters: Vec<(String, UniformValue)>,
+}
+
+impl ShaderEffect {
+    // The output of the previous effect is bound to `u_Texture` unless it is renamed with `with_input`.
+    pub fn new(fragment: &ShaderSource) -> Result<ShaderEffect, ShaderError> {
+        let shader = OpenGLShader::try_new(&ShaderSource::new("fullscreen.vert.glsl", FULLSCREEN_VERTEX_SOURCE), fragment)?;
+        shader.set_debug_label(fragment.name);
+        return Ok(ShaderEffect {
+            name: fragment.name.to_string(),
+            shader,
+            inputs: vec![("u_Texture".to_string(), EffectInput::Previous)],
+            parameters: Vec::new(),
+        });
+    }
+
+    pub fn tone_mapping(operator: ToneMapOperator, exposure: f32) -> ShaderEffect {
+        let mut effect = ShaderEffect::built_in("tone_mapping.frag.glsl", include_str!("../tone_mapping.frag.glsl"));
+        effect.set_parameter("u_Operator", UniformValue::Integer(if operator == ToneMapOperator::Reinhard { 0 } else { 1 }));
+        effect.set_parameter("u_Exposure", UniformValue::Float(exposure));
+        return effect;
+    }
+
+    pub fn gamma_correction(gamma: f32) -> ShaderEffect {
+        let mut effect = ShaderEffect::built_in("gamma_correction.frag.glsl", include_str!("../gamma_correction.frag.glsl"));
+        effect.set_parameter("u_Gamma", UniformValue::Float(gamma));
+        return effect;
+    }
+
+    // Expects gamma-corrected input, so it belongs after tone mapping and gamma correction.
+    pub fn fxaa() -> ShaderEffect {
+        ShaderEffect::built_in("fxaa.frag.glsl", include_str!("../fxaa.frag.glsl"))
+    }
+
+    pub fn vignette(strength: f32, radius: f32, softness: f32) -> ShaderEffect {
+        let mut effect = ShaderEffect::built_in("vignette.frag.glsl", include_str!("../vignette.frag.glsl"));
+        effect.set_parameter("u_Strength", UniformValue::Float(strength));
+        effect.set_parameter("u_Radius", UniformValue::Float(radius));
+        effect.set_parameter("u_Softness", UniformValue::Float(softness));
+        return effect;
+    }
+
+    // The LUT maps input RGB to graded RGB, with red along the width, green along the height and blue along the depth.
+    pub fn color_grading(lut: Rc<OpenGLTexture3D>, intensity: f32) -> ShaderEffect {
+        let mut effect = ShaderEffect::built_in("color_grading.frag.glsl", include_str!("../color_grading.frag.glsl"))
+            .with_input("u_Lut", EffectInput::Texture(lut));
+        effect.set_parameter("u_Intensity", UniformValue::Float(intensity));
+        return effect;
+    }
+
+    fn built_in(name: &str, source: &str) -> ShaderEffect {
+        ShaderEffect::new(&ShaderSource::new(name, source)).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn with_input(mut self, name: &str, input: EffectInput) -> ShaderEffect {
+        self.inputs.retain(|(input_name, _)| input_name != name);
+        self.inputs.push((name.to_string(), input));
+        return self;
+    }
+
+    pub fn set_parameter(&mut self, name: &str, value: UniformValue) {
+        match self.parameters.iter_mut().find(|(parameter_name, _)| parameter_name == name) {
+            Some(parameter) => parameter.1 = value,
+            None => self.parameters.push((name.to_string(), value)),
+        }
+    }
+
+    pub fn get_shader(&self) -> &OpenGLShader {
+        &self.shader
+    }
+
+    fn draw(&self, textures: &[(&str, &dyn Texture)], fullscreen_vertex_array: &OpenGLVertexArray, target: &EffectTarget) {
+        for (name, value) in &self.parameters {
+            value.apply(&self.shader, name);
+        }
+        for (index, (name, texture)) in textures.iter().enumerate() {
+            texture.bind(index as u32);
+            self.shader.set_integer(name, index as i32);
+        }
+
+        target.bind();
+        self.shader.bind();
+        fullscreen_vertex_array.bind();
+        unsafe {
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        }
+        gl_debug::check_errors("Drawing a post-process pass");
+        target.un_bind();
+    }
+}
+
+impl PostProcessEffect for ShaderEffect {
+    fn render(&mut self, context: &EffectContext, target: &EffectTarget) {
+        let textures: Vec<(&str, &dyn Texture)> = self.inputs.iter()
+            .map(|(name, input)| {
+                let texture: &dyn Texture = match input {
+                    EffectInput::Previous => context.previous,
+                    EffectInput::Scene => context.scene,
+                    EffectInput::Texture(texture) => texture.as_ref(),
+                };
+                (name.as_str(), texture)
+            })
+            .collect();
+        self.draw(&textures, context.fullscreen_vertex_array, target);
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+}
+
+// Bright parts of the image are extracted at half resolution, blurred and added back on top.
+pub struct Bloom {
+    threshold_effect: ShaderEffect,
+    blur_effect: ShaderEffect,
+    composite_effect: ShaderEffect,
+    blur_passes: u32,
+    framebuffers: Option<[OpenGLFramebuffer; 2]>,
+}
+
+impl Bloom {
+    pub fn new(threshold: f32, intensity: f32, blur_passes: u32) -> Bloom {
+        let mut threshold_effect = ShaderEffect::built_in("bloom_threshold.frag.glsl", include_str!("../bloom_threshold.frag.glsl"));
+        threshold_effect.set_parameter("u_Threshold", UniformValue::Float(threshold));
+        let blur_effect = ShaderEffect::built_in("gaussian_blur.frag.glsl", include_str!("../gaussian_blur.frag.glsl"));
+        let mut composite_effect = ShaderEffect::built_in("bloom_composite.frag.glsl", include_str!("../bloom_composite.frag.glsl"));
+        composite_effect.set_parameter("u_Intensity", UniformValue::Float(intensity));
+
+        return Bloom {
+            threshold_effect,
+            blur_effect,
+            composite_effect,
+            blur_passes: std::cmp::max(blur_passes, 1),
+            framebuffers: None,
+        };
+    }
+
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold_effect.set_parameter("u_Threshold", UniformValue::Float(threshold));
+    }
+
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.composite_effect.set_parameter("u_Intensity", UniformValue::Float(intensity));
+    }
+
+    fn create_framebuffers_if_needed(&mut self, width: u32, height: u32) {
+        let (width, height) = (std::cmp::max(width / 2, 1), std::cmp::max(height / 2, 1));
+        if self.framebuffers.as_ref().is_none_or(|framebuffers| framebuffers[0].get_width() != width || framebuffers[0].get_height() != height) {
+            let descriptor = TextureDescriptor::new(TextureFormat::RGBA16F).with_filter(TextureFilter::Linear, TextureFilter::Linear);
+            let create = |label: &str| {
+                let framebuffer = OpenGLFramebuffer::with_attachments(width, height, &[descriptor], None).unwrap_or_else(|error| panic!("{}", error));
+                framebuffer.set_debug_label(label);
+                framebuffer
+            };
+            self.framebuffers = Some([create("Bloom Ping"), create("Bloom Pong")]);
+        }
+    }
+}
+
+impl PostProcessEffect for Bloom {
+    fn render(&mut self, context: &EffectContext, target: &EffectTarget) {
+        self.create_framebuffers_if_needed(context.previous.get_width(), context.previous.get_height());
+        let framebuffers = self.framebuffers.as_ref().unwrap();
+        let size = (framebuffers[0].get_width(), framebuffers[0].get_height());
+        let vertex_array = context.fullscreen_vertex_array;
+
+        self.threshold_effect.draw(&[("u_Texture", context.previous)], vertex_array, &EffectTarget { framebuffer: Some(&framebuffers[0]), size });
+        for _ in 0..self.blur_passes {
+            self.blur_effect.set_parameter("u_Direction", UniformValue::Float2([1.0, 0.0]));
+            self.blur_effect.draw(&[("u_Texture", framebuffers[0].get_color_attachment(0))], vertex_array, &EffectTarget { framebuffer: Some(&framebuffers[1]), size });
+            self.blur_effect.set_parameter("u_Direction", UniformValue::Float2([0.0, 1.0]));
+            self.blur_effect.draw(&[("u_Texture", framebuffers[1].get_color_attachment(0))], vertex_array, &EffectTarget { framebuffer: Some(&framebuffers[0]), size });
+        }
+
+        self.composite_effect.draw(&[("u_Texture", context.previous), ("u_Bloom", framebuffers[0].get_color_attachment(0))], vertex_array, target);
+    }
+
+    fn get_name(&self) -> &str {
+        "Bloom"
+    }
+}
+
+// Render the scene between `begin` and `end`; `end` then runs every effect in order, ping-ponging between two
+// intermediate framebuffers, with the last effect drawing straight into the output.
+pub struct PostProcessChain {
+    width: u32,
+    height: u32,
+    format: TextureFormat,
+    scene_framebuffer: OpenGLFramebuffer,
+    ping_pong_framebuffers: [OpenGLFramebuffer; 2],
+    effects: Vec<Box<dyn PostProcessEffect>>,
+    fullscreen_vertex_array: OpenGLVertexArray,
+}
+
+impl PostProcessChain {
+    // `format` is used for the scene and the intermediate targets; a float format keeps HDR values until tone mapping.
+    pub fn new(width: u32, height: u32, format: TextureFormat) -> Result<PostProcessChain, FramebufferError> {
+        let (scene_framebuffer, ping_pong_framebuffers) = PostProcessChain::create_framebuffers(width, height, format)?;
+        let fullscreen_vertex_array = OpenGLVertexArray::new();
+        fullscreen_vertex_array.set_debug_label("Post Process Fullscreen Triangle");
+        return Ok(PostProcessChain {
+            width,
+            height,
+            format,
+            scene_framebuffer,
+            ping_pong_framebuffers,
+            effects: Vec::new(),
+            fullscreen_vertex_array,
+        });
+    }
+
+    fn create_framebuffers(width: u32, height: u32, format: TextureFormat) -> Result<(OpenGLFramebuffer, [OpenGLFramebuffer; 2]), FramebufferError> {
+        let descriptor = TextureDescriptor::new(format).with_filter(TextureFilter::Linear, TextureFilter::Linear);
+        let scene_framebuffer = OpenGLFramebuffer::with_attachments(width, height, &[descriptor], Some(TextureFormat::Depth24Stencil8))?;
+        let ping_pong_framebuffers = [
+            OpenGLFramebuffer::with_attachments(width, height, &[descriptor], None)?,
+            OpenGLFramebuffer::with_attachments(width, height, &[descriptor], None)?,
+        ];
+        scene_framebuffer.set_debug_label("Post Process Scene");
+        ping_pong_framebuffers[0].set_debug_label("Post Process Ping");
+        ping_pong_framebuffers[1].set_debug_label("Post Process Pong");
+        return Ok((scene_framebuffer, ping_pong_framebuffers));
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), FramebufferError> {
+        if width == self.width && height == self.height {
+            return Ok(());
+        }
+        let (scene_framebuffer, ping_pong_framebuffers) = PostProcessChain::create_framebuffers(width, height, self.format)?;
+        self.scene_framebuffer = scene_framebuffer;
+        self.ping_pong_framebuffers = ping_pong_framebuffers;
+        self.width = width;
+        self.height = height;
+        return Ok(());
+    }
+
+    pub fn add_effect<E: PostProcessEffect + 'static>(&mut self, effect: E) {
+        self.effects.push(Box::new(effect));
+    }
+
+    pub fn clear_effects(&mut self) {
+        self.effects.clear();
+    }
+
+    pub fn get_scene_framebuffer(&self) -> &OpenGLFramebuffer {
+        &self.scene_framebuffer
+    }
+
+    pub fn begin(&self) {
+        self.scene_framebuffer.bind();
+    }
+
+    // Runs the effects and writes the result to `output`, or to the default framebuffer when it is `None`. The
+    // fullscreen passes run without depth testing, blending, culling or scissoring, whatever the scene left behind.
+    pub fn end(&mut self, render_state_cache: &mut RenderStateCache, output: Option<&OpenGLFramebuffer>) {
+        self.scene_framebuffer.un_bind();
+        let _debug_group = gl_debug::push_debug_group("Post Processing");
+
+        let output_size = output.map_or((self.width, self.height), |framebuffer| (framebuffer.get_width(), framebuffer.get_height()));
+        let output_target = EffectTarget { framebuffer: output, size: output_size };
+
+        render_state_cache.apply(&RenderState::default());
+        self.run_effects(&output_target);
+    }
+
+    fn run_effects(&mut self, output_target: &EffectTarget) {
+        let scene = self.scene_framebuffer.get_color_attachment(0);
+        if self.effects.is_empty() {
+            self.scene_framebuffer.blit_to(output_target.framebuffer, output_target.size, gl::COLOR_BUFFER_BIT, TextureFilter::Linear);
+            return;
+        }
+
+        let mut previous = scene;
+        let effect_count = self.effects.len();
+        for (index, effect) in self.effects.iter_mut().enumerate() {
+            let _debug_group = gl_debug::push_debug_group(effect.get_name());
+            let context = EffectContext {
+                previous,
+                scene,
+                fullscreen_vertex_array: &self.fullscreen_vertex_array,
+            };
+
+            if index + 1 == effect_count {
+                effect.render(&context, output_target);
+            } else {
+                let framebuffer = &self.ping_pong_framebuffers[index % 2];
+                effect.render(&context, &EffectTarget { framebuffer: Some(framebuffer), size: (self.width, self.height) });
+                previous = framebuffer.get_color_attachment(0);
+            }
+        }
+    }
+}
@@ -0,0 +1,518 @@
+use crate::gl_capabilities;
+
+use gl::types::*;
+
+// GL 3.3 guarantees at least 8 draw buffers, so that many attachments can have their own blend state.
+pub const MAX_BLEND_ATTACHMENTS: usize = 8;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BlendFactor {
+    Zero,
+    One,
+    SourceColor,
+    OneMinusSourceColor,
+    DestinationColor,
+    OneMinusDestinationColor,
+    SourceAlpha,
+    OneMinusSourceAlpha,
+    DestinationAlpha,
+    OneMinusDestinationAlpha,
+    ConstantColor,
+    OneMinusConstantColor,
+    SourceAlphaSaturate,
+}
+
+impl BlendFactor {
+    pub fn get_gl_factor(self) -> GLenum {
+        match self {
+            BlendFactor::Zero => gl::ZERO,
+            BlendFactor::One => gl::ONE,
+            BlendFactor::SourceColor => gl::SRC_COLOR,
+            BlendFactor::OneMinusSourceColor => gl::ONE_MINUS_SRC_COLOR,
+            BlendFactor::DestinationColor => gl::DST_COLOR,
+            BlendFactor::OneMinusDestinationColor => gl::ONE_MINUS_DST_COLOR,
+            BlendFactor::SourceAlpha => gl::SRC_ALPHA,
+            BlendFactor::OneMinusSourceAlpha => gl::ONE_MINUS_SRC_ALPHA,
+            BlendFactor::DestinationAlpha => gl::DST_ALPHA,
+            BlendFactor::OneMinusDestinationAlpha => gl::ONE_MINUS_DST_ALPHA,
+            BlendFactor::ConstantColor => gl::CONSTANT_COLOR,
+            BlendFactor::OneMinusConstantColor => gl::ONE_MINUS_CONSTANT_COLOR,
+            BlendFactor::SourceAlphaSaturate => gl::SRC_ALPHA_SATURATE,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BlendEquation {
+    Add,
+    Subtract,
+    ReverseSubtract,
+    Min,
+    Max,
+}
+
+impl BlendEquation {
+    pub fn get_gl_equation(self) -> GLenum {
+        match self {
+            BlendEquation::Add => gl::FUNC_ADD,
+            BlendEquation::Subtract => gl::FUNC_SUBTRACT,
+            BlendEquation::ReverseSubtract => gl::FUNC_REVERSE_SUBTRACT,
+            BlendEquation::Min => gl::MIN,
+            BlendEquation::Max => gl::MAX,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CompareFunction {
+    Never,
+    Less,
+    Equal,
+    LessEqual,
+    Greater,
+    NotEqual,
+    GreaterEqual,
+    Always,
+}
+
+impl CompareFunction {
+    pub fn get_gl_function(self) -> GLenum {
+        match self {
+            CompareFunction::Never => gl::NEVER,
+            CompareFunction::Less => gl::LESS,
+            CompareFunction::Equal => gl::EQUAL,
+            CompareFunction::LessEqual => gl::LEQUAL,
+            CompareFunction::Greater => gl::GREATER,
+            CompareFunction::NotEqual => gl::NOTEQUAL,
+            CompareFunction::GreaterEqual => gl::GEQUAL,
+            CompareFunction::Always => gl::ALWAYS,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StencilOperation {
+    Keep,
+    Zero,
+    Replace,
+    Increment,
+    IncrementWrap,
+    Decrement,
+    DecrementWrap,
+    Invert,
+}
+
+impl StencilOperation {
+    pub fn get_gl_operation(self) -> GLenum {
+        match self {
+            StencilOperation::Keep => gl::KEEP,
+            StencilOperation::Zero => gl::ZERO,
+            StencilOperation::Replace => gl::REPLACE,
+            StencilOperation::Increment => gl::INCR,
+            StencilOperation::IncrementWrap => gl::INCR_WRAP,
+            StencilOperation::Decrement => gl::DECR,
+            StencilOperation::DecrementWrap => gl::DECR_WRAP,
+            StencilOperation::Invert => gl::INVERT,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CullMode {
+    None,
+    Front,
+    Back,
+    FrontAndBack,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FrontFace {
+    CounterClockwise,
+    Clockwise,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PolygonMode {
+    Fill,
+    Line,
+    Point,
+}
+
+impl PolygonMode {
+    pub fn get_gl_mode(self) -> GLenum {
+        match self {
+            PolygonMode::Fill => gl::FILL,
+            PolygonMode::Line => gl::LINE,
+            PolygonMode::Point => gl::POINT,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BlendState {
+    pub enabled: bool,
+    pub color_equation: BlendEquation,
+    pub alpha_equation: BlendEquation,
+    pub source_color: BlendFactor,
+    pub destination_color: BlendFactor,
+    pub source_alpha: BlendFactor,
+    pub destination_alpha: BlendFactor,
+    // Which of red, green, blue and alpha get written.
+    pub color_mask: [bool; 4],
+}
+
+impl BlendState {
+    pub fn alpha_blending() -> BlendState {
+        BlendState {
+            enabled: true,
+            source_color: BlendFactor::SourceAlpha,
+            destination_color: BlendFactor::OneMinusSourceAlpha,
+            source_alpha: BlendFactor::One,
+            destination_alpha: BlendFactor::OneMinusSourceAlpha,
+            ..BlendState::default()
+        }
+    }
+
+    pub fn premultiplied_alpha_blending() -> BlendState {
+        BlendState {
+            enabled: true,
+            source_color: BlendFactor::One,
+            destination_color: BlendFactor::OneMinusSourceAlpha,
+            source_alpha: BlendFactor::One,
+            destination_alpha: BlendFactor::OneMinusSourceAlpha,
+            ..BlendState::default()
+        }
+    }
+
+    pub fn additive_blending() -> BlendState {
+        BlendState {
+            enabled: true,
+            source_color: BlendFactor::One,
+            destination_color: BlendFactor::One,
+            source_alpha: BlendFactor::One,
+            destination_alpha: BlendFactor::One,
+            ..BlendState::default()
+        }
+    }
+}
+
+impl Default for BlendState {
+    fn default() -> BlendState {
+        BlendState {
+            enabled: false,
+            color_equation: BlendEquation::Add,
+            alpha_equation: BlendEquation::Add,
+            source_color: BlendFactor::One,
+            destination_color: BlendFactor::Zero,
+            source_alpha: BlendFactor::One,
+            destination_alpha: BlendFactor::Zero,
+            color_mask: [true; 4],
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DepthState {
+    pub test_enabled: bool,
+    pub write_enabled: bool,
+    pub function: CompareFunction,
+}
+
+impl Default for DepthState {
+    fn default() -> DepthState {
+        DepthState {
+            test_enabled: false,
+            write_enabled: true,
+            function: CompareFunction::Less,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct StencilFaceState {
+    pub fail: StencilOperation,
+    pub depth_fail: StencilOperation,
+    pub pass: StencilOperation,
+    pub function: CompareFunction,
+    pub reference: i32,
+    pub read_mask: u32,
+    pub write_mask: u32,
+}
+
+impl Default for StencilFaceState {
+    fn default() -> StencilFaceState {
+        StencilFaceState {
+            fail: StencilOperation::Keep,
+            depth_fail: StencilOperation::Keep,
+            pass: StencilOperation::Keep,
+            function: CompareFunction::Always,
+            reference: 0,
+            read_mask: !0,
+            write_mask: !0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct StencilState {
+    pub enabled: bool,
+    pub front: StencilFaceState,
+    pub back: StencilFaceState,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RasterizerState {
+    pub cull_mode: CullMode,
+    pub front_face: FrontFace,
+    pub polygon_mode: PolygonMode,
+    // (x, y, width, height) in window coordinates, or `None` to disable the scissor test.
+    pub scissor: Option<[i32; 4]>,
+}
+
+impl Default for RasterizerState {
+    fn default() -> RasterizerState {
+        RasterizerState {
+            cull_mode: CullMode::None,
+            front_face: FrontFace::CounterClockwise,
+            polygon_mode: PolygonMode::Fill,
+            scissor: None,
+        }
+    }
+}
+
+// A complete description of the fixed-function state a draw depends on. `RenderState::default()` matches the GL defaults.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct RenderState {
+    blend: [BlendState; MAX_BLEND_ATTACHMENTS],
+    blend_color: [f32; 4],
+    depth: DepthState,
+    stencil: StencilState,
+    rasterizer: RasterizerState,
+}
+
+impl RenderState {
+    pub fn with_blend(self, blend: BlendState) -> RenderState {
+        RenderState {
+            blend: [blend; MAX_BLEND_ATTACHMENTS],
+            ..self
+        }
+    }
+
+    pub fn with_attachment_blend(self, attachment: usize, blend: BlendState) -> RenderState {
+        let mut blend_states = self.blend;
+        blend_states[attachment] = blend;
+        RenderState {
+            blend: blend_states,
+            ..self
+        }
+    }
+
+    pub fn with_blend_color(self, blend_color: [f32; 4]) -> RenderState {
+        RenderState {
+            blend_color,
+            ..self
+        }
+    }
+
+    pub fn with_depth(self, depth: DepthState) -> RenderState {
+        RenderState {
+            depth,
+            ..self
+        }
+    }
+
+    pub fn with_stencil(self, stencil: StencilState) -> RenderState {
+        RenderState {
+            stencil,
+            ..self
+        }
+    }
+
+    pub fn with_rasterizer(self, rasterizer: RasterizerState) -> RenderState {
+        RenderState {
+            rasterizer,
+            ..self
+        }
+    }
+
+    pub fn get_blend(&self, attachment: usize) -> &BlendState {
+        &self.blend[attachment]
+    }
+
+    pub fn get_blend_color(&self) -> [f32; 4] {
+        self.blend_color
+    }
+
+    pub fn get_depth(&self) -> &DepthState {
+        &self.depth
+    }
+
+    pub fn get_stencil(&self) -> &StencilState {
+        &self.stencil
+    }
+
+    pub fn get_rasterizer(&self) -> &RasterizerState {
+        &self.rasterizer
+    }
+}
+
+// Remembers the last applied `RenderState` and only issues the GL calls for the parts that differ.
+// Anything that changes this state behind the cache's back has to call `invalidate`.
+pub struct RenderStateCache {
+    current: Option<RenderState>,
+}
+
+impl RenderStateCache {
+    pub fn new() -> RenderStateCache {
+        RenderStateCache {
+            current: None,
+        }
+    }
+
+    pub fn invalidate(&mut self) {
+        self.current = None;
+    }
+
+    pub fn get_current(&self) -> Option<&RenderState> {
+        self.current.as_ref()
+    }
+
+    pub fn apply(&mut self, state: &RenderState) {
+        let current = self.current;
+        unsafe {
+            // Without indexed blending the first attachment's state applies to every attachment.
+            let blend_attachment_count = if gl_capabilities::get().has_indexed_blending() { MAX_BLEND_ATTACHMENTS } else { 1 };
+            for (attachment, blend) in state.blend.iter().enumerate().take(blend_attachment_count) {
+                let previous = current.map(|current| current.blend[attachment]);
+                if blend_attachment_count == 1 {
+                    apply_global_blend_state(blend, previous.as_ref());
+                } else {
+                    apply_blend_state(attachment as GLuint, blend, previous.as_ref());
+                }
+            }
+            if current.is_none_or(|current| current.blend_color != state.blend_color) {
+                let [red, green, blue, alpha] = state.blend_color;
+                gl::BlendColor(red, green, blue, alpha);
+            }
+            if current.is_none_or(|current| current.depth != state.depth) {
+                apply_depth_state(&state.depth);
+            }
+            if current.is_none_or(|current| current.stencil != state.stencil) {
+                apply_stencil_state(&state.stencil);
+            }
+            apply_rasterizer_state(&state.rasterizer, current.as_ref().map(|current| &current.rasterizer));
+        }
+        self.current = Some(*state);
+    }
+}
+
+unsafe fn set_capability(capability: GLenum, enabled: bool) {
+    if enabled {
+        gl::Enable(capability);
+    } else {
+        gl::Disable(capability);
+    }
+}
+
+unsafe fn apply_blend_state(attachment: GLuint, blend: &BlendState, previous: Option<&BlendState>) {
+    if previous.is_none_or(|previous| previous.enabled != blend.enabled) {
+        if blend.enabled {
+            gl::Enablei(gl::BLEND, attachment);
+        } else {
+            gl::Disablei(gl::BLEND, attachment);
+        }
+    }
+    if previous.is_none_or(|previous| previous.color_equation != blend.color_equation || previous.alpha_equation != blend.alpha_equation) {
+        gl::BlendEquationSeparatei(attachment, blend.color_equation.get_gl_equation(), blend.alpha_equation.get_gl_equation());
+    }
+    let factors = (blend.source_color, blend.destination_color, blend.source_alpha, blend.destination_alpha);
+    if previous.is_none_or(|previous| (previous.source_color, previous.destination_color, previous.source_alpha, previous.destination_alpha) != factors) {
+        gl::BlendFuncSeparatei(
+            attachment,
+            blend.source_color.get_gl_factor(),
+            blend.destination_color.get_gl_factor(),
+            blend.source_alpha.get_gl_factor(),
+            blend.destination_alpha.get_gl_factor(),
+        );
+    }
+    if previous.is_none_or(|previous| previous.color_mask != blend.color_mask) {
+        let [red, green, blue, alpha] = blend.color_mask;
+        gl::ColorMaski(attachment, red as GLboolean, green as GLboolean, blue as GLboolean, alpha as GLboolean);
+    }
+}
+
+unsafe fn apply_global_blend_state(blend: &BlendState, previous: Option<&BlendState>) {
+    if previous.is_none_or(|previous| previous.enabled != blend.enabled) {
+        set_capability(gl::BLEND, blend.enabled);
+    }
+    if previous.is_none_or(|previous| previous.color_equation != blend.color_equation || previous.alpha_equation != blend.alpha_equation) {
+        gl::BlendEquationSeparate(blend.color_equation.get_gl_equation(), blend.alpha_equation.get_gl_equation());
+    }
+    let factors = (blend.source_color, blend.destination_color, blend.source_alpha, blend.destination_alpha);
+    if previous.is_none_or(|previous| (previous.source_color, previous.destination_color, previous.source_alpha, previous.destination_alpha) != factors) {
+        gl::BlendFuncSeparate(
+            blend.source_color.get_gl_factor(),
+            blend.destination_color.get_gl_factor(),
+            blend.source_alpha.get_gl_factor(),
+            blend.destination_alpha.get_gl_factor(),
+        );
+    }
+    if previous.is_none_or(|previous| previous.color_mask != blend.color_mask) {
+        let [red, green, blue, alpha] = blend.color_mask;
+        gl::ColorMask(red as GLboolean, green as GLboolean, blue as GLboolean, alpha as GLboolean);
+    }
+}
+
+unsafe fn apply_depth_state(depth: &DepthState) {
+    set_capability(gl::DEPTH_TEST, depth.test_enabled);
+    gl::DepthMask(depth.write_enabled as GLboolean);
+    gl::DepthFunc(depth.function.get_gl_function());
+}
+
+unsafe fn apply_stencil_state(stencil: &StencilState) {
+    set_capability(gl::STENCIL_TEST, stencil.enabled);
+    for (face, state) in [(gl::FRONT, &stencil.front), (gl::BACK, &stencil.back)] {
+        gl::StencilFuncSeparate(face, state.function.get_gl_function(), state.reference, state.read_mask);
+        gl::StencilOpSeparate(face, state.fail.get_gl_operation(), state.depth_fail.get_gl_operation(), state.pass.get_gl_operation());
+        gl::StencilMaskSeparate(face, state.write_mask);
+    }
+}
+
+unsafe fn apply_rasterizer_state(rasterizer: &RasterizerState, previous: Option<&RasterizerState>) {
+    if previous.is_none_or(|previous| previous.cull_mode != rasterizer.cull_mode) {
+        match rasterizer.cull_mode {
+            CullMode::None => gl::Disable(gl::CULL_FACE),
+            CullMode::Front => {
+                gl::Enable(gl::CULL_FACE);
+                gl::CullFace(gl::FRONT);
+            }
+            CullMode::Back => {
+                gl::Enable(gl::CULL_FACE);
+                gl::CullFace(gl::BACK);
+            }
+            CullMode::FrontAndBack => {
+                gl::Enable(gl::CULL_FACE);
+                gl::CullFace(gl::FRONT_AND_BACK);
+            }
+        }
+    }
+    if previous.is_none_or(|previous| previous.front_face != rasterizer.front_face) {
+        gl::FrontFace(if rasterizer.front_face == FrontFace::CounterClockwise { gl::CCW } else { gl::CW });
+    }
+    if previous.is_none_or(|previous| previous.polygon_mode != rasterizer.polygon_mode) {
+        if gl_capabilities::get().has_polygon_mode() {
+            gl::PolygonMode(gl::FRONT_AND_BACK, rasterizer.polygon_mode.get_gl_mode());
+        } else {
+            assert!(rasterizer.polygon_mode == PolygonMode::Fill, "This context can only fill polygons!");
+        }
+    }
+    if previous.is_none_or(|previous| previous.scissor != rasterizer.scissor) {
+        match rasterizer.scissor {
+            Some([x, y, width, height]) => {
+                gl::Enable(gl::SCISSOR_TEST);
+                gl::Scissor(x, y, width, height);
+            }
+            None => gl::Disable(gl::SCISSOR_TEST),
+        }
+    }
+}
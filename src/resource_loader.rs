@@ -0,0 +1,194 @@
+use crate::gl_context::{ GLContext };
+use crate::opengl_fence::{ FenceStatus, OpenGLFence };
+use crate::opengl_texture::{ OpenGLTexture, SharedTexture, TextureDescriptor, TextureFormat };
+use crate::opengl_vertex_buffer::{ OpenGLVertexBuffer, SharedVertexBuffer };
+
+use glfw::{ Context };
+use image::{ DynamicImage };
+
+use std::path::{ PathBuf };
+use std::sync::mpsc::{ self, Receiver, Sender, TryRecvError };
+use std::thread::{ JoinHandle };
+use std::time::{ Duration };
+
+type LoadJob = Box<dyn FnOnce() + Send>;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Image(image::ImageError),
+    // The loader thread went away before finishing, e.g. because a job panicked.
+    LoaderStopped,
+    // Waiting for the GPU to finish the upload failed, e.g. because the context was lost.
+    FenceFailed,
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LoadError::Image(error) => write!(f, "Failed to decode the image: {}", error),
+            LoadError::LoaderStopped => write!(f, "The resource loader stopped before finishing the upload"),
+            LoadError::FenceFailed => write!(f, "Waiting for the upload to finish on the GPU failed"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<image::ImageError> for LoadError {
+    fn from(error: image::ImageError) -> LoadError {
+        LoadError::Image(error)
+    }
+}
+
+// A resource created on the loader's context that the render thread's context takes ownership of.
+pub trait SharedResource: Send + 'static {
+    type Resource;
+
+    fn adopt(self) -> Self::Resource;
+}
+
+impl SharedResource for SharedTexture {
+    type Resource = OpenGLTexture;
+
+    fn adopt(self) -> OpenGLTexture {
+        OpenGLTexture::from_shared(self)
+    }
+}
+
+impl SharedResource for SharedVertexBuffer {
+    type Resource = OpenGLVertexBuffer;
+
+    fn adopt(self) -> OpenGLVertexBuffer {
+        OpenGLVertexBuffer::from_shared(self)
+    }
+}
+
+// The pending result of an upload on the loader thread. It is ready once the loader has finished issuing the upload
+// and the GPU has completed it, so using the resource never waits on the loader.
+pub struct UploadHandle<S: SharedResource> {
+    receiver: Receiver<Result<(S, OpenGLFence), LoadError>>,
+    result: Option<Result<(S, OpenGLFence), LoadError>>,
+}
+
+pub type TextureUpload = UploadHandle<SharedTexture>;
+pub type VertexBufferUpload = UploadHandle<SharedVertexBuffer>;
+
+impl<S: SharedResource> UploadHandle<S> {
+    // Never blocks. Failed uploads count as ready, so `wait` returns their error straight away.
+    pub fn is_ready(&mut self) -> bool {
+        if self.result.is_none() {
+            match self.receiver.try_recv() {
+                Ok(result) => self.result = Some(result),
+                Err(TryRecvError::Empty) => return false,
+                Err(TryRecvError::Disconnected) => self.result = Some(Err(LoadError::LoaderStopped)),
+            }
+        }
+
+        return match &self.result {
+            Some(Ok((_, fence))) => fence.is_signaled(),
+            _ => true,
+        };
+    }
+
+    // Hands the resource to the calling thread's context, blocking until it is ready.
+    pub fn wait(mut self) -> Result<S::Resource, LoadError> {
+        let result = match self.result.take() {
+            Some(result) => result,
+            None => self.receiver.recv().unwrap_or(Err(LoadError::LoaderStopped)),
+        };
+
+        let (resource, fence) = result?;
+        loop {
+            match fence.wait(Duration::from_millis(100)) {
+                FenceStatus::Signaled => return Ok(resource.adopt()),
+                FenceStatus::TimedOut => continue,
+                // Dropping the resource queues its deletion on the calling thread's context.
+                FenceStatus::Failed => return Err(LoadError::FenceFailed),
+            }
+        }
+    }
+}
+
+// Uploads resources on a worker thread with its own context, so large uploads don't stall rendering. The worker's
+// context has to share objects with the render context, e.g. a hidden window made with `Window::create_shared`.
+//
+// Jobs run in submission order. Dropping the loader finishes the queued jobs before the thread exits, so it has to
+// be dropped before the shared window.
+pub struct ResourceLoader {
+    sender: Option<Sender<LoadJob>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ResourceLoader {
+    pub fn new(mut render_context: glfw::RenderContext) -> ResourceLoader {
+        let (sender, receiver) = mpsc::channel::<LoadJob>();
+        let thread = std::thread::Builder::new()
+            .name("Resource Loader".to_string())
+            .spawn(move || {
+                render_context.make_current();
+                // The `gl` function pointers are global, so only the per-context state needs setting up.
+                let gl_context = GLContext::new(&render_context);
+                for job in receiver {
+                    job();
+                    gl_context.flush_destruction_queue();
+                }
+
+                drop(gl_context);
+                glfw::make_context_current(None);
+            })
+            .expect("Failed to start the resource loader thread!");
+
+        return ResourceLoader {
+            sender: Some(sender),
+            thread: Some(thread),
+        };
+    }
+
+    fn submit<S, F>(&self, load: F) -> UploadHandle<S>
+    where
+        S: SharedResource,
+        F: FnOnce() -> Result<S, LoadError> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let job: LoadJob = Box::new(move || {
+            let result = load().map(|resource| (resource, OpenGLFence::new()));
+            // The handle may already have been dropped, which the resource and fence then clean up after.
+            let _ = sender.send(result);
+        });
+        // If the thread has stopped, the job is dropped along with its sender, so the handle reports `LoaderStopped`.
+        let _ = self.sender.as_ref().unwrap().send(job);
+
+        return UploadHandle {
+            receiver,
+            result: None,
+        };
+    }
+
+    pub fn load_texture(&self, image: DynamicImage, descriptor: TextureDescriptor) -> TextureUpload {
+        self.submit(move || Ok(OpenGLTexture::from_dynamic_image(&image, &descriptor).into_shared()))
+    }
+
+    // Decodes the image on the loader thread as well, flipped so its first row ends up at the bottom like GL expects.
+    pub fn load_texture_file<P: Into<PathBuf>>(&self, path: P, srgb: bool) -> TextureUpload {
+        let path = path.into();
+        self.submit(move || {
+            let image = image::open(&path)?.flipv();
+            let descriptor = TextureDescriptor::new(TextureFormat::from_dynamic_image(&image, srgb));
+            return Ok(OpenGLTexture::from_dynamic_image(&image, &descriptor).into_shared());
+        })
+    }
+
+    pub fn upload_vertex_buffer<T: Send + 'static>(&self, data: Vec<T>) -> VertexBufferUpload {
+        self.submit(move || Ok(OpenGLVertexBuffer::new(&data).into_shared()))
+    }
+}
+
+impl Drop for ResourceLoader {
+    fn drop(&mut self) {
+        // Closing the channel ends the worker's job loop.
+        self.sender.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
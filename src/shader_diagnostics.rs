@@ -0,0 +1,254 @@
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DiagnosticSeverity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl DiagnosticSeverity {
+    pub fn get_name(self) -> &'static str {
+        match self {
+            DiagnosticSeverity::Error => "error",
+            DiagnosticSeverity::Warning => "warning",
+            DiagnosticSeverity::Note => "note",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ShaderDiagnostic {
+    pub severity: DiagnosticSeverity,
+    pub source_index: u32,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    pub message: String,
+}
+
+// Mesa:   "0:12(5): error: `foo' undeclared"
+// NVIDIA: "0(12) : error C1008: undefined variable "foo""
+// AMD:    "ERROR: 0:12: 'foo' : undeclared identifier"
+pub fn parse_info_log(info_log: &str) -> Vec<ShaderDiagnostic> {
+    let mut diagnostics: Vec<ShaderDiagnostic> = Vec::new();
+    for line in info_log.lines() {
+        let line = line.trim_end_matches(['\0', '\r', ' ']);
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if let Some(diagnostic) = parse_mesa_line(line)
+            .or_else(|| parse_nvidia_line(line))
+            .or_else(|| parse_amd_line(line)) {
+            diagnostics.push(diagnostic);
+        } else if is_summary_line(line) {
+            continue;
+        } else if let Some(last) = diagnostics.last_mut().filter(|_| line.starts_with(char::is_whitespace)) {
+            last.message.push('\n');
+            last.message.push_str(line.trim());
+        } else {
+            diagnostics.push(ShaderDiagnostic {
+                severity: DiagnosticSeverity::Error,
+                source_index: 0,
+                line: None,
+                column: None,
+                message: line.trim().to_string(),
+            });
+        }
+    }
+    return diagnostics;
+}
+
+pub fn has_errors(diagnostics: &[ShaderDiagnostic]) -> bool {
+    diagnostics.iter().any(|diagnostic| diagnostic.severity == DiagnosticSeverity::Error)
+}
+
+pub fn render_diagnostic(diagnostic: &ShaderDiagnostic, source_name: &str, source: &str) -> String {
+    let mut output = format!("{}: {}\n", diagnostic.severity.get_name(), diagnostic.message);
+
+    let line_number = match diagnostic.line {
+        Some(line_number) if line_number > 0 => line_number as usize,
+        _ => {
+            output.push_str(&format!("  --> {}\n", source_name));
+            return output;
+        }
+    };
+
+    match diagnostic.column {
+        Some(column) => output.push_str(&format!("  --> {}:{}:{}\n", source_name, line_number, column)),
+        None => output.push_str(&format!("  --> {}:{}\n", source_name, line_number)),
+    }
+
+    let lines: Vec<&str> = source.lines().collect();
+    if line_number > lines.len() {
+        return output;
+    }
+
+    let first_line = if line_number > 1 { line_number - 1 } else { line_number };
+    let last_line = std::cmp::min(line_number + 1, lines.len());
+    let gutter_width = last_line.to_string().len();
+
+    output.push_str(&format!("{:width$} |\n", "", width = gutter_width));
+    for current_line in first_line..=last_line {
+        let text = lines[current_line - 1];
+        output.push_str(&format!("{:>width$} | {}\n", current_line, text, width = gutter_width));
+
+        if current_line == line_number {
+            let indent = text.len() - text.trim_start().len();
+            let (caret_offset, caret_length) = match diagnostic.column {
+                Some(column) if column > 0 && (column as usize) <= text.len() => (column as usize - 1, 1),
+                _ => (indent, std::cmp::max(text.trim().len(), 1)),
+            };
+            let padding: String = text.chars().take(caret_offset).map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+            output.push_str(&format!("{:width$} | {}{}\n", "", padding, "^".repeat(caret_length), width = gutter_width));
+        }
+    }
+    output.push_str(&format!("{:width$} |\n", "", width = gutter_width));
+
+    return output;
+}
+
+fn parse_mesa_line(line: &str) -> Option<ShaderDiagnostic> {
+    let (source_index, rest) = parse_number(line)?;
+    let rest = rest.strip_prefix(':')?;
+    let (line_number, rest) = parse_number(rest)?;
+    let rest = rest.strip_prefix('(')?;
+    let (column, rest) = parse_number(rest)?;
+    let rest = rest.strip_prefix("): ")?;
+    let (severity, message) = parse_severity_prefix(rest)?;
+    let message = message.strip_prefix(": ")?;
+
+    return Some(ShaderDiagnostic {
+        severity,
+        source_index,
+        line: Some(line_number),
+        column: Some(column),
+        message: message.trim().to_string(),
+    });
+}
+
+fn parse_nvidia_line(line: &str) -> Option<ShaderDiagnostic> {
+    let (source_index, rest) = parse_number(line)?;
+    let rest = rest.strip_prefix('(')?;
+    let (line_number, rest) = parse_number(rest)?;
+    let rest = rest.strip_prefix(')')?.trim_start();
+    let rest = rest.strip_prefix(':')?.trim_start();
+    let rest = rest.strip_prefix("fatal ").unwrap_or(rest);
+    let (severity, message) = parse_severity_prefix(rest)?;
+
+    return Some(ShaderDiagnostic {
+        severity,
+        source_index,
+        line: Some(line_number),
+        column: None,
+        message: message.trim_start_matches(':').trim().to_string(),
+    });
+}
+
+fn parse_amd_line(line: &str) -> Option<ShaderDiagnostic> {
+    let (severity, rest) = parse_severity_prefix(line)?;
+    let rest = rest.strip_prefix(": ")?;
+    let (source_index, rest) = parse_number(rest)?;
+    let rest = rest.strip_prefix(':')?;
+    let (line_number, rest) = parse_number(rest)?;
+    let message = rest.strip_prefix(':')?;
+
+    return Some(ShaderDiagnostic {
+        severity,
+        source_index,
+        line: Some(line_number),
+        column: None,
+        message: message.trim().to_string(),
+    });
+}
+
+fn is_summary_line(line: &str) -> bool {
+    match parse_severity_prefix(line) {
+        Some((_, rest)) => rest.strip_prefix(": ")
+            .and_then(parse_number)
+            .is_some_and(|(_, rest)| rest.trim_start().starts_with("compilation error")),
+        None => false,
+    }
+}
+
+fn parse_severity_prefix(text: &str) -> Option<(DiagnosticSeverity, &str)> {
+    let prefixes = [
+        ("error", DiagnosticSeverity::Error),
+        ("warning", DiagnosticSeverity::Warning),
+        ("info", DiagnosticSeverity::Note),
+        ("note", DiagnosticSeverity::Note),
+    ];
+    for (prefix, severity) in prefixes.iter() {
+        if text.get(..prefix.len()).is_some_and(|start| start.eq_ignore_ascii_case(prefix)) {
+            return Some((*severity, &text[prefix.len()..]));
+        }
+    }
+    return None;
+}
+
+fn parse_number(text: &str) -> Option<(u32, &str)> {
+    let digits = text.bytes().take_while(u8::is_ascii_digit).count();
+    if digits == 0 {
+        return None;
+    }
+    let number = text[..digits].parse().ok()?;
+    return Some((number, &text[digits..]));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_mesa_lines() {
+        let diagnostics = parse_info_log("0:12(5): error: `foo' undeclared\n0:14(1): warning: unused variable\n");
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0], ShaderDiagnostic {
+            severity: DiagnosticSeverity::Error,
+            source_index: 0,
+            line: Some(12),
+            column: Some(5),
+            message: "`foo' undeclared".to_string(),
+        });
+        assert_eq!(diagnostics[1].severity, DiagnosticSeverity::Warning);
+        assert_eq!(diagnostics[1].line, Some(14));
+    }
+
+    #[test]
+    fn parses_nvidia_lines() {
+        let diagnostics = parse_info_log("0(12) : error C1008: undefined variable \"foo\"\n0(3) : fatal error C9999: out of memory\0");
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0], ShaderDiagnostic {
+            severity: DiagnosticSeverity::Error,
+            source_index: 0,
+            line: Some(12),
+            column: None,
+            message: "C1008: undefined variable \"foo\"".to_string(),
+        });
+        assert_eq!(diagnostics[1].line, Some(3));
+        assert_eq!(diagnostics[1].message, "C9999: out of memory");
+    }
+
+    #[test]
+    fn parses_amd_lines_and_skips_the_summary() {
+        let diagnostics = parse_info_log("ERROR: 0:12: 'foo' : undeclared identifier\nERROR: 1 compilation errors.  No code generated.\n");
+        assert_eq!(diagnostics, vec![ShaderDiagnostic {
+            severity: DiagnosticSeverity::Error,
+            source_index: 0,
+            line: Some(12),
+            column: None,
+            message: "'foo' : undeclared identifier".to_string(),
+        }]);
+    }
+
+    #[test]
+    fn keeps_unrecognised_lines_as_errors() {
+        let diagnostics = parse_info_log("Something went wrong\n    while linking\n");
+        assert_eq!(diagnostics, vec![ShaderDiagnostic {
+            severity: DiagnosticSeverity::Error,
+            source_index: 0,
+            line: None,
+            column: None,
+            message: "Something went wrong\nwhile linking".to_string(),
+        }]);
+        assert!(has_errors(&diagnostics));
+    }
+}
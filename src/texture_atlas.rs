@@ -0,0 +1,375 @@
+use crate::opengl_texture::{ OpenGLTexture, TextureDescriptor };
+
+use image::{ RgbaImage };
+use serde::{ Deserialize, Serialize };
+
+use std::collections::{ HashMap };
+use std::path::{ Path };
+
+#[derive(Debug)]
+pub enum AtlasError {
+    Full,
+    DuplicateName(String),
+    Io(std::io::Error),
+    Image(image::ImageError),
+    Json(serde_json::Error),
+}
+
+impl std::fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AtlasError::Full => write!(f, "The atlas has no room left for the image"),
+            AtlasError::DuplicateName(name) => write!(f, "The atlas already contains an image named '{}'", name),
+            AtlasError::Io(error) => write!(f, "Failed to access the atlas file: {}", error),
+            AtlasError::Image(error) => write!(f, "Failed to encode or decode the atlas image: {}", error),
+            AtlasError::Json(error) => write!(f, "Failed to encode or decode the atlas metadata: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for AtlasError {}
+
+impl From<std::io::Error> for AtlasError {
+    fn from(error: std::io::Error) -> AtlasError {
+        AtlasError::Io(error)
+    }
+}
+
+impl From<image::ImageError> for AtlasError {
+    fn from(error: image::ImageError) -> AtlasError {
+        AtlasError::Image(error)
+    }
+}
+
+impl From<serde_json::Error> for AtlasError {
+    fn from(error: serde_json::Error) -> AtlasError {
+        AtlasError::Json(error)
+    }
+}
+
+// The pixel rectangle of an image inside the atlas, excluding its padding and extruded border.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct AtlasRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct SkylineSegment {
+    x: u32,
+    y: u32,
+    width: u32,
+}
+
+// A skyline bottom-left packer: the top edge of everything placed so far is kept as a list of horizontal segments,
+// and each new rectangle goes wherever it ends up lowest.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct SkylinePacker {
+    width: u32,
+    height: u32,
+    skyline: Vec<SkylineSegment>,
+}
+
+impl SkylinePacker {
+    fn new(width: u32, height: u32) -> SkylinePacker {
+        SkylinePacker {
+            width,
+            height,
+            skyline: vec![SkylineSegment { x: 0, y: 0, width }],
+        }
+    }
+
+    fn insert(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let mut best: Option<(usize, u32, u32)> = None;
+        for index in 0..self.skyline.len() {
+            if let Some(y) = self.get_fit_height(index, width, height) {
+                let x = self.skyline[index].x;
+                if best.is_none_or(|(_, best_x, best_y)| y < best_y || (y == best_y && x < best_x)) {
+                    best = Some((index, x, y));
+                }
+            }
+        }
+
+        let (index, x, y) = best?;
+        self.add_segment(index, x, y + height, width);
+        return Some((x, y));
+    }
+
+    // The height a rectangle would rest at if its left edge was placed at the start of segment `index`.
+    fn get_fit_height(&self, index: usize, width: u32, height: u32) -> Option<u32> {
+        let x = self.skyline[index].x;
+        if x + width > self.width {
+            return None;
+        }
+
+        let mut y = 0;
+        let mut remaining_width = width as i64;
+        for segment in &self.skyline[index..] {
+            if remaining_width <= 0 {
+                break;
+            }
+            y = std::cmp::max(y, segment.y);
+            remaining_width -= segment.width as i64;
+        }
+
+        if y + height > self.height {
+            return None;
+        }
+        return Some(y);
+    }
+
+    fn add_segment(&mut self, index: usize, x: u32, y: u32, width: u32) {
+        self.skyline.insert(index, SkylineSegment { x, y, width });
+
+        // Cut away the parts of the following segments that are now covered by the new one.
+        let right = x + width;
+        while index + 1 < self.skyline.len() {
+            let next = &mut self.skyline[index + 1];
+            if next.x >= right {
+                break;
+            }
+            let covered = right - next.x;
+            if covered < next.width {
+                next.x += covered;
+                next.width -= covered;
+                break;
+            }
+            self.skyline.remove(index + 1);
+        }
+
+        let mut merge_index = 0;
+        while merge_index + 1 < self.skyline.len() {
+            if self.skyline[merge_index].y == self.skyline[merge_index + 1].y {
+                self.skyline[merge_index].width += self.skyline[merge_index + 1].width;
+                self.skyline.remove(merge_index + 1);
+            } else {
+                merge_index += 1;
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TextureAtlas {
+    width: u32,
+    height: u32,
+    // Empty pixels left between neighbouring images.
+    padding: u32,
+    // How many times the outermost row and column of each image are repeated around it, so linear filtering and
+    // mipmapping don't pull in texels from the neighbours.
+    extrude: u32,
+    packer: SkylinePacker,
+    regions: HashMap<String, AtlasRegion>,
+    #[serde(skip)]
+    image: RgbaImage,
+    // The area touched since the last upload, as (x, y, width, height).
+    #[serde(skip)]
+    dirty_area: Option<(u32, u32, u32, u32)>,
+}
+
+impl TextureAtlas {
+    pub fn new(width: u32, height: u32, padding: u32, extrude: u32) -> TextureAtlas {
+        TextureAtlas {
+            width,
+            height,
+            padding,
+            extrude,
+            packer: SkylinePacker::new(width, height),
+            regions: HashMap::new(),
+            image: RgbaImage::new(width, height),
+            dirty_area: None,
+        }
+    }
+
+    // Packing the tallest images first gives a much flatter skyline than packing in the given order.
+    pub fn build(images: &[(&str, &RgbaImage)], width: u32, height: u32, padding: u32, extrude: u32) -> Result<TextureAtlas, AtlasError> {
+        let mut sorted_images = images.to_vec();
+        sorted_images.sort_by(|(_, a), (_, b)| b.height().cmp(&a.height()).then(b.width().cmp(&a.width())));
+
+        let mut atlas = TextureAtlas::new(width, height, padding, extrude);
+        for (name, image) in sorted_images {
+            atlas.insert(name, image)?;
+        }
+        return Ok(atlas);
+    }
+
+    pub fn insert(&mut self, name: &str, image: &RgbaImage) -> Result<AtlasRegion, AtlasError> {
+        if self.regions.contains_key(name) {
+            return Err(AtlasError::DuplicateName(name.to_string()));
+        }
+
+        // The padding only goes on the right and bottom, the skyline already starts flush with the atlas edges.
+        let border = self.extrude * 2;
+        let (x, y) = self.packer
+            .insert(image.width() + border + self.padding, image.height() + border + self.padding)
+            .ok_or(AtlasError::Full)?;
+
+        let region = AtlasRegion {
+            x: x + self.extrude,
+            y: y + self.extrude,
+            width: image.width(),
+            height: image.height(),
+        };
+        self.blit_extruded(image, region);
+        self.mark_dirty(x, y, std::cmp::min(image.width() + border, self.width - x), std::cmp::min(image.height() + border, self.height - y));
+
+        self.regions.insert(name.to_string(), region);
+        return Ok(region);
+    }
+
+    fn blit_extruded(&mut self, image: &RgbaImage, region: AtlasRegion) {
+        // Empty images, like the glyph of a space, have no edge to extrude.
+        if image.width() == 0 || image.height() == 0 {
+            return;
+        }
+
+        let extrude = self.extrude as i64;
+        for y in -extrude..region.height as i64 + extrude {
+            for x in -extrude..region.width as i64 + extrude {
+                let atlas_x = region.x as i64 + x;
+                let atlas_y = region.y as i64 + y;
+                if atlas_x < 0 || atlas_y < 0 || atlas_x >= self.width as i64 || atlas_y >= self.height as i64 {
+                    continue;
+                }
+
+                let source_x = x.clamp(0, region.width as i64 - 1) as u32;
+                let source_y = y.clamp(0, region.height as i64 - 1) as u32;
+                self.image.put_pixel(atlas_x as u32, atlas_y as u32, *image.get_pixel(source_x, source_y));
+            }
+        }
+    }
+
+    fn mark_dirty(&mut self, x: u32, y: u32, width: u32, height: u32) {
+        self.dirty_area = Some(match self.dirty_area {
+            Some((dirty_x, dirty_y, dirty_width, dirty_height)) => {
+                let left = std::cmp::min(x, dirty_x);
+                let top = std::cmp::min(y, dirty_y);
+                let right = std::cmp::max(x + width, dirty_x + dirty_width);
+                let bottom = std::cmp::max(y + height, dirty_y + dirty_height);
+                (left, top, right - left, bottom - top)
+            }
+            None => (x, y, width, height),
+        });
+    }
+
+    pub fn get_region(&self, name: &str) -> Option<AtlasRegion> {
+        self.regions.get(name).copied()
+    }
+
+    // Returns the normalized (u0, v0, u1, v1) rectangle, with v measured from the top row of the atlas image.
+    pub fn get_uv_rect(&self, name: &str) -> Option<[f32; 4]> {
+        let region = self.get_region(name)?;
+        return Some([
+            region.x as f32 / self.width as f32,
+            region.y as f32 / self.height as f32,
+            (region.x + region.width) as f32 / self.width as f32,
+            (region.y + region.height) as f32 / self.height as f32,
+        ]);
+    }
+
+    pub fn get_regions(&self) -> &HashMap<String, AtlasRegion> {
+        &self.regions
+    }
+
+    pub fn get_image(&self) -> &RgbaImage {
+        &self.image
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn create_texture(&mut self, descriptor: &TextureDescriptor) -> OpenGLTexture {
+        self.dirty_area = None;
+        return OpenGLTexture::with_descriptor(self.image.as_raw(), self.width, self.height, descriptor);
+    }
+
+    // Uploads only the area that changed since the texture was created or last updated, for glyph caches that grow at runtime.
+    // Mipmapped textures still need `generate_mipmaps` afterwards.
+    pub fn update_texture(&mut self, texture: &mut OpenGLTexture) {
+        assert!(texture.get_width() == self.width && texture.get_height() == self.height, "The texture doesn't match the atlas size!");
+
+        if let Some((x, y, width, height)) = self.dirty_area.take() {
+            let pixels = image::imageops::crop_imm(&self.image, x, y, width, height).to_image();
+            texture.update_region(x, y, width, height, pixels.as_raw());
+        }
+    }
+
+    // Bakes the atlas to a PNG next to a JSON file holding the packer state and regions.
+    pub fn save<P: AsRef<Path>>(&self, image_path: P, metadata_path: P) -> Result<(), AtlasError> {
+        self.image.save(image_path)?;
+        std::fs::write(metadata_path, serde_json::to_string_pretty(self)?)?;
+        return Ok(());
+    }
+
+    pub fn load<P: AsRef<Path>>(image_path: P, metadata_path: P) -> Result<TextureAtlas, AtlasError> {
+        let mut atlas: TextureAtlas = serde_json::from_str(&std::fs::read_to_string(metadata_path)?)?;
+        atlas.image = image::open(image_path)?.to_rgba8();
+        if atlas.image.width() != atlas.width || atlas.image.height() != atlas.height {
+            return Err(AtlasError::Image(image::ImageError::Parameter(image::error::ParameterError::from_kind(
+                image::error::ParameterErrorKind::DimensionMismatch,
+            ))));
+        }
+        return Ok(atlas);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_image(width: u32, height: u32, value: u8) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, image::Rgba([value, value, value, 255]))
+    }
+
+    #[test]
+    fn packs_images_bottom_left() {
+        let mut atlas = TextureAtlas::new(16, 16, 0, 0);
+        assert_eq!(atlas.insert("a", &create_image(8, 4, 1)).unwrap(), AtlasRegion { x: 0, y: 0, width: 8, height: 4 });
+        assert_eq!(atlas.insert("b", &create_image(8, 2, 2)).unwrap(), AtlasRegion { x: 8, y: 0, width: 8, height: 2 });
+        // The lowest spot is on top of the shorter image.
+        assert_eq!(atlas.insert("c", &create_image(8, 8, 3)).unwrap(), AtlasRegion { x: 8, y: 2, width: 8, height: 8 });
+        assert_eq!(atlas.get_image().get_pixel(9, 3).0, [3, 3, 3, 255]);
+        assert_eq!(atlas.get_uv_rect("b"), Some([0.5, 0.0, 1.0, 0.125]));
+    }
+
+    #[test]
+    fn extrudes_edges_and_skips_empty_images() {
+        let mut atlas = TextureAtlas::new(8, 8, 1, 1);
+        let region = atlas.insert("a", &create_image(2, 2, 9)).unwrap();
+        assert_eq!(region, AtlasRegion { x: 1, y: 1, width: 2, height: 2 });
+        assert_eq!(atlas.get_image().get_pixel(0, 0).0, [9, 9, 9, 255]);
+        assert_eq!(atlas.get_image().get_pixel(3, 3).0, [9, 9, 9, 255]);
+        assert_eq!(atlas.get_image().get_pixel(4, 4).0, [0, 0, 0, 0]);
+
+        let space = atlas.insert("space", &RgbaImage::new(0, 0)).unwrap();
+        assert_eq!((space.width, space.height), (0, 0));
+    }
+
+    #[test]
+    fn rejects_images_that_dont_fit() {
+        let mut atlas = TextureAtlas::new(8, 8, 0, 0);
+        assert!(matches!(atlas.insert("wide", &create_image(9, 1, 1)), Err(AtlasError::Full)));
+        atlas.insert("full", &create_image(8, 8, 1)).unwrap();
+        assert!(matches!(atlas.insert("more", &create_image(1, 1, 1)), Err(AtlasError::Full)));
+        assert!(matches!(atlas.insert("full", &create_image(1, 1, 1)), Err(AtlasError::DuplicateName(_))));
+    }
+
+    #[test]
+    fn metadata_round_trips_through_json() {
+        let mut atlas = TextureAtlas::build(&[("a", &create_image(4, 6, 1)), ("b", &create_image(5, 3, 2))], 32, 32, 1, 0).unwrap();
+        let mut loaded: TextureAtlas = serde_json::from_str(&serde_json::to_string(&atlas).unwrap()).unwrap();
+        loaded.image = atlas.get_image().clone();
+
+        assert_eq!(loaded.get_regions(), atlas.get_regions());
+        // The packer state comes along too, so both place the next image in the same spot.
+        let next = create_image(7, 2, 3);
+        assert_eq!(loaded.insert("c", &next).unwrap(), atlas.insert("c", &next).unwrap());
+    }
+}
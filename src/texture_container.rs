@@ -0,0 +1,687 @@
+use crate::gl_extensions;
+use crate::mipmap;
+use crate::opengl_texture::{ TextureFormat };
+
+use gl::types::*;
+
+const ASTC_BLOCK_SIZES: [(u32, u32); 14] = [
+    (4, 4), (5, 4), (5, 5), (6, 5), (6, 6), (8, 5), (8, 6), (8, 8), (10, 5), (10, 6), (10, 8), (10, 10), (12, 10), (12, 12),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CompressedFormat {
+    Bc1 { alpha: bool, srgb: bool },
+    Bc2 { srgb: bool },
+    Bc3 { srgb: bool },
+    Bc4 { signed: bool },
+    Bc5 { signed: bool },
+    Bc6H { signed: bool },
+    Bc7 { srgb: bool },
+    Etc2Rgb8 { srgb: bool },
+    Etc2Rgb8A1 { srgb: bool },
+    Etc2Rgba8 { srgb: bool },
+    EacR11 { signed: bool },
+    EacRg11 { signed: bool },
+    Astc { block_width: u32, block_height: u32, srgb: bool },
+}
+
+impl CompressedFormat {
+    pub fn get_internal_format(self) -> GLenum {
+        match self {
+            CompressedFormat::Bc1 { alpha: false, srgb: false } => gl_extensions::COMPRESSED_RGB_S3TC_DXT1,
+            CompressedFormat::Bc1 { alpha: true, srgb: false } => gl_extensions::COMPRESSED_RGBA_S3TC_DXT1,
+            CompressedFormat::Bc1 { alpha: false, srgb: true } => gl_extensions::COMPRESSED_SRGB_S3TC_DXT1,
+            CompressedFormat::Bc1 { alpha: true, srgb: true } => gl_extensions::COMPRESSED_SRGB_ALPHA_S3TC_DXT1,
+            CompressedFormat::Bc2 { srgb: false } => gl_extensions::COMPRESSED_RGBA_S3TC_DXT3,
+            CompressedFormat::Bc2 { srgb: true } => gl_extensions::COMPRESSED_SRGB_ALPHA_S3TC_DXT3,
+            CompressedFormat::Bc3 { srgb: false } => gl_extensions::COMPRESSED_RGBA_S3TC_DXT5,
+            CompressedFormat::Bc3 { srgb: true } => gl_extensions::COMPRESSED_SRGB_ALPHA_S3TC_DXT5,
+            CompressedFormat::Bc4 { signed: false } => gl::COMPRESSED_RED_RGTC1,
+            CompressedFormat::Bc4 { signed: true } => gl::COMPRESSED_SIGNED_RED_RGTC1,
+            CompressedFormat::Bc5 { signed: false } => gl::COMPRESSED_RG_RGTC2,
+            CompressedFormat::Bc5 { signed: true } => gl::COMPRESSED_SIGNED_RG_RGTC2,
+            CompressedFormat::Bc6H { signed: false } => gl::COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT,
+            CompressedFormat::Bc6H { signed: true } => gl::COMPRESSED_RGB_BPTC_SIGNED_FLOAT,
+            CompressedFormat::Bc7 { srgb: false } => gl::COMPRESSED_RGBA_BPTC_UNORM,
+            CompressedFormat::Bc7 { srgb: true } => gl::COMPRESSED_SRGB_ALPHA_BPTC_UNORM,
+            CompressedFormat::Etc2Rgb8 { srgb: false } => gl::COMPRESSED_RGB8_ETC2,
+            CompressedFormat::Etc2Rgb8 { srgb: true } => gl::COMPRESSED_SRGB8_ETC2,
+            CompressedFormat::Etc2Rgb8A1 { srgb: false } => gl::COMPRESSED_RGB8_PUNCHTHROUGH_ALPHA1_ETC2,
+            CompressedFormat::Etc2Rgb8A1 { srgb: true } => gl::COMPRESSED_SRGB8_PUNCHTHROUGH_ALPHA1_ETC2,
+            CompressedFormat::Etc2Rgba8 { srgb: false } => gl::COMPRESSED_RGBA8_ETC2_EAC,
+            CompressedFormat::Etc2Rgba8 { srgb: true } => gl::COMPRESSED_SRGB8_ALPHA8_ETC2_EAC,
+            CompressedFormat::EacR11 { signed: false } => gl::COMPRESSED_R11_EAC,
+            CompressedFormat::EacR11 { signed: true } => gl::COMPRESSED_SIGNED_R11_EAC,
+            CompressedFormat::EacRg11 { signed: false } => gl::COMPRESSED_RG11_EAC,
+            CompressedFormat::EacRg11 { signed: true } => gl::COMPRESSED_SIGNED_RG11_EAC,
+            CompressedFormat::Astc { block_width, block_height, srgb } => {
+                let index = ASTC_BLOCK_SIZES.iter()
+                    .position(|size| *size == (block_width, block_height))
+                    .expect("Invalid ASTC block size!") as GLenum;
+                if srgb {
+                    gl_extensions::COMPRESSED_SRGB8_ALPHA8_ASTC_4X4 + index
+                } else {
+                    gl_extensions::COMPRESSED_RGBA_ASTC_4X4 + index
+                }
+            }
+        }
+    }
+
+    pub fn get_block_dimensions(self) -> (u32, u32) {
+        match self {
+            CompressedFormat::Astc { block_width, block_height, .. } => (block_width, block_height),
+            _ => (4, 4),
+        }
+    }
+
+    pub fn get_block_size(self) -> usize {
+        match self {
+            CompressedFormat::Bc1 { .. } | CompressedFormat::Bc4 { .. } => 8,
+            CompressedFormat::Etc2Rgb8 { .. } | CompressedFormat::Etc2Rgb8A1 { .. } | CompressedFormat::EacR11 { .. } => 8,
+            _ => 16,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ContainerFormat {
+    Uncompressed { format: TextureFormat, pixel_type: GLenum, pixel_size: usize },
+    Compressed(CompressedFormat),
+}
+
+impl ContainerFormat {
+    fn uncompressed(format: TextureFormat) -> ContainerFormat {
+        ContainerFormat::Uncompressed {
+            format,
+            pixel_type: format.get_pixel_type(),
+            pixel_size: format.get_pixel_size(),
+        }
+    }
+
+    fn half_float(format: TextureFormat, channel_count: usize) -> ContainerFormat {
+        ContainerFormat::Uncompressed {
+            format,
+            pixel_type: gl::HALF_FLOAT,
+            pixel_size: channel_count * 2,
+        }
+    }
+
+    pub fn get_image_size(self, width: u32, height: u32, depth: u32) -> usize {
+        match self {
+            ContainerFormat::Uncompressed { pixel_size, .. } => width as usize * height as usize * depth as usize * pixel_size,
+            ContainerFormat::Compressed(format) => {
+                let (block_width, block_height) = format.get_block_dimensions();
+                let blocks_wide = width.div_ceil(block_width);
+                let blocks_high = height.div_ceil(block_height);
+                blocks_wide as usize * blocks_high as usize * depth as usize * format.get_block_size()
+            }
+        }
+    }
+
+    // `None` when the size doesn't fit in memory, which only a corrupt header asks for.
+    pub fn try_get_image_size(self, width: u32, height: u32, depth: u32) -> Option<usize> {
+        let (blocks_wide, blocks_high, block_size) = match self {
+            ContainerFormat::Uncompressed { pixel_size, .. } => (width, height, pixel_size),
+            ContainerFormat::Compressed(format) => {
+                let (block_width, block_height) = format.get_block_dimensions();
+                (width.div_ceil(block_width), height.div_ceil(block_height), format.get_block_size())
+            }
+        };
+        return (blocks_wide as usize).checked_mul(blocks_high as usize)?
+            .checked_mul(depth as usize)?
+            .checked_mul(block_size);
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ContainerError {
+    UnknownFormat,
+    Truncated,
+    InvalidHeader(String),
+    Unsupported(String),
+}
+
+impl std::fmt::Display for ContainerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ContainerError::UnknownFormat => write!(f, "Not a KTX2 or DDS file"),
+            ContainerError::Truncated => write!(f, "The texture file is truncated"),
+            ContainerError::InvalidHeader(message) => write!(f, "Invalid texture header: {}", message),
+            ContainerError::Unsupported(message) => write!(f, "Unsupported texture file: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ContainerError {}
+
+#[derive(Clone, Debug)]
+pub struct MipLevel {
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    // Every layer and face of the level back to back, layer major.
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TextureContainer {
+    pub format: ContainerFormat,
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    pub layer_count: u32,
+    pub face_count: u32,
+    pub is_array: bool,
+    pub levels: Vec<MipLevel>,
+}
+
+impl TextureContainer {
+    pub fn parse(bytes: &[u8]) -> Result<TextureContainer, ContainerError> {
+        if bytes.starts_with(&KTX2_IDENTIFIER) {
+            return parse_ktx2(bytes);
+        }
+        if bytes.starts_with(DDS_MAGIC) {
+            return parse_dds(bytes);
+        }
+        return Err(ContainerError::UnknownFormat);
+    }
+
+    pub fn get_image(&self, level: usize, layer: u32, face: u32) -> &[u8] {
+        assert!(layer < self.layer_count && face < self.face_count, "Texture container image out of range!");
+        let mip_level = &self.levels[level];
+        let image_size = self.format.get_image_size(mip_level.width, mip_level.height, mip_level.depth);
+        let index = (layer * self.face_count + face) as usize;
+        return &mip_level.data[index * image_size..(index + 1) * image_size];
+    }
+}
+
+fn get_mip_dimension(size: u32, level: usize) -> u32 {
+    std::cmp::max(size >> level, 1)
+}
+
+// Also keeps `get_mip_dimension` from shifting by 32 or more.
+fn check_level_count(level_count: usize, width: u32, height: u32, depth: u32) -> Result<(), ContainerError> {
+    let max_level_count = mipmap::get_mip_level_count(std::cmp::max(width, depth), height) as usize;
+    if level_count > max_level_count {
+        return Err(ContainerError::InvalidHeader(format!("{} mip levels, at most {} fit", level_count, max_level_count)));
+    }
+    return Ok(());
+}
+
+fn get_level_size(format: ContainerFormat, width: u32, height: u32, depth: u32, image_count: u32) -> Result<usize, ContainerError> {
+    return format.try_get_image_size(width, height, depth)
+        .and_then(|size| size.checked_mul(image_count as usize))
+        .ok_or_else(|| ContainerError::InvalidHeader(format!("a {}x{}x{} image is too large", width, height, depth)));
+}
+
+fn get_data(bytes: &[u8], offset: usize, length: usize) -> Result<&[u8], ContainerError> {
+    let end = offset.checked_add(length).ok_or(ContainerError::Truncated)?;
+    return bytes.get(offset..end).ok_or(ContainerError::Truncated);
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, ContainerError> {
+    let slice = bytes.get(offset..offset + 4).ok_or(ContainerError::Truncated)?;
+    return Ok(u32::from_le_bytes([slice[0], slice[1], slice[2], slice[3]]));
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, ContainerError> {
+    let low = read_u32(bytes, offset)? as u64;
+    let high = read_u32(bytes, offset + 4)? as u64;
+    return Ok(low | (high << 32));
+}
+
+const KTX2_IDENTIFIER: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+const KTX2_LEVEL_INDEX_OFFSET: usize = 80;
+
+fn parse_ktx2(bytes: &[u8]) -> Result<TextureContainer, ContainerError> {
+    let vk_format = read_u32(bytes, 12)?;
+    let width = read_u32(bytes, 20)?;
+    let height = std::cmp::max(read_u32(bytes, 24)?, 1);
+    let depth = std::cmp::max(read_u32(bytes, 28)?, 1);
+    let layer_count = read_u32(bytes, 32)?;
+    let face_count = read_u32(bytes, 36)?;
+    let level_count = std::cmp::max(read_u32(bytes, 40)?, 1) as usize;
+    let supercompression_scheme = read_u32(bytes, 44)?;
+
+    if width == 0 {
+        return Err(ContainerError::InvalidHeader("the width is zero".to_string()));
+    }
+    if face_count != 1 && face_count != 6 {
+        return Err(ContainerError::InvalidHeader(format!("{} faces", face_count)));
+    }
+    if supercompression_scheme != 0 {
+        return Err(ContainerError::Unsupported(format!("supercompression scheme {}", supercompression_scheme)));
+    }
+
+    let format = get_vk_format(vk_format)
+        .ok_or_else(|| ContainerError::Unsupported(format!("VkFormat {}", vk_format)))?;
+    check_level_count(level_count, width, height, depth)?;
+    let image_count = std::cmp::max(layer_count, 1).checked_mul(face_count)
+        .ok_or_else(|| ContainerError::InvalidHeader(format!("{} layers", layer_count)))?;
+
+    let mut levels = Vec::with_capacity(level_count);
+    for level in 0..level_count {
+        let index_offset = KTX2_LEVEL_INDEX_OFFSET + level * 24;
+        let byte_offset = read_u64(bytes, index_offset)? as usize;
+        let byte_length = read_u64(bytes, index_offset + 8)? as usize;
+
+        let level_width = get_mip_dimension(width, level);
+        let level_height = get_mip_dimension(height, level);
+        let level_depth = get_mip_dimension(depth, level);
+        let expected_length = get_level_size(format, level_width, level_height, level_depth, image_count)?;
+        if byte_length != expected_length {
+            return Err(ContainerError::InvalidHeader(format!("level {} is {} bytes, expected {}", level, byte_length, expected_length)));
+        }
+
+        let data = get_data(bytes, byte_offset, byte_length)?;
+        levels.push(MipLevel {
+            width: level_width,
+            height: level_height,
+            depth: level_depth,
+            data: data.to_vec(),
+        });
+    }
+
+    return Ok(TextureContainer {
+        format,
+        width,
+        height,
+        depth,
+        layer_count: std::cmp::max(layer_count, 1),
+        face_count,
+        is_array: layer_count > 0,
+        levels,
+    });
+}
+
+fn get_vk_format(vk_format: u32) -> Option<ContainerFormat> {
+    let compressed = |format| Some(ContainerFormat::Compressed(format));
+    return match vk_format {
+        9 => Some(ContainerFormat::uncompressed(TextureFormat::R8)),
+        16 => Some(ContainerFormat::uncompressed(TextureFormat::RG8)),
+        23 => Some(ContainerFormat::uncompressed(TextureFormat::RGB8)),
+        29 => Some(ContainerFormat::uncompressed(TextureFormat::SRGB8)),
+        37 => Some(ContainerFormat::uncompressed(TextureFormat::RGBA8)),
+        43 => Some(ContainerFormat::uncompressed(TextureFormat::SRGB8Alpha8)),
+        76 => Some(ContainerFormat::half_float(TextureFormat::R16F, 1)),
+        83 => Some(ContainerFormat::half_float(TextureFormat::RG16F, 2)),
+        90 => Some(ContainerFormat::half_float(TextureFormat::RGB16F, 3)),
+        97 => Some(ContainerFormat::half_float(TextureFormat::RGBA16F, 4)),
+        100 => Some(ContainerFormat::uncompressed(TextureFormat::R32F)),
+        103 => Some(ContainerFormat::uncompressed(TextureFormat::RG32F)),
+        106 => Some(ContainerFormat::uncompressed(TextureFormat::RGB32F)),
+        109 => Some(ContainerFormat::uncompressed(TextureFormat::RGBA32F)),
+        131 => compressed(CompressedFormat::Bc1 { alpha: false, srgb: false }),
+        132 => compressed(CompressedFormat::Bc1 { alpha: false, srgb: true }),
+        133 => compressed(CompressedFormat::Bc1 { alpha: true, srgb: false }),
+        134 => compressed(CompressedFormat::Bc1 { alpha: true, srgb: true }),
+        135 => compressed(CompressedFormat::Bc2 { srgb: false }),
+        136 => compressed(CompressedFormat::Bc2 { srgb: true }),
+        137 => compressed(CompressedFormat::Bc3 { srgb: false }),
+        138 => compressed(CompressedFormat::Bc3 { srgb: true }),
+        139 => compressed(CompressedFormat::Bc4 { signed: false }),
+        140 => compressed(CompressedFormat::Bc4 { signed: true }),
+        141 => compressed(CompressedFormat::Bc5 { signed: false }),
+        142 => compressed(CompressedFormat::Bc5 { signed: true }),
+        143 => compressed(CompressedFormat::Bc6H { signed: false }),
+        144 => compressed(CompressedFormat::Bc6H { signed: true }),
+        145 => compressed(CompressedFormat::Bc7 { srgb: false }),
+        146 => compressed(CompressedFormat::Bc7 { srgb: true }),
+        147 => compressed(CompressedFormat::Etc2Rgb8 { srgb: false }),
+        148 => compressed(CompressedFormat::Etc2Rgb8 { srgb: true }),
+        149 => compressed(CompressedFormat::Etc2Rgb8A1 { srgb: false }),
+        150 => compressed(CompressedFormat::Etc2Rgb8A1 { srgb: true }),
+        151 => compressed(CompressedFormat::Etc2Rgba8 { srgb: false }),
+        152 => compressed(CompressedFormat::Etc2Rgba8 { srgb: true }),
+        153 => compressed(CompressedFormat::EacR11 { signed: false }),
+        154 => compressed(CompressedFormat::EacR11 { signed: true }),
+        155 => compressed(CompressedFormat::EacRg11 { signed: false }),
+        156 => compressed(CompressedFormat::EacRg11 { signed: true }),
+        157..=184 => {
+            let (block_width, block_height) = ASTC_BLOCK_SIZES[(vk_format - 157) as usize / 2];
+            compressed(CompressedFormat::Astc { block_width, block_height, srgb: (vk_format - 157) % 2 == 1 })
+        }
+        _ => None,
+    };
+}
+
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+const DDS_HEADER_SIZE: usize = 124;
+const DDS_DX10_HEADER_SIZE: usize = 20;
+
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDPF_LUMINANCE: u32 = 0x20000;
+
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_VOLUME: u32 = 0x200000;
+const DDS_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+
+fn parse_dds(bytes: &[u8]) -> Result<TextureContainer, ContainerError> {
+    let header = 4;
+    if read_u32(bytes, header)? as usize != DDS_HEADER_SIZE {
+        return Err(ContainerError::InvalidHeader("wrong DDS header size".to_string()));
+    }
+
+    let height = std::cmp::max(read_u32(bytes, header + 8)?, 1);
+    let width = std::cmp::max(read_u32(bytes, header + 12)?, 1);
+    let caps2 = read_u32(bytes, header + 108)?;
+    let depth = if caps2 & DDSCAPS2_VOLUME != 0 { std::cmp::max(read_u32(bytes, header + 20)?, 1) } else { 1 };
+    let level_count = std::cmp::max(read_u32(bytes, header + 24)?, 1) as usize;
+
+    let pixel_format_flags = read_u32(bytes, header + 76)?;
+    let four_cc = bytes.get(header + 80..header + 84).ok_or(ContainerError::Truncated)?;
+
+    let mut data_offset = 4 + DDS_HEADER_SIZE;
+    let mut layer_count = 1;
+    let mut is_array = false;
+    let mut face_count = if caps2 & DDSCAPS2_CUBEMAP != 0 { 6 } else { 1 };
+    let mut swap_red_blue = false;
+
+    let format = if pixel_format_flags & DDPF_FOURCC != 0 && four_cc == b"DX10" {
+        let dxgi_format = read_u32(bytes, data_offset)?;
+        let misc_flags = read_u32(bytes, data_offset + 8)?;
+        layer_count = std::cmp::max(read_u32(bytes, data_offset + 12)?, 1);
+        is_array = layer_count > 1;
+        if misc_flags & DDS_RESOURCE_MISC_TEXTURECUBE != 0 {
+            face_count = 6;
+        }
+        data_offset += DDS_DX10_HEADER_SIZE;
+
+        if dxgi_format == 87 || dxgi_format == 91 {
+            swap_red_blue = true;
+        }
+        get_dxgi_format(dxgi_format).ok_or_else(|| ContainerError::Unsupported(format!("DXGI format {}", dxgi_format)))?
+    } else if pixel_format_flags & DDPF_FOURCC != 0 {
+        get_four_cc_format(four_cc)
+            .ok_or_else(|| ContainerError::Unsupported(format!("FourCC {:?}", String::from_utf8_lossy(four_cc))))?
+    } else {
+        let bit_count = read_u32(bytes, header + 84)?;
+        let red_mask = read_u32(bytes, header + 88)?;
+        let alpha_mask = read_u32(bytes, header + 100)?;
+        if pixel_format_flags & DDPF_RGB != 0 && bit_count == 32 && pixel_format_flags & DDPF_ALPHAPIXELS != 0 && alpha_mask == 0xFF000000 {
+            swap_red_blue = red_mask == 0x00FF0000;
+            ContainerFormat::uncompressed(TextureFormat::RGBA8)
+        } else if pixel_format_flags & DDPF_LUMINANCE != 0 && bit_count == 8 {
+            ContainerFormat::uncompressed(TextureFormat::R8)
+        } else {
+            return Err(ContainerError::Unsupported(format!("{} bit uncompressed pixel format", bit_count)));
+        }
+    };
+
+    check_level_count(level_count, width, height, depth)?;
+    let image_count = layer_count.checked_mul(face_count)
+        .ok_or_else(|| ContainerError::InvalidHeader(format!("{} layers", layer_count)))?;
+
+    let mut levels: Vec<MipLevel> = (0..level_count)
+        .map(|level| MipLevel {
+            width: get_mip_dimension(width, level),
+            height: get_mip_dimension(height, level),
+            depth: get_mip_dimension(depth, level),
+            data: Vec::new(),
+        })
+        .collect();
+
+    // DDS stores every mip level of one layer/face before moving on to the next, so it gets regrouped per level here.
+    let mut offset = data_offset;
+    for _ in 0..image_count {
+        for level in levels.iter_mut() {
+            let image_size = get_level_size(format, level.width, level.height, level.depth, 1)?;
+            level.data.extend_from_slice(get_data(bytes, offset, image_size)?);
+            offset += image_size;
+        }
+    }
+
+    if swap_red_blue {
+        for level in levels.iter_mut() {
+            for pixel in level.data.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+    }
+
+    return Ok(TextureContainer {
+        format,
+        width,
+        height,
+        depth,
+        layer_count,
+        face_count,
+        is_array,
+        levels,
+    });
+}
+
+fn get_four_cc_format(four_cc: &[u8]) -> Option<ContainerFormat> {
+    let format = match four_cc {
+        b"DXT1" => CompressedFormat::Bc1 { alpha: true, srgb: false },
+        b"DXT2" | b"DXT3" => CompressedFormat::Bc2 { srgb: false },
+        b"DXT4" | b"DXT5" => CompressedFormat::Bc3 { srgb: false },
+        b"ATI1" | b"BC4U" => CompressedFormat::Bc4 { signed: false },
+        b"BC4S" => CompressedFormat::Bc4 { signed: true },
+        b"ATI2" | b"BC5U" => CompressedFormat::Bc5 { signed: false },
+        b"BC5S" => CompressedFormat::Bc5 { signed: true },
+        _ => return None,
+    };
+    return Some(ContainerFormat::Compressed(format));
+}
+
+fn get_dxgi_format(dxgi_format: u32) -> Option<ContainerFormat> {
+    let compressed = |format| Some(ContainerFormat::Compressed(format));
+    return match dxgi_format {
+        2 => Some(ContainerFormat::uncompressed(TextureFormat::RGBA32F)),
+        6 => Some(ContainerFormat::uncompressed(TextureFormat::RGB32F)),
+        10 => Some(ContainerFormat::half_float(TextureFormat::RGBA16F, 4)),
+        16 => Some(ContainerFormat::uncompressed(TextureFormat::RG32F)),
+        28 | 87 => Some(ContainerFormat::uncompressed(TextureFormat::RGBA8)),
+        29 | 91 => Some(ContainerFormat::uncompressed(TextureFormat::SRGB8Alpha8)),
+        34 => Some(ContainerFormat::half_float(TextureFormat::RG16F, 2)),
+        41 => Some(ContainerFormat::uncompressed(TextureFormat::R32F)),
+        49 => Some(ContainerFormat::uncompressed(TextureFormat::RG8)),
+        54 => Some(ContainerFormat::half_float(TextureFormat::R16F, 1)),
+        61 => Some(ContainerFormat::uncompressed(TextureFormat::R8)),
+        71 => compressed(CompressedFormat::Bc1 { alpha: true, srgb: false }),
+        72 => compressed(CompressedFormat::Bc1 { alpha: true, srgb: true }),
+        74 => compressed(CompressedFormat::Bc2 { srgb: false }),
+        75 => compressed(CompressedFormat::Bc2 { srgb: true }),
+        77 => compressed(CompressedFormat::Bc3 { srgb: false }),
+        78 => compressed(CompressedFormat::Bc3 { srgb: true }),
+        80 => compressed(CompressedFormat::Bc4 { signed: false }),
+        81 => compressed(CompressedFormat::Bc4 { signed: true }),
+        83 => compressed(CompressedFormat::Bc5 { signed: false }),
+        84 => compressed(CompressedFormat::Bc5 { signed: true }),
+        95 => compressed(CompressedFormat::Bc6H { signed: false }),
+        96 => compressed(CompressedFormat::Bc6H { signed: true }),
+        98 => compressed(CompressedFormat::Bc7 { srgb: false }),
+        99 => compressed(CompressedFormat::Bc7 { srgb: true }),
+        _ => None,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn create_ktx2(vk_format: u32, width: u32, height: u32, layer_count: u32, face_count: u32, levels: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = KTX2_IDENTIFIER.to_vec();
+        for value in [vk_format, 1, width, height, 0, layer_count, face_count, levels.len() as u32, 0].iter() {
+            push_u32(&mut bytes, *value);
+        }
+        bytes.resize(KTX2_LEVEL_INDEX_OFFSET, 0);
+
+        let mut offset = KTX2_LEVEL_INDEX_OFFSET + levels.len() * 24;
+        for level in levels {
+            bytes.extend_from_slice(&(offset as u64).to_le_bytes());
+            bytes.extend_from_slice(&(level.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(&(level.len() as u64).to_le_bytes());
+            offset += level.len();
+        }
+        for level in levels {
+            bytes.extend_from_slice(level);
+        }
+        return bytes;
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_dds(width: u32, height: u32, level_count: u32, flags: u32, four_cc: &[u8; 4], masks: [u32; 5], caps2: u32, dx10: Option<[u32; 5]>, data: &[u8]) -> Vec<u8> {
+        let mut bytes = DDS_MAGIC.to_vec();
+        push_u32(&mut bytes, DDS_HEADER_SIZE as u32);
+        push_u32(&mut bytes, 0);
+        push_u32(&mut bytes, height);
+        push_u32(&mut bytes, width);
+        push_u32(&mut bytes, 0);
+        push_u32(&mut bytes, 0);
+        push_u32(&mut bytes, level_count);
+        bytes.resize(4 + 72, 0);
+        push_u32(&mut bytes, 32);
+        push_u32(&mut bytes, flags);
+        bytes.extend_from_slice(four_cc);
+        for mask in masks.iter() {
+            push_u32(&mut bytes, *mask);
+        }
+        push_u32(&mut bytes, 0);
+        push_u32(&mut bytes, caps2);
+        bytes.resize(4 + DDS_HEADER_SIZE, 0);
+        if let Some(dx10) = dx10 {
+            for value in dx10.iter() {
+                push_u32(&mut bytes, *value);
+            }
+        }
+        bytes.extend_from_slice(data);
+        return bytes;
+    }
+
+    #[test]
+    fn parses_ktx2_bc7_mip_chain() {
+        let levels = vec![vec![1; 4 * 16], vec![2; 16], vec![3; 16], vec![4; 16]];
+        let container = TextureContainer::parse(&create_ktx2(145, 8, 8, 0, 1, &levels)).unwrap();
+
+        assert_eq!(container.format, ContainerFormat::Compressed(CompressedFormat::Bc7 { srgb: false }));
+        assert_eq!(container.format.get_image_size(8, 8, 1), 64);
+        assert_eq!(container.levels.len(), 4);
+        assert_eq!((container.levels[3].width, container.levels[3].height), (1, 1));
+        assert_eq!(container.get_image(1, 0, 0), &[2; 16][..]);
+        assert!(!container.is_array);
+    }
+
+    #[test]
+    fn parses_ktx2_astc_array_layers() {
+        let layer_size = 3 * 3 * 16;
+        let mut level = vec![0; layer_size];
+        level.extend(vec![7; layer_size]);
+        let container = TextureContainer::parse(&create_ktx2(172, 20, 20, 2, 1, &[level])).unwrap();
+
+        assert_eq!(container.format, ContainerFormat::Compressed(CompressedFormat::Astc { block_width: 8, block_height: 8, srgb: true }));
+        assert_eq!(CompressedFormat::Astc { block_width: 8, block_height: 8, srgb: true }.get_internal_format(), gl_extensions::COMPRESSED_SRGB8_ALPHA8_ASTC_4X4 + 7);
+        assert!(container.is_array);
+        assert_eq!(container.layer_count, 2);
+        assert_eq!(container.get_image(0, 1, 0), &vec![7; layer_size][..]);
+    }
+
+    #[test]
+    fn parses_ktx2_uncompressed_half_float() {
+        let container = TextureContainer::parse(&create_ktx2(97, 2, 2, 0, 1, &[vec![0; 2 * 2 * 8]])).unwrap();
+        assert_eq!(container.format, ContainerFormat::Uncompressed { format: TextureFormat::RGBA16F, pixel_type: gl::HALF_FLOAT, pixel_size: 8 });
+    }
+
+    #[test]
+    fn rejects_ktx2_supercompression_and_bad_sizes() {
+        let mut bytes = create_ktx2(145, 4, 4, 0, 1, &[vec![0; 16]]);
+        bytes[44] = 2;
+        assert!(matches!(TextureContainer::parse(&bytes), Err(ContainerError::Unsupported(_))));
+
+        let bytes = create_ktx2(145, 4, 4, 0, 1, &[vec![0; 15]]);
+        assert!(matches!(TextureContainer::parse(&bytes), Err(ContainerError::InvalidHeader(_))));
+
+        let bytes = create_ktx2(145, 4, 4, 0, 1, &[vec![0; 16]]);
+        assert_eq!(TextureContainer::parse(&bytes[..bytes.len() - 1]).unwrap_err(), ContainerError::Truncated);
+    }
+
+    #[test]
+    fn rejects_ktx2_level_counts_beyond_the_mip_chain() {
+        let mut bytes = create_ktx2(145, 4, 4, 0, 1, &[vec![0; 16]]);
+        bytes[40..44].copy_from_slice(&40u32.to_le_bytes());
+        assert!(matches!(TextureContainer::parse(&bytes), Err(ContainerError::InvalidHeader(_))));
+
+        bytes[40..44].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(TextureContainer::parse(&bytes), Err(ContainerError::InvalidHeader(_))));
+    }
+
+    #[test]
+    fn rejects_ktx2_sizes_that_overflow() {
+        let mut bytes = create_ktx2(145, 4, 4, 0, 1, &[vec![0; 16]]);
+        bytes[KTX2_LEVEL_INDEX_OFFSET..KTX2_LEVEL_INDEX_OFFSET + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(TextureContainer::parse(&bytes).unwrap_err(), ContainerError::Truncated);
+
+        let bytes = create_ktx2(145, 4, 4, u32::MAX, 6, &[vec![0; 16]]);
+        assert!(matches!(TextureContainer::parse(&bytes), Err(ContainerError::InvalidHeader(_))));
+
+        let bytes = create_ktx2(109, u32::MAX, u32::MAX, 2, 6, &[vec![0; 16]]);
+        assert!(matches!(TextureContainer::parse(&bytes), Err(ContainerError::InvalidHeader(_))));
+    }
+
+    #[test]
+    fn parses_dds_dxt1_with_mips() {
+        let mut data = vec![1; 2 * 2 * 8];
+        data.extend(vec![2; 8]);
+        data.extend(vec![3; 8]);
+        let bytes = create_dds(8, 8, 3, DDPF_FOURCC, b"DXT1", [0; 5], 0, None, &data);
+        let container = TextureContainer::parse(&bytes).unwrap();
+
+        assert_eq!(container.format, ContainerFormat::Compressed(CompressedFormat::Bc1 { alpha: true, srgb: false }));
+        assert_eq!(container.levels.len(), 3);
+        assert_eq!(container.get_image(2, 0, 0), &[3; 8][..]);
+    }
+
+    #[test]
+    fn regroups_dds_cubemap_faces_per_level() {
+        let mut data = Vec::new();
+        for face in 0..6u8 {
+            data.extend(vec![face; 16]);
+            data.extend(vec![face + 10; 16]);
+        }
+        let dx10 = [98, 3, DDS_RESOURCE_MISC_TEXTURECUBE, 1, 0];
+        let bytes = create_dds(4, 4, 2, DDPF_FOURCC, b"DX10", [0; 5], DDSCAPS2_CUBEMAP, Some(dx10), &data);
+        let container = TextureContainer::parse(&bytes).unwrap();
+
+        assert_eq!(container.face_count, 6);
+        assert_eq!(container.get_image(0, 0, 4), &[4; 16][..]);
+        assert_eq!(container.get_image(1, 0, 5), &[15; 16][..]);
+    }
+
+    #[test]
+    fn rejects_dds_level_counts_beyond_the_mip_chain() {
+        let bytes = create_dds(8, 8, 40, DDPF_FOURCC, b"DXT1", [0; 5], 0, None, &[0; 64]);
+        assert!(matches!(TextureContainer::parse(&bytes), Err(ContainerError::InvalidHeader(_))));
+
+        let bytes = create_dds(8, 8, u32::MAX, DDPF_FOURCC, b"DXT1", [0; 5], 0, None, &[0; 64]);
+        assert!(matches!(TextureContainer::parse(&bytes), Err(ContainerError::InvalidHeader(_))));
+    }
+
+    #[test]
+    fn rejects_dds_layer_counts_that_overflow() {
+        let dx10 = [98, 3, DDS_RESOURCE_MISC_TEXTURECUBE, u32::MAX, 0];
+        let bytes = create_dds(4, 4, 1, DDPF_FOURCC, b"DX10", [0; 5], DDSCAPS2_CUBEMAP, Some(dx10), &[0; 16]);
+        assert!(matches!(TextureContainer::parse(&bytes), Err(ContainerError::InvalidHeader(_))));
+    }
+
+    #[test]
+    fn swizzles_dds_bgra_pixels() {
+        let masks = [32, 0x00FF0000, 0x0000FF00, 0x000000FF, 0xFF000000];
+        let bytes = create_dds(1, 1, 1, DDPF_RGB | DDPF_ALPHAPIXELS, &[0; 4], masks, 0, None, &[10, 20, 30, 40]);
+        let container = TextureContainer::parse(&bytes).unwrap();
+
+        assert_eq!(container.format, ContainerFormat::uncompressed(TextureFormat::RGBA8));
+        assert_eq!(container.levels[0].data, vec![30, 20, 10, 40]);
+    }
+
+    #[test]
+    fn rejects_unknown_files() {
+        assert_eq!(TextureContainer::parse(b"\x89PNG\r\n\x1a\n").unwrap_err(), ContainerError::UnknownFormat);
+    }
+}
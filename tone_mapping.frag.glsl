@@ -0,0 +1,27 @@
+#version 440 core
+
+layout(location = 0) out vec4 o_Color;
+
+layout(location = 0) in vec2 v_TexCoord;
+
+uniform sampler2D u_Texture;
+uniform int u_Operator;
+uniform float u_Exposure;
+
+vec3 reinhard(vec3 color) {
+    return color / (color + vec3(1.0));
+}
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve.
+vec3 aces(vec3 color) {
+    vec3 numerator = color * (2.51 * color + 0.03);
+    vec3 denominator = color * (2.43 * color + 0.59) + 0.14;
+    return clamp(numerator / denominator, 0.0, 1.0);
+}
+
+void main() {
+    vec4 color = texture(u_Texture, v_TexCoord);
+    vec3 exposed = color.rgb * u_Exposure;
+    vec3 mapped = u_Operator == 0 ? reinhard(exposed) : aces(exposed);
+    o_Color = vec4(mapped, color.a);
+}
@@ -0,0 +1,17 @@
+#version 440 core
+
+layout(location = 0) out vec4 o_Color;
+
+layout(location = 0) in vec2 v_TexCoord;
+
+uniform sampler2D u_Texture;
+uniform float u_Strength;
+uniform float u_Radius;
+uniform float u_Softness;
+
+void main() {
+    vec4 color = texture(u_Texture, v_TexCoord);
+    float distance_from_center = length(v_TexCoord - vec2(0.5));
+    float falloff = smoothstep(u_Radius, u_Radius - u_Softness, distance_from_center);
+    o_Color = vec4(color.rgb * mix(1.0, falloff, u_Strength), color.a);
+}